@@ -0,0 +1,111 @@
+use crate::shapes::Ray;
+use crate::vec3::Vec3;
+use serde::Serialize;
+
+/// Axis aligned bounding box
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn from_points<I: IntoIterator<Item = Vec3>>(points: I) -> Aabb {
+        points.into_iter().fold(
+            Aabb {
+                min: Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+                max: Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            },
+            |aabb, p| Aabb {
+                min: aabb.min.min(p),
+                max: aabb.max.max(p),
+            },
+        )
+    }
+
+    /// Cube that contains a sphere
+    pub fn around(center: Vec3, radius: f64) -> Aabb {
+        let extent = Vec3::new(radius, radius, radius);
+        Aabb {
+            min: center - extent,
+            max: center + extent,
+        }
+    }
+
+    /// Box that contains both boxes
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// Box that both boxes overlap in, nothing hits it if they don't overlap
+    pub fn intersection(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.max(other.min),
+            max: self.max.min(other.max),
+        }
+    }
+
+    /// The box grown by `pad` on every side, so that flat shapes don't have infinitely thin boxes
+    pub fn padded(&self, pad: f64) -> Aabb {
+        let extent = Vec3::new(pad, pad, pad);
+        Aabb {
+            min: self.min - extent,
+            max: self.max + extent,
+        }
+    }
+
+    /// Whether the point is inside the box or on its surface
+    pub fn contains(&self, point: Vec3) -> bool {
+        (0..3).all(|axis| self.min[axis] <= point[axis] && point[axis] <= self.max[axis])
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Vec3::new(min.x, min.y, min.z),
+            Vec3::new(max.x, min.y, min.z),
+            Vec3::new(min.x, max.y, min.z),
+            Vec3::new(max.x, max.y, min.z),
+            Vec3::new(min.x, min.y, max.z),
+            Vec3::new(max.x, min.y, max.z),
+            Vec3::new(min.x, max.y, max.z),
+            Vec3::new(max.x, max.y, max.z),
+        ]
+    }
+
+    /// Slab test, returns whether the ray hits the box in front of its anchor
+    pub fn hit(&self, ray: &Ray) -> bool {
+        self.hit_range(ray).is_some()
+    }
+
+    /// Slab test, returns the range of distances along the ray that are inside the box and in
+    /// front of its anchor
+    pub fn hit_range(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let mut t_min = 0.0_f64;
+        let mut t_max = f64::INFINITY;
+
+        for axis in 0..3 {
+            let inv_d = 1.0 / ray.dir[axis];
+            let mut t0 = (self.min[axis] - ray.anchor[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - ray.anchor[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // written so that NaNs (ray parallel to and on a slab's face) don't discard the hit
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min {
+                return None;
+            }
+        }
+
+        Some((t_min, t_max))
+    }
+}
@@ -0,0 +1,120 @@
+use crate::obj::{FaceVertex, ObjData};
+use crate::vec3::Vec3;
+
+/// Bicubic Bézier patch, `control_points[row][column]` where 'u' goes along the columns and 'v'
+/// along the rows
+pub struct BezierPatch {
+    control_points: [[Vec3; 4]; 4],
+}
+
+impl BezierPatch {
+    pub fn new(control_points: [[Vec3; 4]; 4]) -> BezierPatch {
+        BezierPatch { control_points }
+    }
+
+    /// Point on the patch at (u, v) along with its partial derivatives along 'u' and 'v'
+    fn evaluate(&self, u: f64, v: f64) -> (Vec3, Vec3, Vec3) {
+        let (bu, bv) = (bernstein(u), bernstein(v));
+        let (dbu, dbv) = (bernstein_derivative(u), bernstein_derivative(v));
+
+        let zero = Vec3::new(0.0, 0.0, 0.0);
+        let (mut point, mut du, mut dv) = (zero, zero, zero);
+        for (row, points) in self.control_points.iter().enumerate() {
+            for (column, &p) in points.iter().enumerate() {
+                point += p * (bu[column] * bv[row]);
+                du += p * (dbu[column] * bv[row]);
+                dv += p * (bu[column] * dbv[row]);
+            }
+        }
+
+        (point, du, dv)
+    }
+
+    /// Normal at (u, v), facing the side from which going along 'u' and then along 'v' turns
+    /// counterclockwise
+    fn normal(&self, u: f64, v: f64) -> Vec3 {
+        let (_, du, dv) = self.evaluate(u, v);
+        let normal = du.cross(dv);
+
+        if normal.near_zero() {
+            // collapsed edge (like at a pole), the normal right next to it is used instead
+            let nudge = |t: f64| t + (0.5 - t) * 1e-3;
+            let (_, du, dv) = self.evaluate(nudge(u), nudge(v));
+            du.cross(dv).normalize()
+        } else {
+            normal.normalize()
+        }
+    }
+
+    /// Splits the patch into a grid of `subdivisions` by `subdivisions` quads, each split in two
+    /// triangles, that carry the patch's exact normals at their vertices. The texture coords are
+    /// (u, v) scaled by the average length of the control polygon along each direction, so that
+    /// they're roughly in world units.
+    pub fn tessellate(&self, subdivisions: usize) -> ObjData {
+        let polygon_length = |points: [Vec3; 4]| {
+            points
+                .windows(2)
+                .map(|pair| (pair[1] - pair[0]).norm())
+                .sum::<f64>()
+        };
+        let u_length = (0..4)
+            .map(|row| polygon_length(self.control_points[row]))
+            .sum::<f64>()
+            / 4.0;
+        let v_length = (0..4)
+            .map(|column| polygon_length(self.control_points.map(|points| points[column])))
+            .sum::<f64>()
+            / 4.0;
+
+        let side = subdivisions + 1;
+        let mut data = ObjData {
+            positions: Vec::with_capacity(side * side),
+            texture_coords: Vec::with_capacity(side * side),
+            normals: Vec::with_capacity(side * side),
+            triangles: Vec::with_capacity(2 * subdivisions * subdivisions),
+        };
+
+        for row in 0..side {
+            for column in 0..side {
+                let u = column as f64 / subdivisions as f64;
+                let v = row as f64 / subdivisions as f64;
+                data.positions.push(self.evaluate(u, v).0);
+                data.texture_coords.push((u * u_length, v * v_length));
+                data.normals.push(self.normal(u, v));
+            }
+        }
+
+        let vertex = |row: usize, column: usize| FaceVertex {
+            position: row * side + column,
+            texture_coords: Some(row * side + column),
+            normal: Some(row * side + column),
+        };
+        for row in 0..subdivisions {
+            for column in 0..subdivisions {
+                let (v00, v01) = (vertex(row, column), vertex(row, column + 1));
+                let (v10, v11) = (vertex(row + 1, column), vertex(row + 1, column + 1));
+                data.triangles.push([v00, v01, v11]);
+                data.triangles.push([v00, v11, v10]);
+            }
+        }
+
+        data
+    }
+}
+
+/// Cubic Bernstein polynomials at t
+fn bernstein(t: f64) -> [f64; 4] {
+    let s = 1.0 - t;
+    [s * s * s, 3.0 * t * s * s, 3.0 * t * t * s, t * t * t]
+}
+
+/// Derivatives of the cubic Bernstein polynomials at t
+fn bernstein_derivative(t: f64) -> [f64; 4] {
+    let s = 1.0 - t;
+    [
+        -3.0 * s * s,
+        3.0 * s * s - 6.0 * t * s,
+        6.0 * t * s - 3.0 * t * t,
+        3.0 * t * t,
+    ]
+}
@@ -0,0 +1,171 @@
+use crate::aabb::Aabb;
+use crate::constants::{BVH_LEAF_SIZE, TOLERANCE, TOLERANCE_MUL};
+use crate::shapes::Ray;
+use crate::vec3::Vec3;
+
+/// Bounding volume hierarchy over some objects, like the ones of a scene or the faces of a mesh,
+/// so that rays are only tested against the objects whose boxes they go through. The objects are
+/// referred to by their index.
+#[derive(Debug, Clone, Default)]
+pub struct Bvh {
+    /// Depth first, so the left child of a branch is always the node right after it
+    nodes: Vec<Node>,
+    /// Indices of the objects, in the order the leaves refer to them
+    order: Vec<usize>,
+    /// Objects without bounds, like planes, which every ray is tested against
+    unbounded: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Leaf {
+        bounds: Aabb,
+        start: usize,
+        end: usize,
+    },
+    Branch {
+        bounds: Aabb,
+        right: usize,
+    },
+}
+
+impl Bvh {
+    /// Builds the hierarchy over objects with these bounds, `None` for the unbounded ones
+    pub fn new(bounds: impl IntoIterator<Item = Option<Aabb>>) -> Bvh {
+        let mut bvh = Bvh::default();
+        let mut bounded = Vec::new();
+        for (i, bounds) in bounds.into_iter().enumerate() {
+            match bounds {
+                Some(bounds) => bounded.push((i, bounds.padded(TOLERANCE * TOLERANCE_MUL))),
+                None => bvh.unbounded.push(i),
+            }
+        }
+
+        if !bounded.is_empty() {
+            bvh.build(&mut bounded);
+        }
+        bvh
+    }
+
+    fn build(&mut self, items: &mut [(usize, Aabb)]) {
+        let bounds = items[1..]
+            .iter()
+            .fold(items[0].1, |bounds, (_, item)| bounds.union(item));
+
+        if items.len() <= BVH_LEAF_SIZE {
+            let start = self.order.len();
+            self.order.extend(items.iter().map(|(i, _)| i));
+            self.nodes.push(Node::Leaf {
+                bounds,
+                start,
+                end: self.order.len(),
+            });
+            return;
+        }
+
+        // split in half along the axis the objects are the most spread over
+        let centroids = Aabb::from_points(items.iter().map(|(_, item)| item.centroid()));
+        let extent = centroids.max - centroids.min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        items.sort_by(|(_, a), (_, b)| a.centroid()[axis].total_cmp(&b.centroid()[axis]));
+        let (left, right) = items.split_at_mut(items.len() / 2);
+
+        let branch = self.nodes.len();
+        self.nodes.push(Node::Branch { bounds, right: 0 });
+        self.build(left);
+        let right_index = self.nodes.len();
+        if let Node::Branch { right, .. } = &mut self.nodes[branch] {
+            *right = right_index;
+        }
+        self.build(right);
+    }
+
+    /// Index of the closest of the objects hit by the ray before `t_max`, along with its distance,
+    /// among the hits `accept` takes. `intersect` gives the distance at which the ray hits an
+    /// object.
+    pub fn closest_hit(
+        &self,
+        ray: &Ray,
+        t_max: f64,
+        intersect: impl Fn(usize) -> Option<f64>,
+        accept: impl Fn(usize, f64) -> bool,
+    ) -> Option<(f64, usize)> {
+        let mut closest = None;
+        let mut t_max = t_max;
+        let mut test = |i: usize, t_max: &mut f64| {
+            if let Some(t) = intersect(i) {
+                if t < *t_max && accept(i, t) {
+                    *t_max = t;
+                    closest = Some((t, i));
+                }
+            }
+        };
+
+        for &i in &self.unbounded {
+            test(i, &mut t_max);
+        }
+
+        let reaches = |bounds: &Aabb, t_max: f64| {
+            bounds
+                .hit_range(ray)
+                .is_some_and(|(enter, _)| enter < t_max)
+        };
+        let mut stack = if self.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![0]
+        };
+        while let Some(node) = stack.pop() {
+            match &self.nodes[node] {
+                Node::Leaf { bounds, start, end } => {
+                    if reaches(bounds, t_max) {
+                        for &i in &self.order[*start..*end] {
+                            test(i, &mut t_max);
+                        }
+                    }
+                }
+                Node::Branch { bounds, right } => {
+                    if reaches(bounds, t_max) {
+                        stack.push(*right);
+                        stack.push(node + 1);
+                    }
+                }
+            }
+        }
+
+        closest
+    }
+
+    /// Indices of the objects whose boxes contain the point, along with the unbounded ones
+    pub fn objects_at(&self, point: Vec3) -> Vec<usize> {
+        let mut objects = self.unbounded.clone();
+        let mut stack = if self.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![0]
+        };
+        while let Some(node) = stack.pop() {
+            match &self.nodes[node] {
+                Node::Leaf { bounds, start, end } => {
+                    if bounds.contains(point) {
+                        objects.extend(&self.order[*start..*end]);
+                    }
+                }
+                Node::Branch { bounds, right } => {
+                    if bounds.contains(point) {
+                        stack.push(*right);
+                        stack.push(node + 1);
+                    }
+                }
+            }
+        }
+
+        objects
+    }
+}
@@ -0,0 +1,99 @@
+use anyhow::{anyhow, Context, Error, Result};
+use image::{Rgb, RgbImage};
+use std::path::Path;
+
+/// How much an image differs from a reference one, with their channels between 0 and 1
+#[derive(Debug, Clone, Copy)]
+pub struct Comparison {
+    /// Mean of the squared differences of every channel of every pixel
+    pub mse: f64,
+    /// Largest difference of any channel
+    pub max_difference: f64,
+    /// Pixel with the largest difference, from the top left corner
+    pub max_pixel: (u32, u32),
+    /// Pixels with any channel different
+    pub differing_pixels: u64,
+    pub total_pixels: u64,
+}
+
+impl Comparison {
+    /// Peak signal to noise ratio in decibels, infinite for identical images
+    pub fn psnr(&self) -> f64 {
+        -10.0 * self.mse.log10()
+    }
+}
+
+/// Compares the image with the reference one, which must be of the same size, and saves a heatmap
+/// of their differences if a path is given for it. The heatmap goes from black where the pixels
+/// match through red and yellow to white at the largest difference.
+pub fn compare_images(
+    reference: &Path,
+    image: &Path,
+    heatmap: Option<&Path>,
+) -> Result<Comparison> {
+    let open = |path: &Path| {
+        image::open(path)
+            .with_context(|| format!("Couldn't read the image '{}'", path.display()))
+            .map(|image| image.to_rgba16())
+    };
+    let (reference, image) = (open(reference)?, open(image)?);
+    if reference.dimensions() != image.dimensions() {
+        return Err(anyhow!(
+            "The images are of different sizes, {}x{} and {}x{}",
+            reference.width(),
+            reference.height(),
+            image.width(),
+            image.height()
+        ));
+    }
+
+    let mut differences = Vec::with_capacity((image.width() * image.height()) as usize);
+    let mut comparison = Comparison {
+        mse: 0.0,
+        max_difference: 0.0,
+        max_pixel: (0, 0),
+        differing_pixels: 0,
+        total_pixels: u64::from(image.width()) * u64::from(image.height()),
+    };
+    for ((x, y, a), b) in reference.enumerate_pixels().zip(image.pixels()) {
+        let mut pixel_difference = 0.0;
+        for c in 0..4 {
+            let difference = (f64::from(a[c]) - f64::from(b[c])).abs() / 65535.0;
+            comparison.mse += difference * difference;
+            pixel_difference = f64::max(pixel_difference, difference);
+        }
+
+        if pixel_difference > 0.0 {
+            comparison.differing_pixels += 1;
+        }
+        if pixel_difference > comparison.max_difference {
+            comparison.max_difference = pixel_difference;
+            comparison.max_pixel = (x, y);
+        }
+        differences.push(pixel_difference);
+    }
+    comparison.mse /= comparison.total_pixels as f64 * 4.0;
+
+    if let Some(path) = heatmap {
+        let scale = if comparison.max_difference > 0.0 {
+            1.0 / comparison.max_difference
+        } else {
+            0.0
+        };
+        let heat = |t: f64| {
+            // red rises first, then green and then blue, so it goes through red and yellow
+            let channel = |start: f64| ((t * 3.0 - start).clamp(0.0, 1.0) * 255.0).round() as u8;
+            Rgb([channel(0.0), channel(1.0), channel(2.0)])
+        };
+        let mut heatmap = RgbImage::new(image.width(), image.height());
+        for (pixel, difference) in heatmap.pixels_mut().zip(differences) {
+            *pixel = heat(difference * scale);
+        }
+        heatmap
+            .save(path)
+            .map_err(Error::msg)
+            .with_context(|| format!("Couldn't write the heatmap '{}'", path.display()))?;
+    }
+
+    Ok(comparison)
+}
@@ -2,6 +2,9 @@
 pub const TOLERANCE: f64 = 1e-6;
 pub const TOLERANCE_MUL: f64 = 100.0;
 
+/// Most objects in each leaf of the bounding volume hierarchy
+pub const BVH_LEAF_SIZE: usize = 4;
+
 /// Default color of scene's background
 pub const DEFAULT_BG_COLOR: &str = "#3D1A28";
 pub const DEFAULT_LIGHT_COLOR: &str = "#FFFFFF";
@@ -9,12 +12,109 @@ pub const DEFAULT_LIGHT_COLOR: &str = "#FFFFFF";
 /// Default values for parameters
 pub const DEFAULT_HARDNESS: f64 = 10.0;
 
-/// flag for calculating shadows
+/// Angular radius in degrees of the sun of the `[sky]`
+pub const DEFAULT_SUN_SIZE: f64 = 1.5;
+
+/// Render layer of the objects that don't set their `layer`
+pub const DEFAULT_LAYER: &str = "default";
+
+/// Points sampled along every ray through the `[medium]`
+pub const MEDIUM_STEPS: u32 = 32;
+/// How far the `[medium]` reaches along the rays that don't hit anything
+pub const MEDIUM_DISTANCE: f64 = 1000.0;
+
+/// Shadow rays sent towards each light with a radius
+pub const DEFAULT_SHADOW_SAMPLES: u64 = 16;
+
+/// Angle in degrees between the axis and the side of the cone of spot lights
+pub const DEFAULT_SPOT_ANGLE: f64 = 30.0;
+
+/// Horizontal field of view in degrees of cameras given with `look_at`
+pub const DEFAULT_FOV: f64 = 60.0;
+/// Field of view in degrees of fisheye cameras, across the circle they see
+pub const DEFAULT_FISHEYE_FOV: f64 = 180.0;
+/// Rays traced per pixel by cameras with an aperture
+pub const DEFAULT_LENS_SAMPLES: u64 = 32;
+/// Stereo images have their eyes separated by the focus distance over this by default
+pub const EYE_SEPARATION_RATIO: f64 = 30.0;
+
+/// flag for calculating shadows (default of `shadows` in the `[render]` section)
 pub const SHADOWS: bool = true;
 
-/// max number of recursive calls due to reflection
+/// whether images are encoded as sRGB rather than written with linear colors (default of `srgb` in
+/// the `[render]` section)
+pub const SRGB: bool = true;
+
+/// max number of recursive calls due to reflection (default of `max_reflections` in the `[render]`
+/// section)
 pub const MAX_REFLECTIONS: u32 = 10;
 
+/// Distance secondary rays are moved along before being traced so that they don't hit the
+/// surface they start from due to float rounding error (default of `bias` in the `[render]`
+/// section)
+pub const RAY_BIAS: f64 = TOLERANCE;
+
+/// Rays sent from every point to gather the light of emissive objects and of the background
+/// (default of `emission_samples` in the `[render]` section)
+pub const EMISSION_SAMPLES: u32 = 16;
+
+/// Side in pixels of the square tiles the image is traced in
+pub const TILE_SIZE: u32 = 32;
+
+/// Side in pixels of the blocks the first pass of a progressive render fills with a single color
+pub const PROGRESSIVE_BLOCK: u32 = 16;
+
+/// Rays traced through each pixel picked for anti-aliasing (default of `aa_samples` in the
+/// `[render]` section)
+pub const AA_SAMPLES: u32 = 16;
+
+/// Radius in pixels of the neighborhood each pixel is averaged over by the denoiser (default of
+/// `denoise_radius` in the `[render]` section)
+pub const DENOISE_RADIUS: u32 = 3;
+
+/// Distance, relative to the distance from the origin, moved along a surface to find how its
+/// texture coordinates change when bump mapping
+pub const BUMP_STEP: f64 = 1e-4;
+/// Length in texture coordinates (world units for most shapes) of one repetition of a bump or
+/// normal map
+pub const DEFAULT_MAP_SIZE: f64 = 10.0;
+
+/// Layers of noise added up by the procedural patterns of objects
+pub const PATTERN_OCTAVES: u32 = 6;
+/// Size of the features of procedural patterns
+pub const DEFAULT_PATTERN_SCALE: f64 = 10.0;
+
+/// How sharply triplanar mapping goes from one projection to the next as the surface turns,
+/// higher values blend them over narrower bands
+pub const TRIPLANAR_SHARPNESS: f64 = 4.0;
+
+/// Distance in texture coordinates from the first color of a gradient to the last one
+pub const DEFAULT_GRADIENT_LENGTH: f64 = 100.0;
+
+/// How far around the side of translucent objects the light from behind them wraps, 0 for it to
+/// stop right where the light grazes the surface
+pub const TRANSLUCENCY_WRAP: f64 = 0.5;
+
+/// Max number of steps when sphere tracing a signed distance field
+pub const SDF_MAX_STEPS: u32 = 512;
+/// Distance to a signed distance field under which it counts as a hit
+pub const SDF_HIT_DISTANCE: f64 = TOLERANCE * TOLERANCE_MUL;
+/// Fraction of the smallest radius of influence that is marched per step looking for the surface
+/// of metaballs
+pub const METABALL_STEP: f64 = 0.05;
+
+/// Most points sampled along every ray through the medium in previews
+pub const PREVIEW_MEDIUM_STEPS: u32 = 8;
+
+/// Layout of the progress bar shown while tracing, see indicatif's templates
+pub const PROGRESS_TEMPLATE: &str =
+    "{wide_bar} {percent:>3}% [{elapsed_precise} elapsed, {eta_precise} left] {msg}";
+
 /// Default values for args
 pub const DEFAULT_RES: u32 = 1000;
 pub const DEFAULT_IMAGE: &str = "out.png";
+/// Output path that writes the image to stdout, as a binary PPM
+pub const STDOUT_IMAGE: &str = "-";
+
+/// How often the watched files are checked for changes
+pub const WATCH_INTERVAL_MS: u64 = 250;
@@ -0,0 +1,102 @@
+use crate::shapes::{colors, Color};
+use crate::vec3::Vec3;
+
+/// How far apart the colors of two pixels can be before they stop being blended, relative to a
+/// range of 1
+const COLOR_SIGMA: f64 = 0.25;
+/// How far apart the normals of two pixels can be (as one minus the cosine between them)
+const NORMAL_SIGMA: f64 = 0.1;
+/// How far apart the depths of two pixels can be, relative to the depth of the filtered one
+const DEPTH_SIGMA: f64 = 0.05;
+
+/// What the ray through the center of a pixel sees first, which tells the denoiser where the edges
+/// between surfaces are
+#[derive(Debug, Clone, Copy)]
+pub struct Guide {
+    /// Facing the camera, zero where nothing is hit
+    pub normal: Vec3,
+    /// Distance from the camera, infinite where nothing is hit
+    pub depth: f64,
+}
+
+impl Guide {
+    pub const BACKGROUND: Guide = Guide {
+        normal: Vec3 {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        },
+        depth: f64::INFINITY,
+    };
+
+    /// How much a pixel showing `other` is blended into one showing this, between 0 and 1
+    fn similarity(&self, other: &Guide) -> f64 {
+        match (self.depth.is_finite(), other.depth.is_finite()) {
+            (false, false) => 1.0,
+            (true, true) => {
+                let normal = (1.0 - self.normal.dot(other.normal)) / NORMAL_SIGMA;
+                let depth = (self.depth - other.depth) / (self.depth * DEPTH_SIGMA);
+                (-0.5 * (normal * normal + depth * depth)).exp()
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+/// Smooths out the noise of the pixels with a joint bilateral filter of the given radius: every
+/// pixel is averaged with its neighbors of similar color that show the same surface according to
+/// their guides, so the noise is blurred but the edges are kept. The pixels and their guides are
+/// given column by column, with `rows` pixels in each, and `column_done` is called after each
+/// column is filtered.
+pub fn denoise(
+    pixels: &[Color],
+    guides: &[Guide],
+    rows: usize,
+    radius: usize,
+    column_done: impl Fn(),
+) -> Vec<Color> {
+    let columns = pixels.len() / rows;
+    let radius = radius as isize;
+    let spatial_sigma = (radius as f64 / 2.0).max(0.5);
+
+    let mut denoised = Vec::with_capacity(pixels.len());
+    for i in 0..columns {
+        for j in 0..rows {
+            let center = i * rows + j;
+            let (color, guide) = (pixels[center], guides[center]);
+
+            let mut sum = colors::BLACK;
+            let mut total = 0.0;
+            for di in -radius..=radius {
+                for dj in -radius..=radius {
+                    let (Some(ni), Some(nj)) = (i.checked_add_signed(di), j.checked_add_signed(dj))
+                    else {
+                        continue;
+                    };
+                    if ni >= columns || nj >= rows {
+                        continue;
+                    }
+                    let neighbor = ni * rows + nj;
+
+                    let distance2 = (di * di + dj * dj) as f64;
+                    let other = pixels[neighbor];
+                    let color2 = ((other.r - color.r).powi(2)
+                        + (other.g - color.g).powi(2)
+                        + (other.b - color.b).powi(2))
+                        / (COLOR_SIGMA * COLOR_SIGMA);
+                    let weight = (-0.5 * (distance2 / (spatial_sigma * spatial_sigma) + color2))
+                        .exp()
+                        * guide.similarity(&guides[neighbor]);
+
+                    sum = sum + pixels[neighbor] * weight;
+                    total += weight;
+                }
+            }
+
+            // the pixel itself always has a weight of 1
+            denoised.push(sum * (1.0 / total));
+        }
+        column_done();
+    }
+    denoised
+}
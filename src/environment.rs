@@ -0,0 +1,39 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::f64::consts::PI;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::shapes::{Color, TextureCoords};
+use crate::texture::{ImageTexture, Wrap};
+use crate::vec3::Vec3;
+
+/// Equirectangular image surrounding the scene, seen by the rays that don't hit anything. The
+/// image's center looks towards +z, its left and right edges towards -z and its top row up (+y).
+#[derive(Debug, Clone, Serialize)]
+pub struct EnvironmentMap {
+    path: String,
+    #[serde(skip)]
+    image: Arc<ImageTexture>,
+}
+
+impl EnvironmentMap {
+    pub fn open(path: &Path) -> Result<EnvironmentMap> {
+        Ok(EnvironmentMap {
+            path: path.display().to_string(),
+            image: Arc::new(ImageTexture::open(path, true)?),
+        })
+    }
+
+    /// Color seen looking along `dir`, bilinearly filtered
+    pub fn sample(&self, dir: Vec3) -> Color {
+        let dir = dir.normalize();
+        let coords = TextureCoords {
+            x: 0.5 + dir.x.atan2(dir.z) / (2.0 * PI),
+            y: 1.0 - dir.y.clamp(-1.0, 1.0).acos() / PI,
+        };
+
+        // wraps around horizontally and stops at the poles vertically
+        self.image.sample_wrapped(coords, Wrap::Repeat, Wrap::Clamp)
+    }
+}
@@ -0,0 +1,236 @@
+use anyhow::{anyhow, Result};
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Evaluates an arithmetic expression like `2 * (height + 0.5) ^ 2`, where names are resolved
+/// through `var`. Supports `+ - * / ^`, parentheses, the constant `pi` and the functions `sqrt`,
+/// `abs`, `min`, `max`, `sin`, `cos` and `tan` (trigonometric functions take degrees).
+pub fn evaluate(expression: &str, var: &mut dyn FnMut(&str) -> Result<f64>) -> Result<f64> {
+    let mut parser = Parser {
+        chars: expression.chars().peekable(),
+        var,
+    };
+
+    let value = parser.sum()?;
+    parser.skip_whitespace();
+    match parser.chars.next() {
+        None => Ok(value),
+        Some(c) => Err(anyhow!("Unexpected '{}' in expression '{}'", c, expression)),
+    }
+}
+
+struct Parser<'a, 'v> {
+    chars: Peekable<Chars<'a>>,
+    var: &'v mut dyn FnMut(&str) -> Result<f64>,
+}
+
+impl Parser<'_, '_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    /// Consumes the next non whitespace char if it's `c`
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        self.chars.next_if_eq(&c).is_some()
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(anyhow!("Expected '{}' in expression", c))
+        }
+    }
+
+    /// sum := product (('+' | '-') product)*
+    fn sum(&mut self) -> Result<f64> {
+        let mut value = self.product()?;
+        loop {
+            if self.eat('+') {
+                value += self.product()?;
+            } else if self.eat('-') {
+                value -= self.product()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// product := unary (('*' | '/') unary)*
+    fn product(&mut self) -> Result<f64> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat('*') {
+                value *= self.unary()?;
+            } else if self.eat('/') {
+                value /= self.unary()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// unary := ('-' | '+') unary | power
+    fn unary(&mut self) -> Result<f64> {
+        if self.eat('-') {
+            Ok(-self.unary()?)
+        } else if self.eat('+') {
+            self.unary()
+        } else {
+            self.power()
+        }
+    }
+
+    /// power := atom ('^' unary)?
+    fn power(&mut self) -> Result<f64> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            Ok(base.powf(self.unary()?))
+        } else {
+            Ok(base)
+        }
+    }
+
+    /// atom := number | name | name '(' sum (',' sum)* ')' | '(' sum ')'
+    fn atom(&mut self) -> Result<f64> {
+        self.skip_whitespace();
+
+        if self.eat('(') {
+            let value = self.sum()?;
+            self.expect(')')?;
+            return Ok(value);
+        }
+
+        match self.chars.peek() {
+            Some(c) if c.is_ascii_digit() || *c == '.' => {
+                let mut number = self.take_while(|c| c.is_ascii_digit() || c == '.');
+                if let Some(e) = self.chars.next_if(|&c| c == 'e' || c == 'E') {
+                    number.push(e);
+                    if let Some(sign) = self.chars.next_if(|&c| c == '-' || c == '+') {
+                        number.push(sign);
+                    }
+                    number.push_str(&self.take_while(|c| c.is_ascii_digit()));
+                }
+                number
+                    .parse::<f64>()
+                    .map_err(|_| anyhow!("Invalid number '{}' in expression", number))
+            }
+            Some(c) if c.is_alphabetic() || *c == '_' => {
+                let name = self.take_while(|c| c.is_alphanumeric() || c == '_');
+                if self.eat('(') {
+                    let mut args = vec![self.sum()?];
+                    while self.eat(',') {
+                        args.push(self.sum()?);
+                    }
+                    self.expect(')')?;
+                    call(&name, &args)
+                } else if name == "pi" {
+                    Ok(std::f64::consts::PI)
+                } else {
+                    (self.var)(&name)
+                }
+            }
+            Some(c) => Err(anyhow!("Unexpected '{}' in expression", c)),
+            None => Err(anyhow!("Unexpected end of expression")),
+        }
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let mut taken = String::new();
+        while let Some(c) = self.chars.next_if(|&c| predicate(c)) {
+            taken.push(c);
+        }
+        taken
+    }
+}
+
+fn call(function: &str, args: &[f64]) -> Result<f64> {
+    match (function, args) {
+        ("sqrt", [x]) => Ok(x.sqrt()),
+        ("abs", [x]) => Ok(x.abs()),
+        ("sin", [x]) => Ok(x.to_radians().sin()),
+        ("cos", [x]) => Ok(x.to_radians().cos()),
+        ("tan", [x]) => Ok(x.to_radians().tan()),
+        ("min", [first, rest @ ..]) => Ok(rest.iter().fold(*first, |a, &b| a.min(b))),
+        ("max", [first, rest @ ..]) => Ok(rest.iter().fold(*first, |a, &b| a.max(b))),
+        ("sqrt" | "abs" | "sin" | "cos" | "tan", _) => Err(anyhow!(
+            "The function '{}' takes 1 argument but it was given {}",
+            function,
+            args.len()
+        )),
+        _ => Err(anyhow!("Unknown function '{}' in expression", function)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expression: &str) -> Result<f64> {
+        evaluate(expression, &mut |name| match name {
+            "height" => Ok(3.0),
+            _ => Err(anyhow!("Unknown name '{}'", name)),
+        })
+    }
+
+    fn assert_eval(expression: &str, expected: f64) {
+        let value = eval(expression).unwrap();
+        assert!(
+            (value - expected).abs() < 1e-12,
+            "'{}' gave {} instead of {}",
+            expression,
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eval("1 + 2 * 3", 7.0);
+        assert_eval("(1 + 2) * 3", 9.0);
+        assert_eval("8 / 4 / 2", 1.0);
+        assert_eval("10 - 2 - 3", 5.0);
+        assert_eval("2 * 3 ^ 2", 18.0);
+        assert_eval("2 * (height + 0.5) ^ 2", 24.5);
+    }
+
+    #[test]
+    fn power_binds_tighter_than_unary_minus() {
+        assert_eval("-2^2", -4.0);
+        assert_eval("(-2)^2", 4.0);
+        assert_eval("2^-1", 0.5);
+    }
+
+    #[test]
+    fn exponent_literals() {
+        assert_eval("1e-3", 0.001);
+        assert_eval("2.5E+2", 250.0);
+        assert_eval("1e3 * 2", 2000.0);
+    }
+
+    #[test]
+    fn functions() {
+        assert_eval("min(3, 1, 2)", 1.0);
+        assert_eval("max(3, 1, 2, height + 1)", 4.0);
+        assert_eval("min(5)", 5.0);
+        assert_eval("sqrt(16) + abs(-1)", 5.0);
+        assert_eval("cos(60)", 0.5);
+        assert_eval("pi", std::f64::consts::PI);
+    }
+
+    #[test]
+    fn wrong_arity_is_an_error() {
+        let error = eval("sqrt(1, 2)").unwrap_err().to_string();
+        assert!(error.contains("takes 1 argument"), "{}", error);
+    }
+
+    #[test]
+    fn trailing_input_is_rejected() {
+        assert!(eval("1 2").is_err());
+        assert!(eval("(1 + 2").is_err());
+        assert!(eval("1 +").is_err());
+        assert!(eval("width").is_err());
+        assert!(eval("foo(1)").is_err());
+    }
+}
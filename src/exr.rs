@@ -0,0 +1,88 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// Magic number every OpenEXR file starts with
+const MAGIC: i32 = 20000630;
+/// Version 2 of the format, as a single part image stored by scanlines
+const VERSION: i32 = 2;
+/// Channels of 32 bit floats
+const FLOAT: i32 = 2;
+
+/// Writes an uncompressed OpenEXR image with 32 bit float channels. The pixels are given row by
+/// row from the top, each with a value for every channel, and the channels are named like in
+/// `["R", "G", "B", "A"]`. The metadata is written as string attributes of the header.
+pub fn write_exr(
+    path: &Path,
+    width: u32,
+    height: u32,
+    channels: &[&str],
+    pixels: &[f32],
+    metadata: &[(String, String)],
+) -> Result<()> {
+    let mut file = Vec::new();
+    file.extend(MAGIC.to_le_bytes());
+    file.extend(VERSION.to_le_bytes());
+
+    // the channels are stored in alphabetical order
+    let mut order = (0..channels.len()).collect::<Vec<usize>>();
+    order.sort_by_key(|&c| channels[c]);
+
+    let mut list = Vec::new();
+    for &c in &order {
+        list.extend(channels[c].as_bytes());
+        list.push(0);
+        list.extend(FLOAT.to_le_bytes());
+        // linear flag and 3 reserved bytes, then the sampling along x and y
+        list.extend([0; 4]);
+        list.extend(1i32.to_le_bytes());
+        list.extend(1i32.to_le_bytes());
+    }
+    list.push(0);
+
+    let window = [0, 0, width as i32 - 1, height as i32 - 1]
+        .into_iter()
+        .flat_map(i32::to_le_bytes)
+        .collect::<Vec<u8>>();
+    let attribute = |file: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]| {
+        for text in [name, kind] {
+            file.extend(text.as_bytes());
+            file.push(0);
+        }
+        file.extend((value.len() as i32).to_le_bytes());
+        file.extend(value);
+    };
+    attribute(&mut file, "channels", "chlist", &list);
+    attribute(&mut file, "compression", "compression", &[0]);
+    attribute(&mut file, "dataWindow", "box2i", &window);
+    attribute(&mut file, "displayWindow", "box2i", &window);
+    attribute(&mut file, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut file, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+    attribute(&mut file, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(&mut file, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    for (name, value) in metadata {
+        attribute(&mut file, name, "string", value.as_bytes());
+    }
+    file.push(0);
+
+    // every scanline is a chunk, found through a table of where each one starts
+    let line_size = width as usize * channels.len() * 4;
+    let table_end = file.len() + height as usize * 8;
+    for y in 0..height as usize {
+        let offset = table_end + y * (8 + line_size);
+        file.extend((offset as u64).to_le_bytes());
+    }
+
+    let row_len = width as usize * channels.len();
+    for (y, row) in pixels.chunks(row_len).enumerate() {
+        file.extend((y as i32).to_le_bytes());
+        file.extend((line_size as i32).to_le_bytes());
+        for &c in &order {
+            for pixel in row.chunks(channels.len()) {
+                file.extend(pixel[c].to_le_bytes());
+            }
+        }
+    }
+
+    std::fs::write(path, file)
+        .with_context(|| format!("Couldn't write the image '{}'", path.display()))
+}
@@ -0,0 +1,251 @@
+use clap::ValueEnum;
+use rand::Rng;
+use std::ops::Range;
+
+use crate::sampling::{seeded_rng, SampleRng};
+
+/// Example scenes that can be generated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SceneKind {
+    /// Random spheres scattered over a checkered floor
+    Spheres,
+    /// Box with a red and a green wall and some spheres inside
+    Cornell,
+    /// Grid of spheres whose reflection grows to the right and specular hardness upwards
+    Materials,
+}
+
+impl SceneKind {
+    /// Amount of spheres used when none is given
+    pub fn default_count(self) -> u32 {
+        match self {
+            SceneKind::Spheres => 40,
+            SceneKind::Cornell => 3,
+            SceneKind::Materials => 5,
+        }
+    }
+}
+
+/// Writes the scene as the contents of an INI file, including its observer. `count` is the amount
+/// of spheres, except for `Materials` where it's the amount of spheres along each side of the grid.
+pub fn generate_scene(kind: SceneKind, seed: u64, count: u32) -> String {
+    let mut rng = seeded_rng(seed);
+    let mut ini = format!(
+        "; {:?} scene generated with seed {} and count {}\n",
+        kind, seed, count
+    );
+
+    match kind {
+        SceneKind::Spheres => spheres(&mut ini, &mut rng, count),
+        SceneKind::Cornell => cornell(&mut ini, &mut rng, count),
+        SceneKind::Materials => materials(&mut ini, &mut rng, count),
+    }
+
+    ini
+}
+
+fn spheres(ini: &mut String, rng: &mut SampleRng, count: u32) {
+    observer(ini, (50.0, 90.0, -60.0), 0.0);
+    scene(ini, 0.2, "#1a2333");
+    light(ini, "sun", (-50.0, 200.0, -100.0), 1.0);
+
+    section(
+        ini,
+        "plane floor",
+        &[
+            ("point", "(0, 0, 0)".to_string()),
+            ("normal", "(0, 1, 0)".to_string()),
+            ("color", "#9a9a9a".to_string()),
+            ("K_d", "0.8".to_string()),
+            ("K_s", "0.1".to_string()),
+            ("checkerboard", "20".to_string()),
+        ],
+    );
+
+    let placed = scatter(rng, count, 3.0..10.0, -30.0..130.0, 10.0..200.0);
+
+    for (i, &(x, z, r)) in placed.iter().enumerate() {
+        let mut fields = vec![
+            ("center", vec((x, r, z))),
+            ("r", format!("{:.2}", r)),
+            ("color", random_color(rng)),
+            ("K_d", format!("{:.2}", rng.gen_range(0.4..0.9))),
+            ("K_s", format!("{:.2}", rng.gen_range(0.1..0.9))),
+        ];
+        if rng.gen_bool(0.3) {
+            fields.push(("reflection", format!("{:.2}", rng.gen_range(0.2..0.8))));
+        }
+        section(ini, &format!("sphere {}", i + 1), &fields);
+    }
+}
+
+fn cornell(ini: &mut String, rng: &mut SampleRng, count: u32) {
+    observer(ini, (50.0, 50.0, -90.0), 0.0);
+    scene(ini, 0.15, "#000000");
+    light(ini, "ceiling", (50.0, 95.0, 50.0), 1.0);
+
+    let walls = [
+        ("floor", (50.0, 0.0, 50.0), (0.0, 1.0, 0.0), "#e0e0e0"),
+        ("ceiling", (50.0, 100.0, 50.0), (0.0, -1.0, 0.0), "#e0e0e0"),
+        ("back", (50.0, 50.0, 100.0), (0.0, 0.0, -1.0), "#e0e0e0"),
+        ("left", (0.0, 50.0, 50.0), (1.0, 0.0, 0.0), "#c02020"),
+        ("right", (100.0, 50.0, 50.0), (-1.0, 0.0, 0.0), "#20a020"),
+    ];
+    for (name, point, normal, color) in walls {
+        section(
+            ini,
+            &format!("plane {}", name),
+            &[
+                ("point", vec(point)),
+                ("normal", vec(normal)),
+                ("color", color.to_string()),
+                ("K_d", "0.9".to_string()),
+                ("K_s", "0".to_string()),
+            ],
+        );
+    }
+
+    let placed = scatter(rng, count, 8.0..16.0, 16.0..84.0, 36.0..84.0);
+    for (i, &(x, z, r)) in placed.iter().enumerate() {
+        let mut fields = vec![
+            ("center", vec((x, r, z))),
+            ("r", format!("{:.2}", r)),
+            ("color", random_color(rng)),
+            ("K_d", "0.7".to_string()),
+            ("K_s", "0.6".to_string()),
+        ];
+        match i % 3 {
+            0 => fields.push(("reflection", "0.8".to_string())),
+            1 => fields.push(("transparency", "0.7".to_string())),
+            _ => {}
+        }
+        section(ini, &format!("sphere {}", i + 1), &fields);
+    }
+}
+
+fn materials(ini: &mut String, rng: &mut SampleRng, count: u32) {
+    observer(ini, (50.0, 50.0, -100.0), 0.0);
+    scene(ini, 0.2, "#202028");
+    light(ini, "key", (-50.0, 150.0, -100.0), 1.0);
+
+    let color = random_color(rng);
+    let side = count.max(1);
+    let spacing = 100.0 / side as f64;
+
+    // 0 on the first row/column and 1 on the last one
+    let s = |i: u32| {
+        if side > 1 {
+            i as f64 / (side - 1) as f64
+        } else {
+            0.5
+        }
+    };
+
+    for row in 0..side {
+        for column in 0..side {
+            let (x, y) = (
+                (column as f64 + 0.5) * spacing,
+                (row as f64 + 0.5) * spacing,
+            );
+            section(
+                ini,
+                &format!("sphere {}x{}", column + 1, row + 1),
+                &[
+                    ("center", vec((x, y, 50.0))),
+                    ("r", format!("{:.2}", spacing * 0.4)),
+                    ("color", color.clone()),
+                    ("K_d", "0.7".to_string()),
+                    ("K_s", "0.8".to_string()),
+                    ("K_n", format!("{:.0}", 2.0 + 198.0 * s(row))),
+                    ("reflection", format!("{:.2}", 0.8 * s(column))),
+                ],
+            );
+        }
+    }
+}
+
+/// Places up to `count` spheres resting on the floor without overlapping, as (x, z, radius).
+/// A sphere that can't be placed after a while is left out.
+fn scatter(
+    rng: &mut SampleRng,
+    count: u32,
+    radius: Range<f64>,
+    x: Range<f64>,
+    z: Range<f64>,
+) -> Vec<(f64, f64, f64)> {
+    let mut placed: Vec<(f64, f64, f64)> = Vec::new();
+    for _ in 0..count {
+        for _ in 0..100 {
+            let r = rng.gen_range(radius.clone());
+            let (x, z) = (rng.gen_range(x.clone()), rng.gen_range(z.clone()));
+            let overlaps = placed
+                .iter()
+                .any(|&(px, pz, pr)| (px - x).powi(2) + (pz - z).powi(2) < (pr + r).powi(2));
+            if !overlaps {
+                placed.push((x, z, r));
+                break;
+            }
+        }
+    }
+    placed
+}
+
+fn observer(ini: &mut String, camera: (f64, f64, f64), z: f64) {
+    section(ini, "camera", &[("position", vec(camera))]);
+    section(
+        ini,
+        "projection plane",
+        &[
+            ("x_min", "0".to_string()),
+            ("y_min", "0".to_string()),
+            ("x_max", "100".to_string()),
+            ("y_max", "100".to_string()),
+            ("z", z.to_string()),
+        ],
+    );
+}
+
+fn scene(ini: &mut String, ambient: f64, bg_color: &str) {
+    section(
+        ini,
+        "scene",
+        &[
+            ("I_a", ambient.to_string()),
+            ("bg_color", bg_color.to_string()),
+        ],
+    );
+}
+
+fn light(ini: &mut String, name: &str, position: (f64, f64, f64), intensity: f64) {
+    section(
+        ini,
+        &format!("light {}", name),
+        &[
+            ("position", vec(position)),
+            ("intensity", intensity.to_string()),
+            ("C1", "1".to_string()),
+            ("C2", "0".to_string()),
+            ("C3", "0".to_string()),
+        ],
+    );
+}
+
+fn section(ini: &mut String, name: &str, fields: &[(&str, String)]) {
+    ini.push_str(&format!("\n[{}]\n", name));
+    for (key, value) in fields {
+        ini.push_str(&format!("{} = {}\n", key, value));
+    }
+}
+
+fn vec((x, y, z): (f64, f64, f64)) -> String {
+    format!("({:.2}, {:.2}, {:.2})", x, y, z)
+}
+
+fn random_color(rng: &mut SampleRng) -> String {
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        rng.gen_range(40..=255u8),
+        rng.gen_range(40..=255u8),
+        rng.gen_range(40..=255u8)
+    )
+}
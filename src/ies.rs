@@ -0,0 +1,186 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use std::path::Path;
+
+use crate::vec3::{Onb, Vec3};
+
+/// Photometric profile of a luminaire read from an IES (LM-63) file, giving how much light it
+/// sends in each direction. Only type C photometry is supported, where the vertical angle is
+/// measured from the luminaire's axis (0 looks along it) and the horizontal angle turns around it.
+#[derive(Debug, Clone, Serialize)]
+pub struct IesProfile {
+    path: String,
+    /// Increasing angles in degrees from the axis
+    #[serde(skip)]
+    vertical_angles: Vec<f64>,
+    /// Increasing angles in degrees around the axis
+    #[serde(skip)]
+    horizontal_angles: Vec<f64>,
+    /// Candela values of each horizontal angle, for every vertical angle, divided by the largest
+    /// one so that the light's intensity is kept as its peak
+    #[serde(skip)]
+    candela: Vec<Vec<f64>>,
+}
+
+impl IesProfile {
+    pub fn open(path: &Path) -> Result<IesProfile> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read the IES file '{}'", path.display()))?;
+        IesProfile::parse(&text, path)
+            .with_context(|| format!("The IES file '{}' isn't valid", path.display()))
+    }
+
+    fn parse(text: &str, path: &Path) -> Result<IesProfile> {
+        // the keywords of the header end at the TILT line, which may be followed by tilt data
+        let mut lines = text.lines();
+        let tilt = lines
+            .by_ref()
+            .map(str::trim)
+            .find(|line| line.to_uppercase().starts_with("TILT="))
+            .ok_or_else(|| anyhow!("It has no TILT line"))?
+            .to_uppercase();
+
+        let mut numbers = lines
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|token| !token.is_empty())
+            .map(|token| {
+                token
+                    .parse::<f64>()
+                    .map_err(|_| anyhow!("'{}' isn't a number", token))
+            });
+        let mut next = || {
+            numbers
+                .next()
+                .unwrap_or_else(|| Err(anyhow!("It ends too soon")))
+        };
+
+        match tilt.as_str() {
+            "TILT=NONE" => (),
+            // lamp to luminaire geometry, then the pairs of angles and multiplying factors, which
+            // only matter for luminaires that are tilted
+            "TILT=INCLUDE" => {
+                next()?;
+                let pairs = next()? as usize;
+                for _ in 0..2 * pairs {
+                    next()?;
+                }
+            }
+            _ => return Err(anyhow!("Tilt data in a separate file isn't supported")),
+        }
+
+        let _lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let vertical_count = next()? as usize;
+        let horizontal_count = next()? as usize;
+        let photometric_type = next()?;
+        // units and size of the luminaire, ballast factor, future use and input watts
+        for _ in 0..7 {
+            next()?;
+        }
+
+        if photometric_type != 1.0 {
+            return Err(anyhow!(
+                "Only type C photometry is supported, but it's of type {}",
+                if photometric_type == 2.0 { "B" } else { "A" }
+            ));
+        }
+        if vertical_count == 0 || horizontal_count == 0 {
+            return Err(anyhow!("It has no angles"));
+        }
+
+        let vertical_angles = (0..vertical_count)
+            .map(|_| next())
+            .collect::<Result<Vec<f64>>>()?;
+        let horizontal_angles = (0..horizontal_count)
+            .map(|_| next())
+            .collect::<Result<Vec<f64>>>()?;
+        let mut candela = (0..horizontal_count)
+            .map(|_| {
+                (0..vertical_count)
+                    .map(|_| next().map(|value| value * multiplier))
+                    .collect::<Result<Vec<f64>>>()
+            })
+            .collect::<Result<Vec<Vec<f64>>>>()?;
+
+        let increasing = |angles: &[f64]| angles.windows(2).all(|pair| pair[0] < pair[1]);
+        if !increasing(&vertical_angles) || !increasing(&horizontal_angles) {
+            return Err(anyhow!("Its angles aren't in increasing order"));
+        }
+
+        let max = candela.iter().flatten().copied().fold(0.0, f64::max);
+        if max <= 0.0 {
+            return Err(anyhow!("It doesn't send light in any direction"));
+        }
+        for value in candela.iter_mut().flatten() {
+            *value /= max;
+        }
+
+        Ok(IesProfile {
+            path: path.display().to_string(),
+            vertical_angles,
+            horizontal_angles,
+            candela,
+        })
+    }
+
+    /// Fraction of the peak intensity sent along `dir`, for a luminaire pointing along `axis`
+    pub fn factor(&self, axis: Vec3, dir: Vec3) -> f64 {
+        let frame = Onb::from_normal(axis);
+        let local = frame.world_to_local(dir.normalize());
+        let vertical = local.z.clamp(-1.0, 1.0).acos().to_degrees();
+        let horizontal = local.y.atan2(local.x).to_degrees().rem_euclid(360.0);
+
+        // the horizontal angles only cover a part of the circle when the luminaire is symmetric
+        let last = self.horizontal_angles[self.horizontal_angles.len() - 1];
+        let horizontal = if last <= 0.0 {
+            0.0
+        } else if last <= 90.0 {
+            let half = if horizontal > 180.0 {
+                360.0 - horizontal
+            } else {
+                horizontal
+            };
+            if half > 90.0 {
+                180.0 - half
+            } else {
+                half
+            }
+        } else if last <= 180.0 && horizontal > 180.0 {
+            360.0 - horizontal
+        } else {
+            horizontal
+        };
+
+        let Some((v, fv)) = bracket(&self.vertical_angles, vertical) else {
+            return 0.0;
+        };
+        let (h, fh) = bracket(&self.horizontal_angles, horizontal).unwrap_or((0, 0.0));
+        let h1 = (h + 1).min(self.horizontal_angles.len() - 1);
+        let v1 = (v + 1).min(self.vertical_angles.len() - 1);
+
+        let at = |h: usize, v: usize| self.candela[h][v];
+        (1.0 - fh) * ((1.0 - fv) * at(h, v) + fv * at(h, v1))
+            + fh * ((1.0 - fv) * at(h1, v) + fv * at(h1, v1))
+    }
+}
+
+/// Index of the angle right before `angle` and how far it is towards the next one, or `None` if
+/// it's outside of the angles
+fn bracket(angles: &[f64], angle: f64) -> Option<(usize, f64)> {
+    if angle < angles[0] || angle > angles[angles.len() - 1] {
+        return None;
+    }
+
+    let i = angles
+        .windows(2)
+        .position(|pair| angle <= pair[1])
+        .unwrap_or(0);
+    let span = angles.get(i + 1).map_or(0.0, |next| next - angles[i]);
+    let t = if span > 0.0 {
+        (angle - angles[i]) / span
+    } else {
+        0.0
+    };
+    Some((i, t))
+}
@@ -1,54 +1,600 @@
+mod aabb;
+mod bezier;
+mod bvh;
+mod compare;
 mod constants;
+mod denoise;
+mod environment;
+mod expr;
+mod exr;
+mod generate;
+mod ies;
+mod medium;
+mod noise;
+mod obj;
+mod pbr;
 mod raytracer;
+mod sampling;
 mod scene;
 mod screen;
 mod shapes;
+mod sky;
+mod solvers;
+mod srgb;
+mod texture;
+mod transform;
 mod vec3;
 
-use anyhow::{Context, Result};
-use screen::ScreenContextManager;
+use anyhow::{anyhow, Context, Result};
+use screen::{is_hdr, BitDepth, Region, Resolution, ScreenContextManager};
 
-use clap::Parser;
-use constants::{DEFAULT_IMAGE, DEFAULT_RES};
-use raytracer::raytrace;
-use scene::{Observer, Scene};
-use std::{thread::sleep, time::Duration};
+use clap::{CommandFactory, Parser, Subcommand};
+use compare::compare_images;
+use constants::{DEFAULT_IMAGE, DEFAULT_RES, STDOUT_IMAGE, WATCH_INTERVAL_MS};
+use generate::{generate_scene, SceneKind};
+use raytracer::{raytrace, raytrace_aov, raytrace_stereo, Aov, Progress, StereoMode};
+use sampling::Sampler;
+use scene::{
+    config_files, find_unknown_fields, ConfigFormat, ConfigOverride, Observer, OtherLayers, Scene,
+    ToneMapping,
+};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, sleep};
+use std::time::{Duration, SystemTime};
 
 fn main() -> Result<()> {
     // Parse args
-    let args = Args::parse();
+    let cli = Cli::parse_from(with_default_command(std::env::args_os().collect()));
 
-    let observer_file = {
-        if let Some(file) = args.observer {
-            file
-        } else {
-            args.scene.clone()
+    match cli.command {
+        Command::Render(args) => render_command(&args),
+        Command::Preview(mut args) => {
+            args.preview = true;
+            render_command(&args)
         }
+        Command::Validate(args) => validate(&args),
+        Command::Info(args) => info(&args),
+        Command::Generate {
+            scene,
+            kind,
+            seed,
+            count,
+        } => generate(&scene, kind, seed, count),
+        Command::Compare {
+            reference,
+            image,
+            heatmap,
+            threshold,
+        } => compare(&reference, &image, heatmap.as_deref(), threshold),
+    }
+}
+
+/// The command line with the `render` subcommand added if no subcommand is given, so the image can
+/// still be rendered with the flags alone. A subcommand given after the flags, like in
+/// `-s scene.ini validate`, is moved before them.
+fn with_default_command(mut args: Vec<OsString>) -> Vec<OsString> {
+    let cli = Cli::command();
+    let is_command = |arg: &OsString| {
+        arg.to_str()
+            .is_some_and(|arg| arg == "help" || cli.find_subcommand(arg).is_some())
+    };
+    // the values of the flags may happen to be named like a subcommand
+    let render = cli
+        .find_subcommand("render")
+        .expect("render is a subcommand");
+    let takes_value = |arg: &OsString| {
+        let arg = arg.to_string_lossy();
+        render.get_arguments().any(|a| {
+            a.is_takes_value_set()
+                && (a
+                    .get_long()
+                    .is_some_and(|long| arg == format!("--{}", long))
+                    || a.get_short()
+                        .is_some_and(|short| arg == format!("-{}", short)))
+        })
     };
 
+    let command = (1..args.len()).find(|&i| is_command(&args[i]) && !takes_value(&args[i - 1]));
+    match command {
+        Some(i) => {
+            let command = args.remove(i);
+            args.insert(1, command);
+        }
+        // without arguments, or with only '--help', clap lists the subcommands
+        None if args.len() == 1
+            || (args.len() == 2 && (args[1] == "-h" || args[1] == "--help")) => {}
+        None => args.insert(1, OsString::from("render")),
+    }
+    args
+}
+
+/// Renders the image, or keeps rendering it whenever its files change if it's watched
+fn render_command(args: &RenderArgs) -> Result<()> {
+    if args.image == STDOUT_IMAGE
+        && (args.frames.is_some()
+            || args.layer_images
+            || !args.aovs.is_empty()
+            || args.progressive
+            || args.watch)
+    {
+        return Err(anyhow!(
+            "Only a single image can be written to stdout, so '-o -' can't be used with --frames, --layer-images, --aov, --progressive or --watch"
+        ));
+    }
+
+    if args.watch {
+        return watch(args);
+    }
+
+    render(args, &AtomicBool::new(false))?;
+
+    sleep(Duration::from_millis(900));
+
+    Ok(())
+}
+
+/// Reads the scene and observer and renders them, stopping early if `cancel` gets set
+fn render(args: &RenderArgs, cancel: &AtomicBool) -> Result<()> {
     // scene stuff
-    let scene = Scene::read_config(args.scene)?;
+    let mut scene = read_scene(&args.input)?;
+    if args.preview {
+        scene.preview();
+    }
+    if let Some(max_depth) = args.max_depth {
+        scene.render.max_reflections = max_depth;
+    }
+    if args.no_shadows {
+        scene.render.shadows = false;
+    }
+    if let Some(seed) = args.seed {
+        scene.render.seed = seed;
+    }
+    if let Some(sampler) = args.sampler {
+        scene.render.sampler = sampler;
+    }
+    if args.linear {
+        scene.render.srgb = false;
+    }
+    if args.transparent {
+        scene.render.transparent = true;
+    }
+    scene.quiet = args.quiet;
+    // only 8 bit images need the colors brighter than white clamped, HDR ones can keep them
+    if is_hdr(&args.image) && scene.render.tone_mapping == ToneMapping::Clamp {
+        scene.render.tone_mapping = ToneMapping::None;
+    }
+    if !args.layers.is_empty() {
+        scene.select_layers(args.layers.clone(), args.other_layers)?;
+    }
+
+    let mut observer = read_observer(&args.input).context("Perhaps you need to specify the path to the observer file you want to read, run with '--help' flag for more info.")?;
+    if args.preview {
+        observer.lens.samples = 1;
+    }
+
+    let frames = match args.frames {
+        Some(frames) => frames,
+        None => return render_layers(args, &mut scene, observer, &args.image, cancel),
+    };
+    if observer.animation.is_none() {
+        return Err(anyhow!(
+            "Rendering several frames needs an [animation] section in the observer"
+        ));
+    }
+
+    for frame in 0..frames {
+        if cancel.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let t = if frames > 1 {
+            f64::from(frame) / f64::from(frames - 1)
+        } else {
+            0.0
+        };
+        let path = suffixed_path(&args.image, &format!("{:04}", frame + 1));
+        if !args.quiet {
+            println!("Rendering frame {}/{} to '{}'", frame + 1, frames, path);
+        }
+        render_layers(args, &mut scene, observer.at_time(t)?, &path, cancel)?;
+    }
+
+    Ok(())
+}
+
+/// Path of an image with the suffix added to its name, like the frame's number of the images of
+/// an animation (out_0001.png)
+fn suffixed_path(image: &str, suffix: &str) -> String {
+    let path = Path::new(image);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}_{}.{}", stem, suffix, extension.to_string_lossy()),
+        None => format!("{}_{}", stem, suffix),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// Renders the image at the path or, if images of the layers were asked for, an image of each of
+/// the selected layers (or of every layer) with the layer's name added to the path
+fn render_layers(
+    args: &RenderArgs,
+    scene: &mut Scene,
+    observer: Observer,
+    path: &str,
+    cancel: &AtomicBool,
+) -> Result<()> {
+    if !args.layer_images {
+        return render_image(args, scene, observer, path, cancel);
+    }
 
-    let observer = Observer::read_config(observer_file).context("Perhaps you need to specify the path to the observer file you want to read, run with '--help' flag for more info.")?;
+    let layers = if args.layers.is_empty() {
+        scene.layers()
+    } else {
+        args.layers.clone()
+    };
+    for layer in layers {
+        if cancel.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        scene.select_layers(vec![layer.clone()], args.other_layers)?;
+        let path = suffixed_path(path, &layer);
+        if !args.quiet {
+            println!("Rendering layer '{}' to '{}'", layer, path);
+        }
+        render_image(args, scene, observer.clone(), &path, cancel)?;
+    }
 
+    Ok(())
+}
+
+/// Renders the scene seen by the observer into the image at the path
+fn render_image(
+    args: &RenderArgs,
+    scene: &Scene,
+    mut observer: Observer,
+    path: &str,
+    cancel: &AtomicBool,
+) -> Result<()> {
     // sdl screen
-    let mut screen = ScreenContextManager::new(args.resolution, args.resolution);
+    let size = args.image_size()?;
+    let mut screen = ScreenContextManager::new(size.width, size.height);
+    screen.set_transparent(scene.render.transparent);
+    screen.set_srgb(scene.render.srgb);
+    screen.set_bit_depth(args.bit_depth);
+    observer.fit_to_image(size.width, size.height);
+    let metadata = metadata(args, scene, &observer, size)?;
+    screen.set_metadata(metadata.clone());
+
+    if args.stereo.is_some() && args.region.is_some() {
+        return Err(anyhow!("Stereo images can't be rendered by regions"));
+    }
+    if args.stereo.is_some() && args.progressive {
+        return Err(anyhow!("Stereo images can't be rendered progressively"));
+    }
+    if args.stereo.is_some() && !args.aovs.is_empty() {
+        return Err(anyhow!("Stereo images can't have AOVs"));
+    }
+    let region = match args.region {
+        Some(region) => {
+            if !region.fits_in(size) {
+                return Err(anyhow!(
+                    "The region {},{},{},{} doesn't fit in the {}x{} image",
+                    region.x,
+                    region.y,
+                    region.width,
+                    region.height,
+                    size.width,
+                    size.height
+                ));
+            }
+            // the rest of the image is kept from the previous render, if there's one
+            if Path::new(path).exists() {
+                screen.load_img(path).context(
+                    "The rest of the image is taken from the existing output image, remove it or pick another output path",
+                )?;
+            }
+            region
+        }
+        None => Region::full(size),
+    };
+
+    // one bar for everything traced for the image, both eyes of stereo images and the AOVs
+    let images = if args.stereo.is_some() { 2 } else { 1 };
+    let progress = Progress::new(
+        images * Progress::image_work(region, scene)
+            + args.aovs.len() as u64 * Progress::aov_work(region),
+        scene,
+    );
 
     // raytrace :)
-    raytrace(args.image, &observer, &scene, &mut screen)?;
+    match args.stereo {
+        Some(mode) => raytrace_stereo(path, &observer, scene, &screen, mode, &progress, cancel)?,
+        None => raytrace(
+            path,
+            &observer,
+            scene,
+            &mut screen,
+            region,
+            args.progressive,
+            &progress,
+            cancel,
+        )?,
+    }
 
-    sleep(Duration::from_millis(900));
+    for &aov in &args.aovs {
+        if cancel.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let aov_path = suffixed_path(path, aov.name());
+        let mut screen = ScreenContextManager::new(size.width, size.height);
+        screen.set_bit_depth(args.bit_depth);
+        screen.set_metadata(metadata.clone());
+        if region != Region::full(size) && Path::new(&aov_path).exists() {
+            screen.load_img(&aov_path).with_context(|| {
+                format!(
+                    "The rest of the {} AOV is taken from its existing image, remove it or pick another output path",
+                    aov.name()
+                )
+            })?;
+        }
+        raytrace_aov(
+            &aov_path,
+            &observer,
+            scene,
+            &mut screen,
+            region,
+            aov,
+            &progress,
+            cancel,
+        )?;
+    }
 
+    progress.finish();
     Ok(())
 }
 
-/// Raytracer that reads .ini config files and generates an image.
+/// Text entries saved in the images, so the settings that produced an image can be told from it
+fn metadata(
+    args: &RenderArgs,
+    scene: &Scene,
+    observer: &Observer,
+    size: Resolution,
+) -> Result<Vec<(String, String)>> {
+    Ok(vec![
+        (
+            "Software".to_string(),
+            format!("raytracer_ini {}", env!("CARGO_PKG_VERSION")),
+        ),
+        ("Scene".to_string(), args.input.scene.clone()),
+        (
+            "Observer".to_string(),
+            args.input.observer_file().to_string(),
+        ),
+        ("Camera".to_string(), serde_json::to_string(observer)?),
+        (
+            "Resolution".to_string(),
+            format!("{}x{}", size.width, size.height),
+        ),
+        (
+            "Render settings".to_string(),
+            serde_json::to_string(&scene.render)?,
+        ),
+    ])
+}
+
+/// Renders again every time the scene or observer files (or the files they include) change,
+/// cancelling the render in progress
+fn watch(args: &RenderArgs) -> Result<()> {
+    loop {
+        let mut files = config_files(&args.input.scene);
+        files.extend(config_files(args.input.observer_file()));
+        let modified = modification_times(&files);
+
+        let changed = AtomicBool::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                while modification_times(&files) == modified {
+                    sleep(Duration::from_millis(WATCH_INTERVAL_MS));
+                }
+                changed.store(true, Ordering::Relaxed);
+            });
+
+            match render(args, &changed) {
+                Ok(()) if changed.load(Ordering::Relaxed) => {}
+                Ok(()) => println!("Saved '{}', waiting for changes...", args.image),
+                Err(e) => eprintln!("Error: {:#}\nWaiting for changes...", e),
+            }
+        });
+
+        println!("Change detected, rendering again");
+    }
+}
+
+fn modification_times(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|file| fs::metadata(file).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+fn read_scene(args: &SceneArgs) -> Result<Scene> {
+    let path = args.scene.as_str();
+    match ConfigFormat::from_path(path) {
+        ConfigFormat::Ini => Scene::read_config(path, &args.set),
+        ConfigFormat::Json => Scene::read_json(path, &args.set),
+        ConfigFormat::Toml => Scene::read_toml(path, &args.set),
+    }
+}
+
+fn read_observer(args: &SceneArgs) -> Result<Observer> {
+    let path = args.observer_file();
+    let camera = args.camera.as_deref();
+    match ConfigFormat::from_path(path) {
+        ConfigFormat::Ini => Observer::read_config(path, &args.set, camera),
+        ConfigFormat::Json => Observer::read_json(path, &args.set, camera),
+        ConfigFormat::Toml => Observer::read_toml(path, &args.set, camera),
+    }
+}
+
+/// Reads the scene and observer, printing every error and unknown field found in them
+fn validate(args: &SceneArgs) -> Result<()> {
+    let (scene_file, observer_file) = (args.scene.as_str(), args.observer_file());
+
+    let mut files = vec![scene_file];
+    if observer_file != scene_file {
+        files.push(observer_file);
+    }
+    for file in files {
+        for warning in find_unknown_fields(file, &args.set)? {
+            eprintln!("warning: {}", warning);
+        }
+    }
+
+    let mut valid = true;
+    if let Err(e) = read_scene(args) {
+        eprintln!("error in scene '{}': {:#}", scene_file, e);
+        valid = false;
+    }
+    if let Err(e) = read_observer(args) {
+        eprintln!("error in observer '{}': {:#}", observer_file, e);
+        valid = false;
+    }
+
+    if valid {
+        println!("The scene and observer are valid");
+        Ok(())
+    } else {
+        Err(anyhow!("The scene or observer have errors"))
+    }
+}
+
+/// Prints the scene and observer as JSON, as they were understood after applying every default
+fn info(args: &SceneArgs) -> Result<()> {
+    let scene = read_scene(args)?;
+    let observer = read_observer(args)?;
+
+    let dump = serde_json::json!({ "scene": scene, "observer": observer });
+    println!("{}", serde_json::to_string_pretty(&dump)?);
+
+    Ok(())
+}
+
+/// Writes a generated example scene to the path, which mustn't exist yet
+fn generate(path: &str, kind: SceneKind, seed: u64, count: Option<u32>) -> Result<()> {
+    if std::path::Path::new(path).exists() {
+        return Err(anyhow!(
+            "The file '{}' already exists, choose another path for the generated scene",
+            path
+        ));
+    }
+
+    let ini = generate_scene(kind, seed, count.unwrap_or_else(|| kind.default_count()));
+    std::fs::write(path, ini).with_context(|| format!("Couldn't write the scene to '{}'", path))?;
+
+    println!("Wrote the scene to '{}', render it with: -s {}", path, path);
+    Ok(())
+}
+
+/// Prints how much the image differs from the reference, failing if its mean squared error is
+/// over the threshold
+fn compare(reference: &str, image: &str, heatmap: Option<&str>, threshold: f64) -> Result<()> {
+    let comparison = compare_images(
+        Path::new(reference),
+        Path::new(image),
+        heatmap.map(Path::new),
+    )?;
+
+    println!("MSE: {:.8}", comparison.mse);
+    println!("PSNR: {:.2} dB", comparison.psnr());
+    println!(
+        "Max difference: {:.6} at {},{}",
+        comparison.max_difference, comparison.max_pixel.0, comparison.max_pixel.1
+    );
+    println!(
+        "Differing pixels: {} of {} ({:.2}%)",
+        comparison.differing_pixels,
+        comparison.total_pixels,
+        100.0 * comparison.differing_pixels as f64 / comparison.total_pixels as f64
+    );
+    if let Some(heatmap) = heatmap {
+        println!("Wrote the heatmap to '{}'", heatmap);
+    }
+
+    if comparison.mse > threshold {
+        return Err(anyhow!(
+            "The images differ by an MSE of {:.8}, over the threshold of {}",
+            comparison.mse,
+            threshold
+        ));
+    }
+    Ok(())
+}
+
+/// Raytracer that reads .ini (or .json/.toml) config files and generates an image.
 #[derive(Parser, Debug)]
 #[clap(author, about, long_about = None)]
-struct Args {
-    #[clap(default_value_t = DEFAULT_RES)]
-    resolution: u32,
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Render the scene seen by the observer into an image, the default when no subcommand is given
+    Render(RenderArgs),
+    /// Render a quick rough image, with the same options as 'render'
+    ///
+    /// Reflections and refractions only go one bounce deep, there's a single sample for depth of
+    /// field, soft shadows and emissive lighting, no anti-aliasing nor denoising, and the medium is
+    /// marched in few steps
+    Preview(RenderArgs),
+    /// Check the scene and observer for errors and unknown fields without rendering
+    Validate(SceneArgs),
+    /// Print the fully resolved scene and observer, defaults included, as JSON
+    Info(SceneArgs),
+    /// Write an example scene (with its observer) to the scene path
+    Generate {
+        /// Path the scene is written to
+        #[clap(short, long)]
+        scene: String,
+
+        #[clap(value_enum)]
+        kind: SceneKind,
+
+        /// Seed for the random placement and colors
+        #[clap(long, default_value_t = 0)]
+        seed: u64,
 
+        /// Number of spheres, or of spheres along each side of the grid for 'materials'
+        #[clap(long)]
+        count: Option<u32>,
+    },
+    /// Compare an image with a reference one, failing if they differ by more than the threshold
+    Compare {
+        /// Path to the reference image
+        reference: String,
+
+        /// Path to the image compared with it
+        image: String,
+
+        /// Save an image of where they differ, brighter where they differ more
+        #[clap(long)]
+        heatmap: Option<String>,
+
+        /// Largest mean squared error (with channels between 0 and 1) the images may have
+        #[clap(long, default_value_t = 0.0)]
+        threshold: f64,
+    },
+}
+
+/// The scene and observer read by every subcommand that needs them
+#[derive(clap::Args, Debug)]
+struct SceneArgs {
     /// Path to scene's config file
     #[clap(short, long)]
     scene: String,
@@ -57,7 +603,142 @@ struct Args {
     #[clap(short = 'O', long)]
     observer: Option<String>,
 
-    /// Path to image output
+    /// Name of the camera to render from, for observers with several '[camera NAME]' sections
+    #[clap(long)]
+    camera: Option<String>,
+
+    /// Override a value of the scene or observer, can be repeated (e.g. --set "light a.intensity=0.5")
+    #[clap(long = "set", value_name = "SECTION.KEY=VALUE")]
+    set: Vec<ConfigOverride>,
+}
+
+impl SceneArgs {
+    /// The observer is read from the scene file unless a different one is given
+    fn observer_file(&self) -> &str {
+        self.observer.as_deref().unwrap_or(&self.scene)
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct RenderArgs {
+    #[clap(flatten)]
+    input: SceneArgs,
+
+    /// Size of the image, either a single number for square images or WIDTHxHEIGHT
+    #[clap(default_value_t = Resolution::square(DEFAULT_RES))]
+    resolution: Resolution,
+
+    /// Width of the image, replaces the one given by the resolution
+    #[clap(long)]
+    width: Option<u32>,
+
+    /// Height of the image, replaces the one given by the resolution
+    #[clap(long)]
+    height: Option<u32>,
+
+    /// Path to image output, '-' writes it to stdout as a binary PPM
     #[clap(short='o', long, default_value = DEFAULT_IMAGE)]
     image: String,
+
+    /// Only render the rectangle X,Y,WIDTH,HEIGHT (in pixels from the top left corner), the rest
+    /// of the image is kept from the existing output image or left black
+    #[clap(long, value_name = "X,Y,WIDTH,HEIGHT")]
+    region: Option<Region>,
+
+    /// Also save these AOVs (comma separated) of the image, each to its own image with the AOV's
+    /// name added to the output image's name (like out_normal.png)
+    #[clap(
+        long = "aov",
+        value_name = "AOVS",
+        value_enum,
+        use_value_delimiter = true
+    )]
+    aovs: Vec<Aov>,
+
+    /// Only render the objects of these layers (comma separated), the others become what
+    /// '--other-layers' says
+    #[clap(long, value_name = "LAYERS", use_value_delimiter = true)]
+    layers: Vec<String>,
+
+    /// What the objects of the layers that aren't rendered become
+    #[clap(long, value_enum, default_value = "shadows")]
+    other_layers: OtherLayers,
+
+    /// Render an image of each layer (or of each of the '--layers'), with the layer's name added
+    /// to the output image's name (like out_background.png)
+    #[clap(long)]
+    layer_images: bool,
+
+    /// Render an image for each eye, from cameras 'eye_separation' apart, and put them together
+    #[clap(long, value_enum)]
+    stereo: Option<StereoMode>,
+
+    /// Render this many frames of the observer's animation, each to the output path with the
+    /// frame's number added (like out_0001.png)
+    #[clap(long)]
+    frames: Option<u32>,
+
+    /// Most reflections and refractions followed by each ray, replaces the scene's
+    /// 'max_reflections' (e.g. 0 or 1 for quick previews)
+    #[clap(long)]
+    max_depth: Option<u32>,
+
+    /// Don't compute shadows, replaces the scene's 'shadows'
+    #[clap(long)]
+    no_shadows: bool,
+
+    /// How the samples of anti-aliasing, depth of field, soft shadows and emissive lighting are
+    /// spread, replaces the scene's 'sampler'
+    #[clap(long, value_enum)]
+    sampler: Option<Sampler>,
+
+    /// Seed of the random samples of every sampled effect, replaces the scene's 'seed'. Renders
+    /// with the same seed are identical, other seeds give other noise
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// Write the image's colors linearly instead of encoded as sRGB, replaces the scene's 'srgb'
+    #[clap(long)]
+    linear: bool,
+
+    /// Bits per channel of the image (8 or 16), 16 bit images don't band in subtle gradients. Only
+    /// some formats, like PNG and TIFF, can store 16, and HDR images are always in floating point
+    #[clap(long, default_value_t = BitDepth::Eight)]
+    bit_depth: BitDepth,
+
+    /// Leave the background transparent and save the image as RGBA, replaces the scene's
+    /// 'transparent'
+    #[clap(long)]
+    transparent: bool,
+
+    /// Save a coarse preview of the image first and refine it over several passes, saving the
+    /// image after each one
+    #[clap(long)]
+    progressive: bool,
+
+    /// Keep running and render again whenever the scene or observer files change
+    #[clap(long)]
+    watch: bool,
+
+    /// Don't show the progress of the render
+    #[clap(short, long)]
+    quiet: bool,
+
+    /// Whether it's rendered as a rough preview, set by the 'preview' subcommand
+    #[clap(skip)]
+    preview: bool,
+}
+
+impl RenderArgs {
+    /// The resolution with the width and height flags applied
+    fn image_size(&self) -> Result<Resolution> {
+        let size = Resolution {
+            width: self.width.unwrap_or(self.resolution.width),
+            height: self.height.unwrap_or(self.resolution.height),
+        };
+        if size.width == 0 || size.height == 0 {
+            return Err(anyhow!("The width and height of the image can't be 0"));
+        }
+        Ok(size)
+    }
 }
@@ -0,0 +1,26 @@
+use serde::Serialize;
+
+use crate::shapes::Color;
+
+/// Homogeneous participating medium, like fog or smoke, filling the space between the objects.
+/// It dims everything seen through it and scatters the light of the lights that reach it, so
+/// spot lights show up as beams.
+#[derive(Debug, Clone, Serialize)]
+pub struct Medium {
+    /// Share of the light absorbed or scattered per unit of distance
+    pub density: f64,
+    /// Color of the light the medium scatters, fog fully lit by a white light of intensity 1 looks
+    /// like this color
+    pub color: Color,
+    /// Points sampled along every ray to gather the scattered light
+    pub steps: u32,
+    /// How far the medium reaches along the rays that don't hit anything
+    pub distance: f64,
+}
+
+impl Medium {
+    /// Fraction of the light that goes through `distance` of the medium
+    pub fn transmittance(&self, distance: f64) -> f64 {
+        (-self.density * distance).exp()
+    }
+}
@@ -0,0 +1,179 @@
+use rand::seq::SliceRandom;
+use serde::Serialize;
+use std::f64::consts::PI;
+
+use crate::constants::PATTERN_OCTAVES;
+use crate::sampling::seeded_rng;
+use crate::shapes::Color;
+use crate::vec3::Vec3;
+
+/// Gradient noise, ref: https://mrl.cs.nyu.edu/~perlin/noise/
+#[derive(Debug, Clone)]
+pub struct Perlin {
+    /// Shuffled 0..256 repeated twice so that indices can overflow past 256
+    permutation: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Perlin {
+        let mut permutation: Vec<usize> = (0..256).collect();
+        permutation.shuffle(&mut seeded_rng(seed));
+        permutation.extend_from_within(..);
+
+        Perlin { permutation }
+    }
+
+    /// Noise value at the point, roughly between -1 and 1, that's 0 at every integer lattice point
+    pub fn noise(&self, point: Vec3) -> f64 {
+        let p = &self.permutation;
+
+        let (x, y, z) = (point.x.floor(), point.y.floor(), point.z.floor());
+        let (xi, yi, zi) = (
+            x.rem_euclid(256.0) as usize,
+            y.rem_euclid(256.0) as usize,
+            z.rem_euclid(256.0) as usize,
+        );
+        let (xf, yf, zf) = (point.x - x, point.y - y, point.z - z);
+        let (u, v, w) = (fade(xf), fade(yf), fade(zf));
+
+        let a = p[xi] + yi;
+        let aa = p[a] + zi;
+        let ab = p[a + 1] + zi;
+        let b = p[xi + 1] + yi;
+        let ba = p[b] + zi;
+        let bb = p[b + 1] + zi;
+
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, grad(p[aa], xf, yf, zf), grad(p[ba], xf - 1.0, yf, zf)),
+                lerp(
+                    u,
+                    grad(p[ab], xf, yf - 1.0, zf),
+                    grad(p[bb], xf - 1.0, yf - 1.0, zf),
+                ),
+            ),
+            lerp(
+                v,
+                lerp(
+                    u,
+                    grad(p[aa + 1], xf, yf, zf - 1.0),
+                    grad(p[ba + 1], xf - 1.0, yf, zf - 1.0),
+                ),
+                lerp(
+                    u,
+                    grad(p[ab + 1], xf, yf - 1.0, zf - 1.0),
+                    grad(p[bb + 1], xf - 1.0, yf - 1.0, zf - 1.0),
+                ),
+            ),
+        )
+    }
+
+    /// Fractal brownian motion, sum of `octaves` layers of noise each with double the frequency
+    /// and half the amplitude of the previous one, normalized to stay roughly between -1 and 1
+    pub fn fbm(&self, point: Vec3, octaves: u32) -> f64 {
+        let mut sum = 0.0;
+        let mut amplitude = 1.0;
+        let mut total_amplitude = 0.0;
+        let mut p = point;
+
+        for _ in 0..octaves {
+            sum += amplitude * self.noise(p);
+            total_amplitude += amplitude;
+            amplitude *= 0.5;
+            p *= 2.0;
+        }
+
+        sum / total_amplitude
+    }
+
+    /// Like `fbm` but adding up the absolute value of each layer, which gives billowy patterns
+    /// between 0 and roughly 1
+    pub fn turbulence(&self, point: Vec3, octaves: u32) -> f64 {
+        let mut sum = 0.0;
+        let mut amplitude = 1.0;
+        let mut total_amplitude = 0.0;
+        let mut p = point;
+
+        for _ in 0..octaves {
+            sum += amplitude * self.noise(p).abs();
+            total_amplitude += amplitude;
+            amplitude *= 0.5;
+            p *= 2.0;
+        }
+
+        sum / total_amplitude
+    }
+}
+
+/// Procedural solid texture that blends an object's color with a second one through space
+#[derive(Debug, Clone, Serialize)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    /// Second color of the pattern
+    pub color: Color,
+    /// Size of the pattern's features, like the width of the veins or the rings
+    pub scale: f64,
+    /// How much the noise distorts the veins of marble or the rings of wood
+    pub turbulence: f64,
+    #[serde(skip)]
+    pub perlin: Perlin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum PatternKind {
+    /// Veins along x bent by turbulence
+    Marble,
+    /// Rings around the y axis, slightly distorted by noise
+    Wood,
+    /// Plain fractal noise
+    Noise,
+}
+
+impl Pattern {
+    /// Color at the point, between the object's `base` color and the pattern's color
+    pub fn color_at(&self, point: Vec3, base: Color) -> Color {
+        let p = point * (1.0 / self.scale);
+        let t = match self.kind {
+            PatternKind::Marble => {
+                let veins = p.x + self.turbulence * self.perlin.turbulence(p, PATTERN_OCTAVES);
+                0.5 * (1.0 + (veins * PI).sin())
+            }
+            PatternKind::Wood => {
+                let rings =
+                    (p.x * p.x + p.z * p.z).sqrt() + self.turbulence * self.perlin.noise(p * 0.5);
+                // sharp dark edge at the end of each ring
+                rings.rem_euclid(1.0).powi(3)
+            }
+            PatternKind::Noise => 0.5 * (1.0 + self.perlin.fbm(p, PATTERN_OCTAVES)),
+        }
+        .clamp(0.0, 1.0);
+
+        (1.0 - t) * base + t * self.color
+    }
+}
+
+/// 6t^5 - 15t^4 + 10t^3
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+/// Dot product between the offset and one of 12 gradient directions chosen by the hash
+fn grad(hash: usize, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
@@ -0,0 +1,142 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::vec3::Vec3;
+
+/// Vertex of a face, indices into the `positions`, `texture_coords` and `normals` of the
+/// `ObjData`
+#[derive(Debug, Clone, Copy)]
+pub struct FaceVertex {
+    pub position: usize,
+    pub texture_coords: Option<usize>,
+    pub normal: Option<usize>,
+}
+
+/// Geometry read from a Wavefront OBJ file, polygons are triangulated as fans
+pub struct ObjData {
+    pub positions: Vec<Vec3>,
+    pub texture_coords: Vec<(f64, f64)>,
+    pub normals: Vec<Vec3>,
+    pub triangles: Vec<[FaceVertex; 3]>,
+}
+
+/// Reads the vertices, texture coords, vertex normals and faces of an OBJ file, every other
+/// statement (groups, materials...) is ignored
+pub fn read_obj<P: AsRef<Path>>(path: P) -> Result<ObjData> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read OBJ file '{}'", path.display()))?;
+
+    let mut data = ObjData {
+        positions: Vec::new(),
+        texture_coords: Vec::new(),
+        normals: Vec::new(),
+        triangles: Vec::new(),
+    };
+
+    for (i, line) in contents.lines().enumerate() {
+        parse_line(line, &mut data)
+            .with_context(|| format!("In OBJ file '{}' at line {}", path.display(), i + 1))?;
+    }
+
+    if data.triangles.is_empty() {
+        return Err(anyhow!("OBJ file '{}' has no faces", path.display()));
+    }
+
+    Ok(data)
+}
+
+fn parse_line(line: &str, data: &mut ObjData) -> Result<()> {
+    let mut tokens = line.split_whitespace();
+
+    match tokens.next() {
+        Some("v") => data.positions.push(parse_vec3(tokens)?),
+        Some("vt") => data.texture_coords.push(parse_texture_coords(tokens)?),
+        Some("vn") => data.normals.push(parse_vec3(tokens)?),
+        Some("f") => {
+            let vertices = tokens
+                .map(|t| parse_face_vertex(t, data))
+                .collect::<Result<Vec<FaceVertex>>>()?;
+
+            if vertices.len() < 3 {
+                return Err(anyhow!("Face with less than 3 vertices"));
+            }
+
+            for j in 1..vertices.len() - 1 {
+                data.triangles
+                    .push([vertices[0], vertices[j], vertices[j + 1]]);
+            }
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
+
+fn parse_vec3<'a, I: Iterator<Item = &'a str>>(tokens: I) -> Result<Vec3> {
+    let floats = tokens
+        .take(3)
+        .map(|t| t.parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+        .context("Invalid floating point number")?;
+
+    if floats.len() != 3 {
+        return Err(anyhow!("Expected 3 coordinates, found {}", floats.len()));
+    }
+
+    Ok(Vec3::new(floats[0], floats[1], floats[2]))
+}
+
+/// Parses `u [v] [w]`, where `v` defaults to 0 and `w` is ignored
+fn parse_texture_coords<'a, I: Iterator<Item = &'a str>>(tokens: I) -> Result<(f64, f64)> {
+    let floats = tokens
+        .take(2)
+        .map(|t| t.parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+        .context("Invalid floating point number")?;
+
+    match floats[..] {
+        [u] => Ok((u, 0.0)),
+        [u, v] => Ok((u, v)),
+        _ => Err(anyhow!(
+            "Expected at least 1 texture coordinate, found none"
+        )),
+    }
+}
+
+/// Parses `v`, `v/vt`, `v//vn` or `v/vt/vn`, where indices start at 1 and negative ones are
+/// relative to the end of the list
+fn parse_face_vertex(token: &str, data: &ObjData) -> Result<FaceVertex> {
+    let mut indices = token.split('/');
+
+    let position = resolve_index(indices.next(), data.positions.len())?
+        .ok_or_else(|| anyhow!("Face vertex '{}' has no position index", token))?;
+    let texture_coords = resolve_index(indices.next(), data.texture_coords.len())?;
+    let normal = resolve_index(indices.next(), data.normals.len())?;
+
+    Ok(FaceVertex {
+        position,
+        texture_coords,
+        normal,
+    })
+}
+
+fn resolve_index(index: Option<&str>, len: usize) -> Result<Option<usize>> {
+    let index = match index {
+        Some(i) if !i.is_empty() => i.parse::<i64>().context("Invalid face index")?,
+        _ => return Ok(None),
+    };
+
+    let resolved = if index < 0 {
+        len as i64 + index
+    } else {
+        index - 1
+    };
+
+    if resolved < 0 || resolved >= len as i64 {
+        Err(anyhow!("Face index {} is out of range", index))
+    } else {
+        Ok(Some(resolved as usize))
+    }
+}
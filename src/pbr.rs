@@ -0,0 +1,55 @@
+use std::f64::consts::PI;
+
+use crate::shapes::{colors, Color};
+use crate::vec3::Vec3;
+
+/// Specular reflectance of dielectrics seen head on, which is about the same for most of them
+const DIELECTRIC_F0: f64 = 0.04;
+
+/// Diffuse and specular light reflected towards the viewer by a metallic-roughness surface lit
+/// from one direction, following the Cook-Torrance microfacet model with the GGX distribution.
+/// All the vectors point away from the surface and are normalized.
+///
+/// Both are multiplied by pi and by the cosine of the light's angle, so a white rough dielectric
+/// reflects about as much diffuse light as the Phong model with a K_d of 1.
+pub fn cook_torrance(
+    normal: Vec3,
+    view: Vec3,
+    light: Vec3,
+    base_color: Color,
+    metallic: f64,
+    roughness: f64,
+) -> (Color, Color) {
+    let n_dot_l = normal.dot(light);
+    let n_dot_v = normal.dot(view);
+    if n_dot_l <= 0.0 || n_dot_v <= 0.0 {
+        return (colors::BLACK, colors::BLACK);
+    }
+
+    let half = (view + light).normalize();
+    let n_dot_h = normal.dot(half).max(0.0);
+    let v_dot_h = view.dot(half).max(0.0);
+
+    // perfectly smooth surfaces would only reflect along a single direction, which point lights
+    // never hit
+    let alpha = roughness.max(0.02).powi(2);
+
+    // GGX normal distribution
+    let alpha2 = alpha * alpha;
+    let d = alpha2 / (PI * (n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0).powi(2));
+
+    // Smith's shadowing and masking with Schlick's approximation
+    let k = alpha / 2.0;
+    let g1 = |cos: f64| cos / (cos * (1.0 - k) + k);
+    let g = g1(n_dot_l) * g1(n_dot_v);
+
+    // Schlick's Fresnel, metals tint their reflections with their own color
+    let f0 = (1.0 - metallic) * (colors::WHITE * DIELECTRIC_F0) + metallic * base_color;
+    let fresnel = f0 + (1.0 - v_dot_h).powi(5) * (colors::WHITE - f0);
+
+    let specular = fresnel * (d * g / (4.0 * n_dot_l * n_dot_v));
+    // what isn't reflected is scattered under the surface, which metals don't do
+    let diffuse = (colors::WHITE - fresnel) * base_color * ((1.0 - metallic) / PI);
+
+    (diffuse * (PI * n_dot_l), specular * (PI * n_dot_l))
+}
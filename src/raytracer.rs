@@ -1,177 +1,1015 @@
 use anyhow::Result;
-use indicatif::ProgressIterator;
+use clap::ValueEnum;
+use indicatif::{HumanCount, ProgressBar, ProgressStyle};
 use itertools::multiunzip;
+use rand::Rng;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::ops::Range;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use crate::constants::{MAX_REFLECTIONS, SHADOWS, TOLERANCE, TOLERANCE_MUL};
-use crate::scene::{Light, Observer, Scene};
-use crate::screen::ScreenContextManager;
-use crate::shapes::{Color, Ray, Shape, ShapeCalculations};
-use crate::vec3::Vec3;
+use crate::constants::{
+    PROGRESSIVE_BLOCK, PROGRESS_TEMPLATE, TILE_SIZE, TOLERANCE, TOLERANCE_MUL, TRANSLUCENCY_WRAP,
+};
+use crate::denoise::{denoise, Guide};
+use crate::medium::Medium;
+use crate::pbr::cook_torrance;
+use crate::sampling::{samples_2d, seeded_rng, square_to_cosine_hemisphere, SampleRng};
+use crate::scene::{LightUnits, Observer, Scene};
+use crate::screen::{Region, Resolution, ScreenContextManager};
+use crate::shapes::{colors, Color, Ray, RayKind, Shading, Shape, ShapeCalculations};
+use crate::vec3::{Onb, Vec3};
 
+/// How the images of both eyes are put together by `raytrace_stereo`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StereoMode {
+    /// Red-cyan anaglyph, the red channel comes from the left eye and the others from the right
+    Anaglyph,
+    /// Left eye's image on the left half and right eye's on the right half
+    SideBySide,
+}
+
+/// Renders the region of the image and saves it to the path, the pixels outside of the region are
+/// left as they are in the screen. If `cancel` gets set the render stops early and the image isn't
+/// saved. A `progressive` render saves a coarse version of the image first and then refines it,
+/// saving it again after every pass.
+#[allow(clippy::too_many_arguments)]
 pub fn raytrace<P: AsRef<Path>>(
     path: P,
     observer: &Observer,
     scene: &Scene,
     screen: &mut ScreenContextManager,
+    region: Region,
+    progressive: bool,
+    progress: &Progress,
+    cancel: &AtomicBool,
 ) -> Result<()> {
-    let ratio_x = (observer.max_p.x - observer.min_p.x) / f64::from(screen.get_width());
-    let ratio_y = (observer.max_p.y - observer.min_p.y) / f64::from(screen.get_height());
+    let save = |screen: &ScreenContextManager| screen.save_img(&path);
+    let preview = progressive.then_some(&save as &Preview<'_>);
+    if trace_region(observer, scene, screen, region, progress, cancel, preview)? {
+        screen.save_img(&path)?;
+    }
 
-    let z_t = observer.plane_z;
+    Ok(())
+}
 
-    for i in (0..screen.get_width()).progress() {
-        for j in 0..screen.get_height() {
-            // Get ray
-            let x_t = (f64::from(i) + 0.5) * ratio_x + observer.min_p.x;
-            let y_t = (f64::from(j) + 0.5) * ratio_y + observer.min_p.y;
-            let target = Vec3::new(x_t, y_t, z_t);
-            let ray = Ray::from_2_points(observer.camera, target);
+/// Renders the image seen by each eye of the observer, of the screen's size, and saves both
+/// composed into one image saved like the screen would be. If `cancel` gets set the render stops
+/// early and nothing is saved.
+pub fn raytrace_stereo<P: AsRef<Path>>(
+    path: P,
+    observer: &Observer,
+    scene: &Scene,
+    screen: &ScreenContextManager,
+    mode: StereoMode,
+    progress: &Progress,
+    cancel: &AtomicBool,
+) -> Result<()> {
+    let size = Resolution {
+        width: screen.get_width(),
+        height: screen.get_height(),
+    };
+    let mut eyes = Vec::new();
+    for side in [-0.5, 0.5] {
+        let mut screen = screen.blank(size.width, size.height);
+        if !trace_region(
+            &observer.eye(side),
+            scene,
+            &mut screen,
+            Region::full(size),
+            progress,
+            cancel,
+            None,
+        )? {
+            return Ok(());
+        }
+        eyes.push(screen);
+    }
 
-            // Get color
-            let color = get_color_pixel(ray, scene, 1.0, MAX_REFLECTIONS);
+    let composed = match mode {
+        StereoMode::Anaglyph => ScreenContextManager::anaglyph(&eyes[0], &eyes[1]),
+        StereoMode::SideBySide => ScreenContextManager::side_by_side(&eyes[0], &eyes[1]),
+    };
+    composed.save_img(path)
+}
 
-            // Paint
-            screen.set_color(color.r as f32, color.g as f32, color.b as f32);
-            screen.plot_pixel(i, (screen.get_height() - 1) - j); // flip images so they're not upside down
+/// Extra image of what the camera sees through the center of each pixel, for compositing and for
+/// external denoisers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Aov {
+    /// Normal of the surface facing the camera in world space, each axis mapped from -1..1 to 0..1
+    Normal,
+    /// Color of the surface before it's lit
+    Albedo,
+    /// Color made from the id of each object, so that any object can be selected in post
+    Id,
+}
+
+impl Aov {
+    /// Added to the output image's name for the AOV's image
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Id => "id",
         }
     }
+}
 
-    screen.save_img(path)?;
+/// Renders the AOV over the region of the image and saves it to the path, like `raytrace` does
+/// with the image itself. Its values are written linearly, never encoded as sRGB.
+#[allow(clippy::too_many_arguments)]
+pub fn raytrace_aov<P: AsRef<Path>>(
+    path: P,
+    observer: &Observer,
+    scene: &Scene,
+    screen: &mut ScreenContextManager,
+    region: Region,
+    aov: Aov,
+    progress: &Progress,
+    cancel: &AtomicBool,
+) -> Result<()> {
+    let (width, height) = (screen.get_width(), screen.get_height());
+    let rows = (height - region.y - region.height)..(height - region.y);
+    let tiles = Tile::split(region.x..region.x + region.width, rows.clone(), TILE_SIZE);
+    let mut pixels = vec![Pixel::EMPTY; (region.width * region.height) as usize];
+
+    let finished = trace_tiles(&tiles, scene, 0, progress, cancel, |i, j, _| {
+        pixels[((i - region.x) * region.height + (j - rows.start)) as usize] =
+            Pixel::opaque(get_aov(
+                observer,
+                scene,
+                (f64::from(i) + 0.5) / f64::from(width),
+                (f64::from(j) + 0.5) / f64::from(height),
+                aov,
+            ));
+        Some(1)
+    });
+    if finished {
+        paint(screen, region, &pixels, 1);
+        screen.save_img(path)?;
+    }
 
     Ok(())
 }
 
-/// o1 = percentage of color that belongs to the current call (relevant for reflections)
-fn get_color_pixel(ray: Ray, scene: &Scene, total_o1: f64, reflections: u32) -> Color {
-    if let Some(inter) = get_first_intersection(&ray, scene) {
-        let normal = inter.object.get_normal_vec(inter.point);
-        // bump mapping experiments ( wip / trippy weird stuff, idk how to go about this)
-        //let normal = Vec3 {
-        //    x: tex.y % (normal.y),
-        //    y: tex.x % (normal.x),
-        //    z: normal.z % (normal.x),
-        //};
-        //let normal = (normal + inter.point).normalize().normalize();
+/// Shows the screen while it's being refined
+type Preview<'a> = dyn Fn(&ScreenContextManager) -> Result<()> + 'a;
+
+/// Paints the region of the screen, returning false if it was cancelled before finishing. With a
+/// `preview`, the region is first traced one pixel out of every few and refined pass after pass,
+/// the preview being shown the screen after each pass but the last.
+fn trace_region(
+    observer: &Observer,
+    scene: &Scene,
+    screen: &mut ScreenContextManager,
+    region: Region,
+    progress: &Progress,
+    cancel: &AtomicBool,
+    preview: Option<&Preview<'_>>,
+) -> Result<bool> {
+    let (width, height) = (screen.get_width(), screen.get_height());
+
+    // rows are counted from the bottom
+    let rows = (height - region.y - region.height)..(height - region.y);
+    let tiles = Tile::split(region.x..region.x + region.width, rows.clone(), TILE_SIZE);
+    // the region's pixels, column by column
+    let mut pixels = vec![Pixel::EMPTY; (region.width * region.height) as usize];
+    let index = |i: u32, j: u32| ((i - region.x) * region.height + (j - rows.start)) as usize;
+    let mut pass = 0;
+
+    // each pass traces the pixels at the corners of blocks of the given side, half the size of
+    // the previous pass' ones, which already traced the rest of the corners
+    let first_block = if preview.is_some() {
+        PROGRESSIVE_BLOCK
+    } else {
+        1
+    };
+    let blocks = std::iter::successors(Some(first_block), |block| (*block > 1).then(|| block / 2));
+    for block in blocks {
+        let is_corner = |i: u32, j: u32, block: u32| {
+            (i - region.x).is_multiple_of(block) && (j - rows.start).is_multiple_of(block)
+        };
+
+        let finished = trace_tiles(&tiles, scene, pass, progress, cancel, |i, j, rng| {
+            if !is_corner(i, j, block) || (block < first_block && is_corner(i, j, 2 * block)) {
+                return None;
+            }
+
+            let (pixel, rays) = trace_pixel(
+                observer,
+                scene,
+                (f64::from(i) + 0.5) / f64::from(width),
+                (f64::from(j) + 0.5) / f64::from(height),
+                rng,
+            );
+            pixels[index(i, j)] = pixel;
+            Some(rays)
+        });
+        if !finished {
+            return Ok(false);
+        }
+        pass += 1;
+
+        if let Some(preview) = preview {
+            if block > 1 {
+                paint(screen, region, &pixels, block);
+                preview(screen)?;
+            }
+        }
+    }
+
+    // pixels that stand out from their neighbors are likely on an edge, so only those are traced
+    // again through several points spread over them
+    if let Some(threshold) = scene.render.aa_threshold {
+        let first_pass = pixels.clone();
+        let contrast = |a: Pixel, b: Pixel| {
+            (a.color.r - b.color.r)
+                .abs()
+                .max((a.color.g - b.color.g).abs())
+                .max((a.color.b - b.color.b).abs())
+                .max((a.alpha - b.alpha).abs())
+        };
+
+        let finished = trace_tiles(&tiles, scene, pass, progress, cancel, |i, j, rng| {
+            let color = first_pass[index(i, j)];
+            let neighbors = [
+                (i > region.x).then(|| index(i - 1, j)),
+                (i + 1 < region.x + region.width).then(|| index(i + 1, j)),
+                (j > rows.start).then(|| index(i, j - 1)),
+                (j + 1 < rows.end).then(|| index(i, j + 1)),
+            ];
+            let is_edge = neighbors
+                .into_iter()
+                .flatten()
+                .any(|neighbor| contrast(color, first_pass[neighbor]) > threshold);
+            if !is_edge {
+                return Some(0);
+            }
+
+            let samples = samples_2d(scene.render.aa_samples, scene.render.sampler, rng);
+            let mut rays = 0;
+            pixels[index(i, j)] = Pixel::average(samples.into_iter().map(|(dx, dy)| {
+                let (pixel, sample_rays) = trace_pixel(
+                    observer,
+                    scene,
+                    (f64::from(i) + dx) / f64::from(width),
+                    (f64::from(j) + dy) / f64::from(height),
+                    rng,
+                );
+                rays += sample_rays;
+                pixel
+            }));
+            Some(rays)
+        });
+        if !finished {
+            return Ok(false);
+        }
+    }
+
+    if scene.render.denoise {
+        let guides = (region.x..region.x + region.width)
+            .flat_map(|i| rows.clone().map(move |j| (i, j)))
+            .map(|(i, j)| {
+                progress.advance(1, 0);
+                get_guide(
+                    observer,
+                    scene,
+                    (f64::from(i) + 0.5) / f64::from(width),
+                    (f64::from(j) + 0.5) / f64::from(height),
+                )
+            })
+            .collect::<Vec<Guide>>();
+        let colors = pixels
+            .iter()
+            .map(|pixel| pixel.color)
+            .collect::<Vec<Color>>();
+        let denoised = denoise(
+            &colors,
+            &guides,
+            region.height as usize,
+            scene.render.denoise_radius as usize,
+            || progress.advance(u64::from(region.height), 0),
+        );
+        for (pixel, color) in pixels.iter_mut().zip(denoised) {
+            pixel.color = color;
+        }
+    }
+
+    paint(screen, region, &pixels, 1);
+
+    Ok(true)
+}
+
+/// Rectangle of pixels traced as a unit, with its columns and its rows counted from the bottom
+#[derive(Debug, Clone)]
+struct Tile {
+    columns: Range<u32>,
+    rows: Range<u32>,
+}
+
+impl Tile {
+    /// Tiles of the given side covering the columns and rows, the ones at the edges may be smaller
+    fn split(columns: Range<u32>, rows: Range<u32>, side: u32) -> Vec<Tile> {
+        let starts = |range: &Range<u32>| range.clone().step_by(side as usize);
+        starts(&columns)
+            .flat_map(|i| {
+                let columns = i..(i + side).min(columns.end);
+                starts(&rows).map(move |j| Tile {
+                    columns: columns.clone(),
+                    rows: j..(j + side).min(rows.end),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Progress of everything traced for an image, shown as a bar with the share of the work done, the
+/// time elapsed and left, and the camera rays traced per second. The work is counted in pixels.
+pub struct Progress {
+    bar: ProgressBar,
+    rays: Cell<u64>,
+}
+
+impl Progress {
+    /// Progress over the given work, hidden if the scene is rendered quietly
+    pub fn new(work: u64, scene: &Scene) -> Progress {
+        let bar = if scene.quiet {
+            ProgressBar::hidden()
+        } else {
+            ProgressBar::new(work).with_style(
+                ProgressStyle::with_template(PROGRESS_TEMPLATE).expect("valid template"),
+            )
+        };
+        Progress {
+            bar,
+            rays: Cell::new(0),
+        }
+    }
+
+    /// Work of rendering the region of an image: every pixel is traced once over all the passes,
+    /// checked once more for antialiasing, and has its guide traced and is filtered if denoised
+    pub fn image_work(region: Region, scene: &Scene) -> u64 {
+        let steps = 1
+            + u64::from(scene.render.aa_threshold.is_some())
+            + if scene.render.denoise { 2 } else { 0 };
+        Progress::aov_work(region) * steps
+    }
+
+    /// Work of rendering an AOV over the region, a ray through each pixel
+    pub fn aov_work(region: Region) -> u64 {
+        u64::from(region.width) * u64::from(region.height)
+    }
+
+    fn advance(&self, work: u64, rays: u64) {
+        self.rays.set(self.rays.get() + rays);
+        self.bar.inc(work);
+        let rate = self.rays.get() as f64 / self.bar.elapsed().as_secs_f64().max(f64::EPSILON);
+        self.bar
+            .set_message(format!("{} rays/s", HumanCount(rate as u64)));
+    }
+
+    pub fn finish(&self) {
+        self.bar.finish();
+    }
+
+    /// Leaves the bar where it stopped, for renders that are cancelled
+    fn abandon(&self) {
+        self.bar.abandon();
+    }
+}
+
+/// Calls `trace` on every pixel of the tiles, taken one by one from a queue. Each tile has its own
+/// random number generator, seeded by the scene's seed, the pass and the tile, so that its samples
+/// don't depend on the order the tiles are traced in. `trace` returns the camera rays it traced,
+/// or none if the pixel isn't part of the pass, and the pixels that are advance the progress.
+/// Returns false if it was cancelled before finishing.
+fn trace_tiles(
+    tiles: &[Tile],
+    scene: &Scene,
+    pass: u64,
+    progress: &Progress,
+    cancel: &AtomicBool,
+    mut trace: impl FnMut(u32, u32, &mut SampleRng) -> Option<u64>,
+) -> bool {
+    let seed = scene.render.seed;
+    let mut queue: VecDeque<(usize, &Tile)> = tiles.iter().enumerate().collect();
+
+    while let Some((index, tile)) = queue.pop_front() {
+        if cancel.load(Ordering::Relaxed) {
+            progress.abandon();
+            return false;
+        }
+
+        // spread the seed's bits so that nearby seeds don't share most of their tiles' generators
+        let mut rng =
+            seeded_rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ ((pass << 32) | index as u64));
+        let (mut pixels, mut rays) = (0, 0);
+        for i in tile.columns.clone() {
+            for j in tile.rows.clone() {
+                if let Some(pixel_rays) = trace(i, j, &mut rng) {
+                    pixels += 1;
+                    rays += pixel_rays;
+                }
+            }
+        }
+        progress.advance(pixels, rays);
+    }
+
+    true
+}
+
+/// Color of a pixel along with its alpha, the share of the camera rays through it that hit an
+/// object. The alpha is only below 1 in transparent images, and the color is premultiplied by it.
+#[derive(Debug, Clone, Copy)]
+struct Pixel {
+    color: Color,
+    alpha: f64,
+}
+
+impl Pixel {
+    const EMPTY: Pixel = Pixel {
+        color: colors::BLACK,
+        alpha: 0.0,
+    };
+
+    fn opaque(color: Color) -> Pixel {
+        Pixel { color, alpha: 1.0 }
+    }
+
+    /// Average of the pixels, empty if there are none
+    fn average(pixels: impl Iterator<Item = Pixel>) -> Pixel {
+        let (mut color, mut alpha, mut n) = (colors::BLACK, 0.0, 0.0);
+        for pixel in pixels {
+            color = color + pixel.color;
+            alpha += pixel.alpha;
+            n += 1.0;
+        }
+        if n == 0.0 {
+            return Pixel::EMPTY;
+        }
+        Pixel {
+            color: color * (1.0 / n),
+            alpha: alpha / n,
+        }
+    }
+}
+
+/// Paints the region of the screen with its pixels, given column by column from the bottom. Each
+/// block of the given side is filled with its corner.
+fn paint(screen: &mut ScreenContextManager, region: Region, pixels: &[Pixel], block: u32) {
+    let height = screen.get_height();
+    let bottom = height - region.y - region.height;
+
+    for i in region.x..region.x + region.width {
+        for j in bottom..bottom + region.height {
+            let corner_i = i - (i - region.x) % block;
+            let corner_j = j - (j - bottom) % block;
+            let pixel =
+                pixels[((corner_i - region.x) * region.height + (corner_j - bottom)) as usize];
+            // images store their colors without the alpha multiplied in
+            let color = if pixel.alpha > 0.0 {
+                pixel.color * (1.0 / pixel.alpha)
+            } else {
+                colors::BLACK
+            };
+
+            screen.set_color(color.r, color.g, color.b, pixel.alpha);
+            screen.plot_pixel(i, (height - 1) - j); // flip images so they're not upside down
+        }
+    }
+}
+
+/// Pixel seen through the point of the screen at `x` and `y`, both between 0 and 1 with `y` going
+/// up, averaged over the rays going through the lens, along with how many rays those were. In
+/// transparent images the rays that don't hit anything are left transparent instead of showing
+/// the background.
+fn trace_pixel(
+    observer: &Observer,
+    scene: &Scene,
+    x: f64,
+    y: f64,
+    rng: &mut SampleRng,
+) -> (Pixel, u64) {
+    let rays = observer.lens_rays(x, y, scene.render.sampler, rng);
+    let count = rays.len() as u64;
+    if rays.is_empty() && !scene.render.transparent {
+        return (Pixel::opaque(colors::BLACK), count);
+    }
+
+    let pixels = rays
+        .into_iter()
+        .map(|ray| observer.clip(ray))
+        .map(|(ray, far)| {
+            let inter = get_first_intersection(&ray, far, RayKind::Camera, scene);
+            if inter.is_none() && scene.render.transparent {
+                return Pixel::EMPTY;
+            }
+
+            let color = get_seen_color(ray, inter, scene, 1.0, scene.render.max_reflections, rng);
+            Pixel::opaque(
+                scene
+                    .render
+                    .tone_mapping
+                    .apply(clamp_sample(color, scene) * scene.exposure),
+            )
+        });
+    (Pixel::average(pixels), count)
+}
+
+/// Surface seen through the center of the point of the screen at `x` and `y`, as in `trace_pixel`,
+/// along with its shading normal facing the camera and its distance from the camera
+fn get_primary_hit<'a>(
+    observer: &Observer,
+    scene: &'a Scene,
+    x: f64,
+    y: f64,
+) -> Option<(Intersection<'a>, Vec3, f64)> {
+    let (ray, far) = observer.clip(observer.ray(x, y)?);
+    let inter = get_first_intersection(&ray, far, RayKind::Camera, scene)?;
+
+    let mut normal = inter.object.get_normal_vec(inter.point);
+    if normal.dot(ray.dir) > 0.0 {
+        normal = -normal;
+    }
+    let normal = inter.object.get_shading_normal(inter.point, normal);
+    let depth = observer.near + (inter.point - ray.anchor).norm();
+    Some((inter, normal, depth))
+}
 
-        let backwards_vec = -1.0 * ray.dir;
+/// Guide of the denoiser at the point of the screen at `x` and `y`
+fn get_guide(observer: &Observer, scene: &Scene, x: f64, y: f64) -> Guide {
+    match get_primary_hit(observer, scene, x, y) {
+        Some((_, normal, depth)) => Guide { normal, depth },
+        None => Guide::BACKGROUND,
+    }
+}
+
+/// Value of the AOV at the point of the screen at `x` and `y`, black where nothing is hit
+fn get_aov(observer: &Observer, scene: &Scene, x: f64, y: f64, aov: Aov) -> Color {
+    let Some((inter, normal, _)) = get_primary_hit(observer, scene, x, y) else {
+        return colors::BLACK;
+    };
+    match aov {
+        Aov::Normal => Color {
+            r: (normal.x + 1.0) / 2.0,
+            g: (normal.y + 1.0) / 2.0,
+            b: (normal.z + 1.0) / 2.0,
+        },
+        Aov::Albedo => inter.object.get_color_at(inter.point).clamp(),
+        Aov::Id => {
+            // each byte of the id makes a channel, kept away from black so no object looks like
+            // the background
+            let [r, g, b, _] = inter.object.get_params().id.to_le_bytes();
+            let channel = |byte: u8| 0.2 + 0.8 * f64::from(byte) / 255.0;
+            Color {
+                r: channel(r),
+                g: channel(g),
+                b: channel(b),
+            }
+        }
+    }
+}
+
+/// o1 = percentage of color that belongs to the current call (relevant for reflections). Nothing
+/// farther than `far` along the ray is hit, nor objects hidden from its `kind` of rays.
+fn get_color_pixel(
+    ray: Ray,
+    far: f64,
+    kind: RayKind,
+    scene: &Scene,
+    total_o1: f64,
+    reflections: u32,
+    rng: &mut SampleRng,
+) -> Color {
+    let inter = get_first_intersection(&ray, far, kind, scene);
+    get_seen_color(ray, inter, scene, total_o1, reflections, rng)
+}
+
+/// Color seen along the ray, which hits `inter`, through the scene's medium if it has one
+fn get_seen_color(
+    ray: Ray,
+    inter: Option<Intersection>,
+    scene: &Scene,
+    total_o1: f64,
+    reflections: u32,
+    rng: &mut SampleRng,
+) -> Color {
+    let Some(medium) = &scene.medium else {
+        return get_surface_color(ray, inter, scene, total_o1, reflections, rng);
+    };
+
+    // the medium dims what's seen through it and adds the light it scatters along the way
+    let distance = inter
+        .as_ref()
+        .map_or(medium.distance, |inter| (inter.point - ray.anchor).norm());
+    let scattered = get_scattered_light(&ray, distance, medium, scene, rng);
+    medium.transmittance(distance)
+        * get_surface_color(ray, inter, scene, total_o1, reflections, rng)
+        + scattered
+}
+
+/// Color of the surface the ray hits, or of the background if it doesn't hit anything
+fn get_surface_color(
+    ray: Ray,
+    inter: Option<Intersection>,
+    scene: &Scene,
+    total_o1: f64,
+    reflections: u32,
+    rng: &mut SampleRng,
+) -> Color {
+    if let Some(inter) = inter {
+        let mut normal = inter.object.get_normal_vec(inter.point);
+        // seen from the inside (like a camera within a sphere) the surface faces the other way, and
+        // so does the back of double sided surfaces
+        let backwards = normal.dot(ray.dir) > 0.0;
+        let inside = inter.object.has_inside() && backwards;
+        if inside || (backwards && inter.object.double_sided()) {
+            normal = -normal;
+        }
+        let normal = inter.object.get_shading_normal(inter.point, normal);
+
+        let backwards_vec = -ray.dir;
+        let bias = get_bias(inter.object, scene);
 
         // Calculate stuff relating to each specific light that has to be reused, for optimization
         // purposes
         let (shadow_intersections, light_factors, l_vecs): (Vec<f64>, Vec<f64>, Vec<Vec3>) =
             multiunzip(scene.get_lights().iter().map(|light| {
+                if !light.lights_object(inter.object.get_params()) {
+                    return (1.0, 0.0, light.get_l_vec(inter.point));
+                }
+
+                // light from behind translucent objects goes through them, so they don't shadow
+                // themselves from it
+                let through = (inter.object.translucency() > 0.0
+                    && light.get_l_vec(inter.point).dot(normal) < 0.0)
+                    .then_some(inter.object);
+
                 (
-                    if SHADOWS {
-                        get_shadow_intersection(
-                            &Ray::from_2_points(inter.point, light.position).advance(TOLERANCE),
-                            scene,
-                            light,
-                        )
+                    if scene.render.shadows && light.cast_shadows {
+                        // averaged over the light's surface, which softens the shadows' edges
+                        let targets =
+                            light.get_shadow_targets(inter.point, scene.render.sampler, rng);
+                        let n = targets.len() as f64;
+                        targets
+                            .into_iter()
+                            .map(|target| {
+                                get_shadow_intersection(
+                                    &Ray::from_2_points(inter.point, target).advance(bias),
+                                    scene,
+                                    target,
+                                    through,
+                                    bias,
+                                )
+                            })
+                            .sum::<f64>()
+                            / n
                     } else {
-                        0.0
+                        1.0
                     },
-                    // F_att * Ip
-                    light.get_attenuation((light.position - inter.point).norm()) * light.intensity,
+                    // F_att * Ip, limited to the cone of spot lights, shaped by the light's profile
+                    // and dimmed by the medium on the way
+                    light.get_attenuation((light.position - inter.point).norm(), scene.light_units)
+                        * light.intensity
+                        * light.get_spot_factor(inter.point)
+                        * light.get_ies_factor(inter.point)
+                        * scene.transmittance((light.position - inter.point).norm()),
                     // L vectors
                     light.get_l_vec(inter.point),
                 )
             }));
 
-        let total_intensity = (scene
-            .get_lights()
-            .iter()
-            .enumerate()
-            .map(|(i, light)| {
-                let mut intensity: f64 =
-                    (l_vecs[i].dot(normal)).max(0.0) * light_factors[i] * inter.object.k_d();
+        let surface_color = match inter.object.shading() {
+            Shading::Phong => {
+                let total_intensity = scene.light_units.limit_color(
+                    scene
+                        .get_lights()
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, light)| light.diffuse)
+                        .map(|(i, light)| {
+                            let mut intensity: f64 = (l_vecs[i].dot(normal).max(0.0)
+                                + get_translucent_light(&inter, l_vecs[i], normal))
+                                * light_factors[i]
+                                * inter.object.k_d();
 
-                if SHADOWS {
-                    intensity *= shadow_intersections[i]
-                }
+                            if scene.render.shadows {
+                                intensity *= shadow_intersections[i]
+                            }
 
-                light.color * intensity
-            })
-            .sum::<Color>()
-            + (scene.ambient_color * scene.ambient * inter.object.k_a()))
-        .min(1.0);
+                            light.color * intensity
+                        })
+                        .sum::<Color>()
+                        + (scene.ambient_color * scene.ambient * inter.object.k_a()),
+                );
 
-        let rgb_d = total_intensity * inter.object.get_color_at(inter.point);
+                let rgb_d = total_intensity * inter.object.get_color_at(inter.point);
 
-        let total_speculation = (scene
-            .get_lights()
-            .iter()
-            .enumerate()
-            .map(|(i, light)| {
-                let reflection_vec: Vec3 = 2.0 * normal * (normal.dot(l_vecs[i])) - l_vecs[i];
-
-                let mut specular: f64 = (reflection_vec.dot(backwards_vec))
-                    .max(0.0)
-                    .powf(inter.object.k_n())
-                    * light_factors[i]
-                    * inter.object.k_s();
-
-                if SHADOWS {
-                    specular *= shadow_intersections[i];
-                }
+                let total_speculation = scene.light_units.limit_color(
+                    scene
+                        .get_lights()
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, light)| light.specular)
+                        .map(|(i, light)| {
+                            let reflection_vec: Vec3 =
+                                2.0 * normal * (normal.dot(l_vecs[i])) - l_vecs[i];
 
-                (light.color - rgb_d) * specular
-            })
-            .sum::<Color>())
-        .min(1.0);
+                            let mut specular: f64 = (reflection_vec.dot(backwards_vec))
+                                .max(0.0)
+                                .powf(inter.object.k_n())
+                                * light_factors[i]
+                                * inter.object.k_s();
 
-        let object_color = rgb_d + total_speculation;
+                            if scene.render.shadows {
+                                specular *= shadow_intersections[i];
+                            }
 
-        let o1 = inter.object.o1();
-        if o1 < 1.0 && total_o1 > TOLERANCE * TOLERANCE_MUL {
-            let transparency_c = if inter.object.transparency() > TOLERANCE {
-                let refraction_dir = get_refractive_dir(&ray);
+                            // normalized highlights only fill what the diffuse light left up to 1
+                            match scene.light_units {
+                                LightUnits::Normalized => (light.color - rgb_d) * specular,
+                                LightUnits::Physical => light.color * specular,
+                            }
+                        })
+                        .sum::<Color>(),
+                );
 
-                // We advance the anchor a bit (a TOLERANCE amount) to avoid the sphere getting stuck
-                let transparency_vec = Ray {
-                    anchor: inter.point,
-                    dir: refraction_dir,
-                }
-                .advance(TOLERANCE);
+                rgb_d + total_speculation
+            }
+            Shading::Pbr {
+                metallic,
+                roughness,
+            } => {
+                let base_color = inter.object.get_color_at(inter.point);
+                scene.light_units.limit_color(
+                    scene
+                        .get_lights()
+                        .iter()
+                        .enumerate()
+                        .map(|(i, light)| {
+                            let (diffuse, specular) = cook_torrance(
+                                normal,
+                                backwards_vec,
+                                l_vecs[i],
+                                base_color,
+                                metallic,
+                                roughness,
+                            );
+                            let mut intensity = light_factors[i];
+                            if scene.render.shadows {
+                                intensity *= shadow_intersections[i];
+                            }
 
-                get_color_pixel(
-                    transparency_vec,
-                    scene,
-                    total_o1 * inter.object.transparency(),
-                    reflections,
+                            let diffuse = diffuse
+                                + base_color
+                                    * ((1.0 - metallic)
+                                        * get_translucent_light(&inter, l_vecs[i], normal));
+                            let reflected = match (light.diffuse, light.specular) {
+                                (true, true) => diffuse + specular,
+                                (true, false) => diffuse,
+                                (false, true) => specular,
+                                (false, false) => colors::BLACK,
+                            };
+                            reflected * light.color * intensity
+                        })
+                        .sum::<Color>()
+                        + scene.ambient_color * base_color * (scene.ambient * inter.object.k_a()),
                 )
+            }
+        };
+
+        let object_color = surface_color
+            + inter.object.emission()
+            + get_gathered_light(&inter, normal, scene, rng);
+
+        let o1 = inter.object.o1();
+        let Some(roulette_weight) = continue_ray(total_o1, scene, rng) else {
+            // with russian roulette the reflected and transmitted light is dropped, which the
+            // rays that go on make up for
+            return match scene.render.roulette_threshold {
+                Some(_) => o1 * object_color,
+                None => object_color,
+            };
+        };
+        if o1 < 1.0 {
+            // only shapes with an inside hold a medium that bends the rays, the outside is always
+            // air
+            let eta = match (inter.object.has_inside(), inside) {
+                (false, _) => 1.0,
+                (true, false) => 1.0 / inter.object.ior(),
+                (true, true) => inter.object.ior(),
+            };
+
+            // with fresnel the share of the transparency that's reflected grows towards grazing
+            // angles, thin surfaces (like a plane of water) reflect as if they were entered
+            let (reflection, transparency) = if inter.object.fresnel() {
+                let surface_eta = if inter.object.has_inside() {
+                    eta
+                } else {
+                    1.0 / inter.object.ior()
+                };
+                let reflectance = schlick(-ray.dir.normalize().dot(normal), surface_eta);
+                (
+                    inter.object.reflection() + inter.object.transparency() * reflectance,
+                    inter.object.transparency() * (1.0 - reflectance),
+                )
+            } else {
+                (inter.object.reflection(), inter.object.transparency())
+            };
+
+            let transparency_c = if transparency > TOLERANCE {
+                // light that can't leave the medium is totally reflected inside it, which takes
+                // one of the ray's reflections so it can't bounce forever
+                let (refraction_dir, reflections) = match get_refractive_dir(&ray, normal, eta) {
+                    Some(dir) => (Some(dir), reflections),
+                    None if reflections > 0 => (
+                        Some(ray.dir - 2.0 * (ray.dir.dot(normal)) * normal),
+                        reflections - 1,
+                    ),
+                    None => (None, 0),
+                };
+
+                match refraction_dir {
+                    Some(refraction_dir) => {
+                        // We advance the anchor a bit (the object's bias) to avoid the sphere
+                        // getting stuck
+                        let transparency_vec = Ray {
+                            anchor: inter.point,
+                            dir: refraction_dir,
+                        }
+                        .advance(bias);
+
+                        get_color_pixel(
+                            transparency_vec,
+                            f64::INFINITY,
+                            RayKind::Reflection,
+                            scene,
+                            total_o1 * transparency,
+                            reflections,
+                            rng,
+                        )
+                    }
+                    None => object_color,
+                }
             } else {
                 object_color
             };
 
-            let reflection_c = if inter.object.reflection() > TOLERANCE && reflections > 0 {
+            let reflection_c = if reflection > TOLERANCE && reflections > 0 {
                 let reflection_dir = ray.dir - 2.0 * (ray.dir.dot(normal)) * normal;
 
-                // We advance the anchor a bit (a TOLERANCE amount) to avoid the sphere getting stuck
+                // We advance the anchor a bit (the object's bias) to avoid the sphere getting stuck
                 // reflecting itself due to float rounding error
                 let reflection_vec = Ray {
                     anchor: inter.point,
                     dir: reflection_dir,
                 }
-                .advance(TOLERANCE);
+                .advance(bias);
 
                 get_color_pixel(
                     reflection_vec,
+                    f64::INFINITY,
+                    RayKind::Reflection,
                     scene,
-                    total_o1 * inter.object.reflection(),
+                    total_o1 * reflection,
                     reflections - 1,
+                    rng,
                 )
             } else {
                 object_color
             };
             o1 * (object_color)
-                + inter.object.reflection() * reflection_c
-                + inter.object.transparency() * transparency_c
+                + roulette_weight
+                    * (reflection * reflection_c
+                        + transparency * (transparency_c * inter.object.transparency_color()))
         } else {
             object_color
         }
     } else {
-        scene.bg_color
+        scene.background(ray.dir)
+    }
+}
+
+/// Whether the reflected and transmitted rays of a ray carrying `total_o1` of the pixel's color are
+/// traced, and what their color is multiplied by if they are. Without russian roulette they're cut
+/// off once they carry too little. With it, rays carrying less than the threshold only go on with
+/// a probability proportional to what they carry and are weighted up to make up for the ones that
+/// don't, so on average the image is the same.
+fn continue_ray(total_o1: f64, scene: &Scene, rng: &mut SampleRng) -> Option<f64> {
+    match scene.render.roulette_threshold {
+        None => (total_o1 > TOLERANCE * TOLERANCE_MUL).then_some(1.0),
+        Some(threshold) if total_o1 < threshold => {
+            let probability = total_o1 / threshold;
+            (rng.gen::<f64>() < probability).then(|| 1.0 / probability)
+        }
+        Some(_) => Some(1.0),
+    }
+}
+
+/// Light of the lights scattered towards the ray's anchor by the medium over the first `distance`
+/// of the ray, ray marched in even steps from a random offset so that it's grainy instead of banded
+fn get_scattered_light(
+    ray: &Ray,
+    distance: f64,
+    medium: &Medium,
+    scene: &Scene,
+    rng: &mut SampleRng,
+) -> Color {
+    let dir = ray.dir.normalize();
+    let step = distance / f64::from(medium.steps);
+    let offset = rng.gen::<f64>();
+
+    let scattered = (0..medium.steps)
+        .map(|k| {
+            let t = (f64::from(k) + offset) * step;
+            let point = ray.anchor + dir * t;
+            let lit = scene
+                .get_lights()
+                .iter()
+                .map(|light| {
+                    let light_distance = (light.position - point).norm();
+                    let mut intensity = light.get_attenuation(light_distance, scene.light_units)
+                        * light.intensity
+                        * light.get_spot_factor(point)
+                        * light.get_ies_factor(point)
+                        * medium.transmittance(light_distance);
+                    if intensity > 0.0 && scene.render.shadows && light.cast_shadows {
+                        intensity *= get_shadow_intersection(
+                            &Ray::from_2_points(point, light.position),
+                            scene,
+                            light.position,
+                            None,
+                            scene.render.bias,
+                        );
+                    }
+                    light.color * intensity
+                })
+                .sum::<Color>();
+            lit * medium.transmittance(t)
+        })
+        .sum::<Color>();
+
+    scattered * medium.color * (medium.density * step)
+}
+
+/// The sample's color scaled down so that no channel goes over the scene's firefly clamp, if it has
+/// one
+fn clamp_sample(color: Color, scene: &Scene) -> Color {
+    let peak = color.r.max(color.g).max(color.b);
+    match scene.render.firefly_clamp {
+        Some(limit) if peak > limit => color * (limit / peak),
+        _ => color,
+    }
+}
+
+/// Fraction of the light coming from behind the surface that makes it through translucent objects,
+/// wrapped a bit around their sides
+fn get_translucent_light(inter: &Intersection, l_vec: Vec3, normal: Vec3) -> f64 {
+    let translucency = inter.object.translucency();
+    if translucency <= 0.0 {
+        return 0.0;
+    }
+
+    translucency * ((TRANSLUCENCY_WRAP - l_vec.dot(normal)) / (1.0 + TRANSLUCENCY_WRAP)).max(0.0)
+}
+
+/// Diffuse light the point gets from emissive objects and from the background (when `ibl` is set),
+/// gathered by sending rays all around it
+fn get_gathered_light(
+    inter: &Intersection,
+    normal: Vec3,
+    scene: &Scene,
+    rng: &mut SampleRng,
+) -> Color {
+    let samples = scene.render.emission_samples;
+    if (!scene.emissive && scene.ibl <= 0.0) || samples == 0 || inter.object.k_d() <= 0.0 {
+        return colors::BLACK;
     }
+
+    // cosine weighted directions, so each sample's emission just has to be averaged
+    let frame = Onb::from_normal(normal);
+    let gathered = samples_2d(samples, scene.render.sampler, rng)
+        .into_iter()
+        .map(|(u, v)| {
+            let ray = Ray {
+                anchor: inter.point,
+                dir: frame.local_to_world(square_to_cosine_hemisphere(u, v)),
+            }
+            .advance(get_bias(inter.object, scene));
+            clamp_sample(
+                match get_first_intersection(&ray, f64::INFINITY, RayKind::Reflection, scene) {
+                    Some(hit) => hit.object.emission(),
+                    None => scene.background(ray.dir) * scene.ibl,
+                },
+                scene,
+            )
+        })
+        .sum::<Color>()
+        * (1.0 / f64::from(samples));
+
+    gathered * inter.object.k_d() * inter.object.get_color_at(inter.point)
 }
 
 struct Intersection<'a> {
@@ -180,61 +1018,99 @@ struct Intersection<'a> {
     point: Vec3,
 }
 
-fn get_first_intersection<'a>(ray: &Ray, scene: &'a Scene) -> Option<Intersection<'a>> {
-    // Init tmin and the intersected shape
-    let mut tmin = f64::INFINITY;
-    let mut intersection: Option<Intersection> = None;
-
-    for object in scene.get_objects() {
-        if let Some(t) = object.get_intersection(ray) {
-            if t < tmin {
-                tmin = t;
-                intersection = Some(Intersection {
-                    //t: tmin,
-                    object,
-                    point: ray.point_at_t(tmin),
-                });
-            }
-        }
-    }
+fn get_first_intersection<'a>(
+    ray: &Ray,
+    t_max: f64,
+    kind: RayKind,
+    scene: &'a Scene,
+) -> Option<Intersection<'a>> {
+    scene
+        .closest_hit(ray, t_max, |object, _| scene.hits(object, kind))
+        .map(|(t, object)| Intersection {
+            //t: tmin,
+            object,
+            point: ray.point_at_t(t),
+        })
+}
 
-    intersection
+/// Distance secondary rays start away from the object's surface: its own bias or else the scene's
+fn get_bias(object: &Shape, scene: &Scene) -> f64 {
+    object.bias().unwrap_or(scene.render.bias)
 }
 
 /// Returns the total transparency of the intersection, if there's no intersection then it reports
-/// 1.0 (total transparency)
-fn get_shadow_intersection<'a>(ray: &Ray, scene: &'a Scene, light: &Light) -> f64 {
-    let t_light: f64 = (light.position - ray.anchor).norm();
-
-    for object in scene.get_objects() {
-        if let Some(t) = object.get_intersection(ray) {
-            if t < t_light && t > TOLERANCE {
-                // revisamos t > TOLERANCE para que el objeto no se auto-detecte como intersección
-                let total_transparency = if object.transparency() > 0.0 {
-                    object.transparency()
-                        * get_shadow_intersection(
-                            &Ray {
-                                anchor: ray.point_at_t(t),
-                                dir: get_refractive_dir(ray),
-                            }
-                            .advance(TOLERANCE),
-                            scene,
-                            light,
-                        )
-                } else {
-                    0.0
-                };
-                return total_transparency;
-            }
+/// 1.0 (total transparency). The `ignored` object doesn't block the light, nor does anything closer
+/// than `bias` to the ray's anchor.
+fn get_shadow_intersection(
+    ray: &Ray,
+    scene: &Scene,
+    target: Vec3,
+    ignored: Option<&Shape>,
+    bias: f64,
+) -> f64 {
+    let t_light: f64 = (target - ray.anchor).norm();
+
+    // revisamos t > bias para que el objeto no se auto-detecte como intersección
+    let blocker = scene.closest_hit(ray, t_light, |object, t| {
+        t > bias
+            && scene.hits(object, RayKind::Shadow)
+            && !ignored.is_some_and(|ignored| std::ptr::eq(ignored, object))
+    });
+
+    match blocker {
+        Some((t, object)) if object.transparency() > 0.0 => {
+            let bias = get_bias(object, scene);
+            object.transparency()
+                * get_shadow_intersection(
+                    &Ray {
+                        anchor: ray.point_at_t(t),
+                        dir: ray.dir,
+                    }
+                    .advance(bias),
+                    scene,
+                    target,
+                    ignored,
+                    bias,
+                )
         }
+        Some(_) => 0.0,
+        None => 1.0,
     }
+}
+
+/// Fraction of the light reflected by the surface between two media, by Schlick's approximation of
+/// the Fresnel equations. `cos_i` is the cosine of the angle between the ray and the normal and
+/// `eta` the ratio of indices of refraction as in `get_refractive_dir`.
+fn schlick(cos_i: f64, eta: f64) -> f64 {
+    // leaving a denser medium the angle on the other side is the one that counts
+    let cos = if eta > 1.0 {
+        let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+        if sin2_t >= 1.0 {
+            return 1.0;
+        }
+        (1.0 - sin2_t).sqrt()
+    } else {
+        cos_i
+    };
 
-    1.0
+    let r0 = ((1.0 - eta) / (1.0 + eta)).powi(2);
+    r0 + (1.0 - r0) * (1.0 - cos.clamp(0.0, 1.0)).powi(5)
 }
 
-fn get_refractive_dir(ray: &Ray) -> Vec3 {
-    // Since we're doing non-refractive transparency this doesn't change anything,  keeping it here
-    // to add refraction in the future
+/// Direction the ray goes on in after crossing the surface with the given normal (facing the
+/// ray), following Snell's law with `eta` being the index of refraction of the medium the ray
+/// leaves over that of the one it enters. `None` if the light is totally reflected instead.
+fn get_refractive_dir(ray: &Ray, normal: Vec3, eta: f64) -> Option<Vec3> {
+    if eta == 1.0 {
+        return Some(ray.dir);
+    }
 
-    ray.dir
+    let dir = ray.dir.normalize();
+    let cos_i = -dir.dot(normal);
+    let k = 1.0 - eta * eta * (1.0 - cos_i * cos_i);
+    if k < 0.0 {
+        None
+    } else {
+        Some(eta * dir + (eta * cos_i - k.sqrt()) * normal)
+    }
 }
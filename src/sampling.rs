@@ -0,0 +1,216 @@
+use clap::ValueEnum;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use std::f64::consts::PI;
+
+use crate::vec3::{Onb, Vec3};
+
+/// RNG used for every stochastic effect, seedable so renders can be reproduced.
+pub type SampleRng = SmallRng;
+
+pub fn seeded_rng(seed: u64) -> SampleRng {
+    SampleRng::seed_from_u64(seed)
+}
+
+/// How a batch of 2D samples in the unit square is distributed. Every effect that needs several
+/// samples (anti-aliasing, depth of field, soft shadows and the light gathered from emissive
+/// objects) takes them in batches from `samples_2d`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ValueEnum)]
+pub enum Sampler {
+    /// Independent uniform samples
+    Random,
+    /// One jittered sample per cell of a sqrt(n) x sqrt(n) grid, remaining samples are uniform
+    Jittered,
+    /// Low-discrepancy points of the Halton sequence in bases 2 and 3, shifted by a random offset
+    /// so that each batch is different
+    Halton,
+}
+
+/// Generates `n` samples in [0, 1)^2
+pub fn samples_2d(n: u32, sampler: Sampler, rng: &mut SampleRng) -> Vec<(f64, f64)> {
+    let mut samples = Vec::with_capacity(n as usize);
+
+    match sampler {
+        Sampler::Random => (),
+        Sampler::Jittered => {
+            let k = (n as f64).sqrt().floor() as u32;
+            let cell = 1.0 / k as f64;
+            for i in 0..k {
+                for j in 0..k {
+                    samples.push((
+                        (i as f64 + rng.gen::<f64>()) * cell,
+                        (j as f64 + rng.gen::<f64>()) * cell,
+                    ));
+                }
+            }
+        }
+        Sampler::Halton => {
+            let (offset_x, offset_y): (f64, f64) = (rng.gen(), rng.gen());
+            for i in 1..=n {
+                samples.push((
+                    (radical_inverse(i, 2) + offset_x).fract(),
+                    (radical_inverse(i, 3) + offset_y).fract(),
+                ));
+            }
+        }
+    }
+
+    while samples.len() < n as usize {
+        samples.push((rng.gen(), rng.gen()));
+    }
+
+    samples
+}
+
+/// The digits of `i` in the base mirrored around the decimal point, like 0.011 for 6 = 110 in base 2
+fn radical_inverse(mut i: u32, base: u32) -> f64 {
+    let mut inverse = 0.0;
+    let mut digit_value = 1.0 / f64::from(base);
+    while i > 0 {
+        inverse += f64::from(i % base) * digit_value;
+        i /= base;
+        digit_value /= f64::from(base);
+    }
+    inverse
+}
+
+/// Maps a point in the unit square to the unit disc, returned as (x, y)
+/// Uses Shirley's concentric mapping so stratification is preserved.
+pub fn square_to_disc(u: f64, v: f64) -> (f64, f64) {
+    let a = 2.0 * u - 1.0;
+    let b = 2.0 * v - 1.0;
+
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, (PI / 4.0) * (b / a))
+    } else {
+        (b, (PI / 2.0) - (PI / 4.0) * (a / b))
+    };
+
+    (r * theta.cos(), r * theta.sin())
+}
+
+/// Maps a point in the unit square to a uniformly distributed direction on the unit sphere
+#[allow(dead_code)]
+pub fn square_to_sphere(u: f64, v: f64) -> Vec3 {
+    let z = 1.0 - 2.0 * u;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Maps a point in the unit square to a uniformly distributed direction on the hemisphere around +z
+#[allow(dead_code)]
+pub fn square_to_hemisphere(u: f64, v: f64) -> Vec3 {
+    let z = u;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Maps a point in the unit square to a cosine-weighted direction on the hemisphere around +z
+pub fn square_to_cosine_hemisphere(u: f64, v: f64) -> Vec3 {
+    let (x, y) = square_to_disc(u, v);
+    let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+
+    Vec3::new(x, y, z)
+}
+
+#[allow(dead_code)]
+pub fn random_in_disc(rng: &mut SampleRng) -> (f64, f64) {
+    square_to_disc(rng.gen(), rng.gen())
+}
+
+#[allow(dead_code)]
+pub fn random_on_sphere(rng: &mut SampleRng) -> Vec3 {
+    square_to_sphere(rng.gen(), rng.gen())
+}
+
+/// Uniform direction in the hemisphere around `normal`
+#[allow(dead_code)]
+pub fn random_in_hemisphere(normal: Vec3, rng: &mut SampleRng) -> Vec3 {
+    Onb::from_normal(normal).local_to_world(square_to_hemisphere(rng.gen(), rng.gen()))
+}
+
+/// Cosine-weighted direction in the hemisphere around `normal`
+#[allow(dead_code)]
+pub fn random_cosine_direction(normal: Vec3, rng: &mut SampleRng) -> Vec3 {
+    Onb::from_normal(normal).local_to_world(square_to_cosine_hemisphere(rng.gen(), rng.gen()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::TOLERANCE;
+
+    fn assert_unit(v: Vec3) {
+        assert!(
+            (v.length_squared() - 1.0).abs() < TOLERANCE,
+            "{:?} isn't a unit vector",
+            v
+        );
+    }
+
+    #[test]
+    fn sphere_samples_are_unit_vectors() {
+        let mut rng = seeded_rng(0);
+        for _ in 0..1000 {
+            assert_unit(random_on_sphere(&mut rng));
+        }
+    }
+
+    #[test]
+    fn hemisphere_samples_are_above_the_normal() {
+        let mut rng = seeded_rng(1);
+        let normal = Vec3::new(1.0, -2.0, 0.5).normalize();
+        for _ in 0..1000 {
+            for direction in [
+                random_in_hemisphere(normal, &mut rng),
+                random_cosine_direction(normal, &mut rng),
+            ] {
+                assert_unit(direction);
+                assert!(direction.dot(normal) >= -TOLERANCE);
+            }
+        }
+    }
+
+    #[test]
+    fn square_to_hemisphere_points_towards_z() {
+        for (u, v) in [(0.0, 0.0), (0.5, 0.25), (1.0, 1.0), (0.1, 0.9)] {
+            for direction in [
+                square_to_hemisphere(u, v),
+                square_to_cosine_hemisphere(u, v),
+            ] {
+                assert_unit(direction);
+                assert!(direction.z >= 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn disc_samples_are_inside_the_unit_disc() {
+        let mut rng = seeded_rng(2);
+        for _ in 0..1000 {
+            let (x, y) = random_in_disc(&mut rng);
+            assert!(x * x + y * y <= 1.0 + TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn samples_are_in_the_unit_square() {
+        let mut rng = seeded_rng(3);
+        for sampler in [Sampler::Random, Sampler::Jittered, Sampler::Halton] {
+            let samples = samples_2d(10, sampler, &mut rng);
+            assert_eq!(samples.len(), 10);
+            assert!(samples
+                .iter()
+                .all(|&(x, y)| (0.0..1.0).contains(&x) && (0.0..1.0).contains(&y)));
+        }
+    }
+}
@@ -1,42 +1,312 @@
 use anyhow::{anyhow, Context, Error, Result};
+use clap::ValueEnum;
 use configparser::ini::Ini;
-use std::path::Path;
-
-use crate::constants::{DEFAULT_BG_COLOR, DEFAULT_HARDNESS, DEFAULT_LIGHT_COLOR};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::f64::consts::PI;
+use std::fmt;
+use std::fs;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::bezier::BezierPatch;
+use crate::bvh::Bvh;
+use crate::constants::{
+    AA_SAMPLES, DEFAULT_BG_COLOR, DEFAULT_FISHEYE_FOV, DEFAULT_FOV, DEFAULT_GRADIENT_LENGTH,
+    DEFAULT_HARDNESS, DEFAULT_LAYER, DEFAULT_LENS_SAMPLES, DEFAULT_LIGHT_COLOR, DEFAULT_MAP_SIZE,
+    DEFAULT_PATTERN_SCALE, DEFAULT_SHADOW_SAMPLES, DEFAULT_SPOT_ANGLE, DEFAULT_SUN_SIZE,
+    DENOISE_RADIUS, EMISSION_SAMPLES, EYE_SEPARATION_RATIO, MAX_REFLECTIONS, MEDIUM_DISTANCE,
+    MEDIUM_STEPS, PREVIEW_MEDIUM_STEPS, RAY_BIAS, SHADOWS, SRGB,
+};
+use crate::environment::EnvironmentMap;
+use crate::expr::evaluate;
+use crate::ies::IesProfile;
+use crate::medium::Medium;
+use crate::noise::{Pattern, PatternKind, Perlin};
+use crate::obj::read_obj;
+use crate::sampling::{samples_2d, square_to_disc, SampleRng, Sampler};
 use crate::shapes::{
-    Color, Cone, Cylinder, Disc, ObjectParameters, Plane, Shape, Sphere, Triangle,
+    colors, Bump, BumpKind, Color, Cone, Csg, CsgOperation, Cylinder, Disc, Ellipsoid, Heightfield,
+    Instance, Mapping, Mesh, Metaballs, ObjectParameters, Plane, Polygon, Ray, RayKind, Ring, Sdf,
+    SdfPrimitive, Shading, Shape, ShapeCalculations, Sphere, Torus, Triangle, UvTransform,
+    Visibility,
 };
-use crate::vec3::Vec3;
+use crate::sky::Sky;
+use crate::texture::{Gradient, GradientKind, ImageTexture, Wrap};
+use crate::transform::Matrix4;
+use crate::vec3::{Onb, Vec3};
 
+#[derive(Serialize)]
 pub struct Scene {
     objects: Vec<Shape>,
+    /// Built over the objects once they're read
+    #[serde(skip)]
+    bvh: Bvh,
     lights: Vec<Light>,
     pub ambient: f64,
     pub bg_color: Color,
+    /// Seen instead of `bg_color` when set
+    pub bg_image: Option<EnvironmentMap>,
+    /// Seen instead of `bg_color` when set, from the `[sky]` section
+    pub sky: Option<Sky>,
+    /// Fog filling the space between the objects, from the `[medium]` section
+    pub medium: Option<Medium>,
     pub ambient_color: Color,
+    /// Strength of the light the background casts on the objects (image-based lighting)
+    pub ibl: f64,
+    /// Multiplies every pixel's color before it's written to the image
+    pub exposure: f64,
+    pub light_units: LightUnits,
+    pub render: RenderSettings,
+    /// Whether any object has an emission, which then lights the others
+    pub emissive: bool,
+    /// Layers whose objects are rendered, all of them if none were selected
+    #[serde(skip)]
+    selected_layers: Option<(Vec<String>, OtherLayers)>,
+    /// Whether the progress of the render is kept from being shown
+    #[serde(skip)]
+    pub quiet: bool,
 }
 
-impl Scene {
-    pub fn get_objects(&self) -> &Vec<Shape> {
-        &self.objects
+/// What the objects of the layers that aren't selected for a render become
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OtherLayers {
+    /// Nothing sees them
+    Hidden,
+    /// They still cast shadows on the selected layers, but can't be seen
+    Shadows,
+}
+
+/// Settings of the raytracer itself, read from the optional `[render]` section
+#[derive(Debug, Clone, Serialize)]
+pub struct RenderSettings {
+    pub max_reflections: u32,
+    pub shadows: bool,
+    /// Distance secondary rays start away from the surface they leave
+    pub bias: f64,
+    /// Rays sent from every point to gather the light of emissive objects and of the background
+    pub emission_samples: u32,
+    /// Largest difference in any channel between neighboring pixels before they're anti-aliased,
+    /// no anti-aliasing is done if it isn't set
+    pub aa_threshold: Option<f64>,
+    /// Rays traced through each anti-aliased pixel
+    pub aa_samples: u32,
+    /// How the samples of every effect that takes several are spread
+    pub sampler: Sampler,
+    /// Share of the pixel's color below which reflected and transmitted rays are terminated by
+    /// russian roulette, instead of being cut off once they carry almost nothing
+    pub roulette_threshold: Option<f64>,
+    /// Whether the colors written to the image are encoded as sRGB instead of left linear
+    pub srgb: bool,
+    /// Whether the image has an alpha channel, transparent where the camera's rays don't hit
+    /// anything instead of showing the background
+    pub transparent: bool,
+    /// How the colors seen through the pixels are brought into the range the image can show
+    pub tone_mapping: ToneMapping,
+    /// Brightest any single sample of a sampled effect may be, which keeps rare very bright
+    /// samples from speckling the image with fireflies
+    pub firefly_clamp: Option<f64>,
+    /// Whether the image is smoothed by the denoiser once it's traced
+    pub denoise: bool,
+    /// Radius in pixels of the neighborhood each pixel is averaged over by the denoiser
+    pub denoise_radius: u32,
+    /// Seeds the random numbers of every sampled effect, the same seed always gives the same image
+    pub seed: u64,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            max_reflections: MAX_REFLECTIONS,
+            shadows: SHADOWS,
+            bias: RAY_BIAS,
+            emission_samples: EMISSION_SAMPLES,
+            aa_threshold: None,
+            aa_samples: AA_SAMPLES,
+            sampler: Sampler::Jittered,
+            roulette_threshold: None,
+            srgb: SRGB,
+            transparent: false,
+            tone_mapping: ToneMapping::Clamp,
+            firefly_clamp: None,
+            denoise: false,
+            denoise_radius: DENOISE_RADIUS,
+            seed: 0,
+        }
     }
+}
+
+impl Scene {
     pub fn get_lights(&self) -> &Vec<Light> {
         &self.lights
     }
 
-    pub fn read_config<P: AsRef<Path>>(path: P) -> Result<Scene> {
-        let mut config = Ini::new();
+    /// Closest object hit by the ray before `t_max`, along with its distance, among the hits
+    /// `accept` takes
+    pub fn closest_hit(
+        &self,
+        ray: &Ray,
+        t_max: f64,
+        accept: impl Fn(&Shape, f64) -> bool,
+    ) -> Option<(f64, &Shape)> {
+        self.bvh
+            .closest_hit(
+                ray,
+                t_max,
+                |i| self.objects[i].get_intersection(ray),
+                |i, t| accept(&self.objects[i], t),
+            )
+            .map(|(t, i)| (t, &self.objects[i]))
+    }
+
+    /// Fraction of the light that goes through `distance` of the scene's medium, all of it if
+    /// there's none
+    pub fn transmittance(&self, distance: f64) -> f64 {
+        self.medium
+            .as_ref()
+            .map_or(1.0, |medium| medium.transmittance(distance))
+    }
+
+    /// Whether rays of the kind hit the object, following its visibility and the selected layers
+    pub fn hits(&self, object: &Shape, kind: RayKind) -> bool {
+        if !object.visible_to(kind) {
+            return false;
+        }
+        match &self.selected_layers {
+            Some((layers, _)) if layers.iter().any(|layer| layer == object.layer()) => true,
+            Some((_, OtherLayers::Shadows)) => kind == RayKind::Shadow,
+            Some((_, OtherLayers::Hidden)) => false,
+            None => true,
+        }
+    }
+
+    /// Names of the render layers of the objects, sorted
+    pub fn layers(&self) -> Vec<String> {
+        self.objects
+            .iter()
+            .map(|object| object.layer().to_string())
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
+    /// Makes the render much cheaper at the cost of quality, for quick previews: reflections and
+    /// refractions only go one bounce deep, soft shadows and emissive lighting take a single
+    /// sample, there's no anti-aliasing nor denoising and the medium is marched in few steps
+    pub fn preview(&mut self) {
+        self.render.max_reflections = self.render.max_reflections.min(1);
+        self.render.emission_samples = 1;
+        for light in &mut self.lights {
+            light.shadow_samples = light.shadow_samples.min(1);
+        }
+        self.render.aa_threshold = None;
+        self.render.denoise = false;
+        if let Some(medium) = &mut self.medium {
+            medium.steps = medium.steps.min(PREVIEW_MEDIUM_STEPS);
+        }
+    }
+
+    /// Only renders the objects of the given layers from now on, the others become what `others`
+    /// says
+    pub fn select_layers(&mut self, layers: Vec<String>, others: OtherLayers) -> Result<()> {
+        let existing = self.layers();
+        if let Some(missing) = layers.iter().find(|layer| !existing.contains(layer)) {
+            return Err(anyhow!(
+                "The scene has no objects in the layer '{}', its layers are: {}",
+                missing,
+                existing.join(", ")
+            ));
+        }
+        self.selected_layers = Some((layers, others));
+        Ok(())
+    }
+
+    /// Color seen looking along `dir` when nothing is hit
+    pub fn background(&self, dir: Vec3) -> Color {
+        match (&self.bg_image, &self.sky) {
+            (Some(image), _) => image.sample(dir),
+            (None, Some(sky)) => sky.sample(dir),
+            (None, None) => self.bg_color,
+        }
+    }
+
+    pub fn read_config<P: AsRef<Path>>(path: P, overrides: &[ConfigOverride]) -> Result<Scene> {
+        Scene::read_file(path.as_ref(), ConfigFormat::Ini, overrides)
+    }
+
+    pub fn read_json<P: AsRef<Path>>(path: P, overrides: &[ConfigOverride]) -> Result<Scene> {
+        Scene::read_file(path.as_ref(), ConfigFormat::Json, overrides)
+    }
+
+    pub fn read_toml<P: AsRef<Path>>(path: P, overrides: &[ConfigOverride]) -> Result<Scene> {
+        Scene::read_file(path.as_ref(), ConfigFormat::Toml, overrides)
+    }
+
+    fn read_file(path: &Path, format: ConfigFormat, overrides: &[ConfigOverride]) -> Result<Scene> {
+        let config = read_ini(path, format, overrides)?;
         let mut objects = Vec::<Shape>::new();
         let mut lights = Vec::<Light>::new();
+        let mut errors = ConfigErrors::default();
 
-        config.set_comment_symbols(&[';', '"']);
-        config.load(path).map_err(|s| anyhow!(s))?;
         //println!("Map: {:?}", map);
 
-        let ambient = get_float_fails(&config, "scene", "I_a")?;
-        let bg_color = get_color_default(&config, "scene", "bg_color", DEFAULT_BG_COLOR)?;
-        let ambient_color =
-            get_color_default(&config, "scene", "ambient_color", DEFAULT_LIGHT_COLOR)?;
+        let ambient = errors
+            .check(|| get_float_fails(&config, "scene", "I_a"))
+            .unwrap_or(0.0);
+        let bg_color = errors
+            .check(|| get_color_default(&config, "scene", "bg_color", DEFAULT_BG_COLOR))
+            .unwrap_or(colors::BLACK);
+        let mut ambient_color = errors
+            .check(|| get_color_default(&config, "scene", "ambient_color", DEFAULT_LIGHT_COLOR))
+            .unwrap_or(colors::BLACK);
+        let ibl = errors
+            .check(|| get_float_default(&config, "scene", "ibl", 0.0))
+            .unwrap_or(0.0);
+        if ibl < 0.0 {
+            errors.check(|| -> Result<()> {
+                Err(anyhow!("In section 'scene' the 'ibl' can't be negative"))
+            });
+        }
+        let exposure = errors
+            .check(|| get_float_default(&config, "scene", "exposure", 0.0))
+            .map_or(1.0, f64::exp2);
+        let light_units = errors
+            .check(|| get_light_units(&config))
+            .unwrap_or(LightUnits::Normalized);
+        let render = errors
+            .check(|| read_render_settings(&config))
+            .unwrap_or_default();
+        let bg_image = errors
+            .check(|| {
+                config
+                    .path("scene", "bg_image")
+                    .map(|path| {
+                        EnvironmentMap::open(&path)
+                            .context("In attribute 'bg_image' in section 'scene'")
+                    })
+                    .transpose()
+            })
+            .flatten();
+        let sky = errors.check(|| read_sky(&config)).flatten();
+        let mut medium = errors.check(|| read_medium(&config)).flatten();
+        if let Some(sky) = &sky {
+            if bg_image.is_some() {
+                errors.check(|| -> Result<()> {
+                    Err(anyhow!(
+                        "The scene can't have both a 'bg_image' and a [sky] section"
+                    ))
+                });
+            }
+            if errors
+                .check(|| get_bool_default(&config, "sky", "tint_ambient", false))
+                .unwrap_or(false)
+            {
+                ambient_color = ambient_color * sky.ambient_tint();
+            }
+        }
 
         // spheres (checks for prefix)
         for sphere_section in config
@@ -44,14 +314,58 @@ impl Scene {
             .iter()
             .filter(|s| s.len() >= 6 && &s[0..6] == "sphere")
         {
-            let center = get_vec3_fails(&config, sphere_section, "center")?;
+            errors.check(|| {
+                let center = get_vec3_fails(&config, sphere_section, "center")?;
+
+                let radius = get_float_fails(&config, sphere_section, "radius")
+                    .or_else(|_| get_float_fails(&config, sphere_section, "r"))?;
 
-            let radius = get_float_fails(&config, sphere_section, "radius")
-                .or_else(|_| get_float_fails(&config, sphere_section, "r"))?;
+                let params = get_params(&config, sphere_section)?;
 
-            let params = get_params(&config, sphere_section)?;
+                objects.push(Shape::Sphere(Sphere::new(center, radius, params)));
+                Ok(())
+            });
+        }
 
-            objects.push(Shape::Sphere(Sphere::new(center, radius, params)));
+        for ellipsoid_section in config
+            .sections()
+            .iter()
+            .filter(|s| s.len() >= 9 && &s[0..9] == "ellipsoid")
+        {
+            errors.check(|| {
+                let center = get_vec3_fails(&config, ellipsoid_section, "center")?;
+
+                let radii = Vec3::new(
+                    get_float_fails(&config, ellipsoid_section, "rx")?,
+                    get_float_fails(&config, ellipsoid_section, "ry")?,
+                    get_float_fails(&config, ellipsoid_section, "rz")?,
+                );
+                if radii.x <= 0.0 || radii.y <= 0.0 || radii.z <= 0.0 {
+                    return Err(anyhow!(
+                        "In section '{}' the rx, ry and rz must be positive",
+                        ellipsoid_section
+                    ));
+                }
+
+                let rotation = get_vec3_default(
+                    &config,
+                    ellipsoid_section,
+                    "rotation",
+                    Vec3::new(0.0, 0.0, 0.0),
+                )?;
+                let basis = Onb::from_euler(
+                    rotation.x.to_radians(),
+                    rotation.y.to_radians(),
+                    rotation.z.to_radians(),
+                );
+
+                let params = get_params(&config, ellipsoid_section)?;
+
+                objects.push(Shape::Ellipsoid(Ellipsoid::new(
+                    center, radii, basis, params,
+                )));
+                Ok(())
+            });
         }
 
         for cylinder_section in config
@@ -59,19 +373,30 @@ impl Scene {
             .iter()
             .filter(|s| s.len() >= 8 && &s[0..8] == "cylinder")
         {
-            let anchor = get_vec3_fails(&config, cylinder_section, "anchor")?;
-            let dir = get_vec3_fails(&config, cylinder_section, "dir")?;
-
-            let radius = get_float_fails(&config, cylinder_section, "radius")
-                .or_else(|_| get_float_fails(&config, cylinder_section, "r"))?;
-
-            let length = get_float_fails(&config, cylinder_section, "length")?;
-
-            let params = get_params(&config, cylinder_section)?;
-
-            objects.push(Shape::Cylinder(Cylinder::new(
-                anchor, dir, radius, length, params,
-            )));
+            errors.check(|| {
+                let anchor = get_vec3_fails(&config, cylinder_section, "anchor")?;
+                let dir = get_vec3_fails(&config, cylinder_section, "dir")
+                    .or_else(|_| get_vec3_fails(&config, cylinder_section, "direction"))?;
+                if dir.near_zero() {
+                    return Err(anyhow!(
+                        "In section '{}' the direction can't be zero",
+                        cylinder_section
+                    ));
+                }
+
+                let radius = get_float_fails(&config, cylinder_section, "radius")
+                    .or_else(|_| get_float_fails(&config, cylinder_section, "r"))?;
+
+                let length = get_float_fails(&config, cylinder_section, "length")?;
+                let caps = get_bool_default(&config, cylinder_section, "caps", false)?;
+
+                let params = get_params(&config, cylinder_section)?;
+
+                objects.push(Shape::Cylinder(Cylinder::new(
+                    anchor, dir, radius, length, caps, params,
+                )));
+                Ok(())
+            });
         }
 
         for cone_section in config
@@ -79,16 +404,26 @@ impl Scene {
             .iter()
             .filter(|s| s.len() >= 4 && &s[0..4] == "cone")
         {
-            let anchor = get_vec3_fails(&config, cone_section, "anchor")?;
-            let dir = get_vec3_fails(&config, cone_section, "dir")?;
-
-            let length = get_float_fails(&config, cone_section, "length")?;
-            let k1 = get_float_fails(&config, cone_section, "k1")?;
-            let k2 = get_float_fails(&config, cone_section, "k2")?;
-
-            let params = get_params(&config, cone_section)?;
-
-            objects.push(Shape::Cone(Cone::new(anchor, dir, length, k1, k2, params)));
+            errors.check(|| {
+                let anchor = get_vec3_fails(&config, cone_section, "anchor")?;
+                let dir = get_vec3_fails(&config, cone_section, "dir")
+                    .or_else(|_| get_vec3_fails(&config, cone_section, "direction"))?;
+                if dir.near_zero() {
+                    return Err(anyhow!(
+                        "In section '{}' the direction can't be zero",
+                        cone_section
+                    ));
+                }
+
+                let length = get_float_fails(&config, cone_section, "length")?;
+                let k1 = get_float_fails(&config, cone_section, "k1")?;
+                let k2 = get_float_fails(&config, cone_section, "k2")?;
+
+                let params = get_params(&config, cone_section)?;
+
+                objects.push(Shape::Cone(Cone::new(anchor, dir, length, k1, k2, params)));
+                Ok(())
+            });
         }
 
         for plane_section in config
@@ -96,13 +431,16 @@ impl Scene {
             .iter()
             .filter(|s| s.len() >= 5 && &s[0..5] == "plane")
         {
-            let point = get_vec3_fails(&config, plane_section, "point")?;
+            errors.check(|| {
+                let point = get_vec3_fails(&config, plane_section, "point")?;
 
-            let normal = get_vec3_fails(&config, plane_section, "normal")?;
+                let normal = get_vec3_fails(&config, plane_section, "normal")?;
 
-            let params = get_params(&config, plane_section)?;
+                let params = get_params(&config, plane_section)?;
 
-            objects.push(Shape::Plane(Plane::new(normal, point, params)));
+                objects.push(Shape::Plane(Plane::new(normal, point, params)));
+                Ok(())
+            });
         }
 
         for disc_section in config
@@ -110,15 +448,60 @@ impl Scene {
             .iter()
             .filter(|s| s.len() >= 4 && &s[0..4] == "disc")
         {
-            let center = get_vec3_fails(&config, disc_section, "center")?;
-            let radius = get_float_fails(&config, disc_section, "radius")
-                .or_else(|_| get_float_fails(&config, disc_section, "r"))?;
+            errors.check(|| {
+                let center = get_vec3_fails(&config, disc_section, "center")?;
+                let radius = get_float_fails(&config, disc_section, "radius")
+                    .or_else(|_| get_float_fails(&config, disc_section, "r"))?;
+
+                let normal = get_vec3_fails(&config, disc_section, "normal")?;
 
-            let normal = get_vec3_fails(&config, disc_section, "normal")?;
+                if normal.near_zero() {
+                    return Err(anyhow!(
+                        "In section '{}' the normal vector can't be zero",
+                        disc_section
+                    ));
+                }
 
-            let params = get_params(&config, disc_section)?;
+                let params = get_params(&config, disc_section)?;
+
+                objects.push(Shape::Disc(Disc::new(normal, center, radius, params)));
+                Ok(())
+            });
+        }
 
-            objects.push(Shape::Disc(Disc::new(normal, center, radius, params)));
+        for ring_section in config
+            .sections()
+            .iter()
+            .filter(|s| s.len() >= 4 && &s[0..4] == "ring")
+        {
+            errors.check(|| {
+                let center = get_vec3_fails(&config, ring_section, "center")?;
+                let r_inner = get_float_fails(&config, ring_section, "r_inner")?;
+                let r_outer = get_float_fails(&config, ring_section, "r_outer")?;
+
+                if r_inner > r_outer {
+                    return Err(anyhow!(
+                        "In section '{}' the inner radius is bigger than the outer radius",
+                        ring_section
+                    ));
+                }
+
+                let normal = get_vec3_fails(&config, ring_section, "normal")?;
+
+                if normal.near_zero() {
+                    return Err(anyhow!(
+                        "In section '{}' the normal vector can't be zero",
+                        ring_section
+                    ));
+                }
+
+                let params = get_params(&config, ring_section)?;
+
+                objects.push(Shape::Ring(Ring::new(
+                    normal, center, r_inner, r_outer, params,
+                )));
+                Ok(())
+            });
         }
 
         for triangle_section in config
@@ -126,13 +509,528 @@ impl Scene {
             .iter()
             .filter(|s| s.len() >= 8 && &s[0..8] == "triangle")
         {
-            let a = get_vec3_fails(&config, triangle_section, "a")?;
-            let b = get_vec3_fails(&config, triangle_section, "b")?;
-            let c = get_vec3_fails(&config, triangle_section, "c")?;
+            errors.check(|| {
+                let a = get_vec3_fails(&config, triangle_section, "a")?;
+                let b = get_vec3_fails(&config, triangle_section, "b")?;
+                let c = get_vec3_fails(&config, triangle_section, "c")?;
+
+                if (b - a).cross(c - a).near_zero() {
+                    return Err(anyhow!(
+                        "In section '{}' the triangle's vertices are collinear, it has no area",
+                        triangle_section
+                    ));
+                }
+
+                let vertex_normals = match (
+                    config.get(triangle_section, "na"),
+                    config.get(triangle_section, "nb"),
+                    config.get(triangle_section, "nc"),
+                ) {
+                    (None, None, None) => None,
+                    (Some(_), Some(_), Some(_)) => {
+                        let normals = [
+                            get_vec3_fails(&config, triangle_section, "na")?,
+                            get_vec3_fails(&config, triangle_section, "nb")?,
+                            get_vec3_fails(&config, triangle_section, "nc")?,
+                        ];
+                        if normals.iter().any(|n| n.near_zero()) {
+                            return Err(anyhow!(
+                                "In section '{}' the vertex normals can't be zero vectors",
+                                triangle_section
+                            ));
+                        }
+                        Some(normals)
+                    }
+                    _ => {
+                        return Err(anyhow!(
+                            "In section '{}' either all of the vertex normals 'na', 'nb' and \
+                            'nc' or none of them must be given",
+                            triangle_section
+                        ))
+                    }
+                };
+
+                let params = get_params(&config, triangle_section)?;
+
+                objects.push(Shape::Triangle(Triangle::new(
+                    a,
+                    b,
+                    c,
+                    vertex_normals,
+                    params,
+                )));
+                Ok(())
+            });
+        }
+
+        for polygon_section in config
+            .sections()
+            .iter()
+            .filter(|s| s.len() >= 7 && &s[0..7] == "polygon")
+        {
+            errors.check(|| {
+                let vertices = get_tuples_fails(&config, polygon_section, "vertices")?
+                    .into_iter()
+                    .map(|vertex| match vertex[..] {
+                        [x, y, z] => Ok(Vec3::new(x, y, z)),
+                        _ => Err(anyhow!(
+                        "In attribute 'vertices' in section {} every vertex must be 3-dimensional",
+                        polygon_section
+                    )),
+                    })
+                    .collect::<Result<Vec<Vec3>>>()?;
+
+                let params = get_params(&config, polygon_section)?;
+
+                let polygon = Polygon::new(vertices, params)
+                    .map_err(|e| anyhow!("In section '{}' {}", polygon_section, e))?;
+                objects.push(Shape::Polygon(polygon));
+                Ok(())
+            });
+        }
+
+        for torus_section in config
+            .sections()
+            .iter()
+            .filter(|s| s.len() >= 5 && &s[0..5] == "torus")
+        {
+            errors.check(|| {
+                let center = get_vec3_fails(&config, torus_section, "center")?;
+                let axis = get_vec3_fails(&config, torus_section, "axis")?;
+
+                if axis.near_zero() {
+                    return Err(anyhow!(
+                        "In section '{}' the axis can't be zero",
+                        torus_section
+                    ));
+                }
+
+                let major_r = get_float_fails(&config, torus_section, "major_radius")?;
+                let minor_r = get_float_fails(&config, torus_section, "minor_radius")?;
+                if major_r <= 0.0 || minor_r <= 0.0 {
+                    return Err(anyhow!(
+                        "In section '{}' the major_radius and minor_radius must be positive",
+                        torus_section
+                    ));
+                }
+
+                let params = get_params(&config, torus_section)?;
+
+                objects.push(Shape::Torus(Torus::new(
+                    center, axis, major_r, minor_r, params,
+                )));
+                Ok(())
+            });
+        }
+
+        for obj_section in config
+            .sections()
+            .iter()
+            .filter(|s| s.len() >= 3 && &s[0..3] == "obj")
+        {
+            errors.check(|| {
+                let path = config.path(obj_section, "path").ok_or_else(|| {
+                    anyhow!(
+                        "Missing attribute 'path' for {} in config file",
+                        obj_section
+                    )
+                })?;
+                let data = read_obj(&path)?;
+
+                let position =
+                    get_vec3_default(&config, obj_section, "position", Vec3::new(0.0, 0.0, 0.0))?;
+                let scale = get_float_default(&config, obj_section, "scale", 1.0)?;
+                let rotation =
+                    get_vec3_default(&config, obj_section, "rotation", Vec3::new(0.0, 0.0, 0.0))?;
+                let basis = Onb::from_euler(
+                    rotation.x.to_radians(),
+                    rotation.y.to_radians(),
+                    rotation.z.to_radians(),
+                );
+
+                let params = get_params(&config, obj_section)?;
+
+                objects.push(Shape::Mesh(
+                    Mesh::from_obj(&data, scale, &basis, position, params)
+                        .with_context(|| format!("In section '{}'", obj_section))?,
+                ));
+                Ok(())
+            });
+        }
+
+        for patch_section in config
+            .sections()
+            .iter()
+            .filter(|s| s.len() >= 5 && &s[0..5] == "patch")
+        {
+            errors.check(|| {
+                let points = get_tuples_fails(&config, patch_section, "points")?
+                    .into_iter()
+                    .map(|point| match point[..] {
+                        [x, y, z] => Ok(Vec3::new(x, y, z)),
+                        _ => Err(anyhow!(
+                            "In attribute 'points' in section {} every control point must be \
+                            3-dimensional",
+                            patch_section
+                        )),
+                    })
+                    .collect::<Result<Vec<Vec3>>>()?;
+                if points.len() != 16 {
+                    return Err(anyhow!(
+                        "In section '{}' the patch must have 16 control points and it has {}",
+                        patch_section,
+                        points.len()
+                    ));
+                }
+                let control_points =
+                    [0, 1, 2, 3].map(|row| [0, 1, 2, 3].map(|column| points[row * 4 + column]));
+
+                let subdivisions = get_uint_default(&config, patch_section, "subdivisions", 16)?;
+                if subdivisions < 1 {
+                    return Err(anyhow!(
+                        "In section '{}' the subdivisions must be at least 1",
+                        patch_section
+                    ));
+                }
+
+                let data = BezierPatch::new(control_points).tessellate(subdivisions as usize);
+
+                let params = get_params(&config, patch_section)?;
+
+                objects.push(Shape::Mesh(
+                    Mesh::from_obj(
+                        &data,
+                        1.0,
+                        &Onb::from_euler(0.0, 0.0, 0.0),
+                        Vec3::new(0.0, 0.0, 0.0),
+                        params,
+                    )
+                    .with_context(|| format!("In section '{}'", patch_section))?,
+                ));
+                Ok(())
+            });
+        }
+
+        for sdf_section in config
+            .sections()
+            .iter()
+            .filter(|s| s.len() >= 3 && &s[0..3] == "sdf")
+        {
+            errors.check(|| {
+                let center = get_vec3_fails(&config, sdf_section, "center")?;
+                let rotation =
+                    get_vec3_default(&config, sdf_section, "rotation", Vec3::new(0.0, 0.0, 0.0))?;
+                let basis = Onb::from_euler(
+                    rotation.x.to_radians(),
+                    rotation.y.to_radians(),
+                    rotation.z.to_radians(),
+                );
+
+                let primitive = match config.get(sdf_section, "type").as_deref().map(str::trim) {
+                    Some("rounded_box") => SdfPrimitive::RoundedBox {
+                        half_size: get_vec3_fails(&config, sdf_section, "size")? / 2.0,
+                        radius: get_float_default(&config, sdf_section, "radius", 0.0)?,
+                    },
+                    Some("torus") => SdfPrimitive::Torus {
+                        major_r: get_float_fails(&config, sdf_section, "major_radius")?,
+                        minor_r: get_float_fails(&config, sdf_section, "minor_radius")?,
+                    },
+                    Some("capsule") => SdfPrimitive::Capsule {
+                        half_length: get_float_fails(&config, sdf_section, "length")? / 2.0,
+                        radius: get_float_fails(&config, sdf_section, "radius")?,
+                    },
+                    Some(other) => {
+                        return Err(anyhow!(
+                            "In section '{}' the type '{}' isn't valid, it must be one of: \
+                            rounded_box, torus, capsule",
+                            sdf_section,
+                            other
+                        ))
+                    }
+                    None => {
+                        return Err(anyhow!(
+                            "Missing attribute 'type' for {} in config file",
+                            sdf_section
+                        ))
+                    }
+                };
+
+                let params = get_params(&config, sdf_section)?;
+
+                objects.push(Shape::Sdf(Sdf::new(primitive, center, basis, params)));
+                Ok(())
+            });
+        }
+
+        for metaballs_section in config
+            .sections()
+            .iter()
+            .filter(|s| s.len() >= 9 && &s[0..9] == "metaballs")
+        {
+            errors.check(|| {
+                let balls = get_tuples_fails(&config, metaballs_section, "balls")?
+                    .into_iter()
+                    .map(|ball| match ball[..] {
+                        [x, y, z, radius] if radius > 0.0 => Ok((Vec3::new(x, y, z), radius, 1.0)),
+                        [x, y, z, radius, weight] if radius > 0.0 => {
+                            Ok((Vec3::new(x, y, z), radius, weight))
+                        }
+                        _ => Err(anyhow!(
+                            "In attribute 'balls' in section {} every ball must be of the form \
+                            (x, y, z, radius) or (x, y, z, radius, weight) with a positive radius",
+                            metaballs_section
+                        )),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                let threshold = get_float_default(&config, metaballs_section, "threshold", 0.5)?;
+                if threshold <= 0.0 || threshold >= 1.0 {
+                    return Err(anyhow!(
+                        "In section '{}' the threshold must be between 0 and 1 (exclusive)",
+                        metaballs_section
+                    ));
+                }
+
+                let params = get_params(&config, metaballs_section)?;
+
+                objects.push(Shape::Metaballs(Metaballs::new(&balls, threshold, params)));
+                Ok(())
+            });
+        }
+
+        for heightfield_section in config
+            .sections()
+            .iter()
+            .filter(|s| s.len() >= 11 && &s[0..11] == "heightfield")
+        {
+            errors.check(|| {
+                let (samples, samples_x, samples_z) = match config
+                    .path(heightfield_section, "image")
+                {
+                    Some(path) => {
+                        let image = image::open(&path)
+                            .context(format!(
+                                "In section '{}' couldn't read the image '{}'",
+                                heightfield_section,
+                                path.display()
+                            ))?
+                            .to_luma16();
+                        let (width, height) = (image.width() as usize, image.height() as usize);
+                        if width < 2 || height < 2 {
+                            return Err(anyhow!(
+                                "In section '{}' the image must be at least 2x2 pixels",
+                                heightfield_section
+                            ));
+                        }
+
+                        // the image's top row is the far end of the heightfield (highest 'z')
+                        let samples = (0..height)
+                            .rev()
+                            .flat_map(|row| (0..width).map(move |column| (column, row)))
+                            .map(|(column, row)| {
+                                image.get_pixel(column as u32, row as u32)[0] as f64
+                                    / u16::MAX as f64
+                            })
+                            .collect::<Vec<f64>>();
+                        (samples, width, height)
+                    }
+                    None => {
+                        let resolution =
+                            get_uint_default(&config, heightfield_section, "resolution", 128)?
+                                as usize;
+                        if resolution < 1 {
+                            return Err(anyhow!(
+                                "In section '{}' the resolution must be at least 1",
+                                heightfield_section
+                            ));
+                        }
+                        let frequency = get_float_default(
+                            &config,
+                            heightfield_section,
+                            "noise_frequency",
+                            4.0,
+                        )?;
+                        let octaves = get_uint_default(&config, heightfield_section, "octaves", 5)?;
+                        let perlin =
+                            Perlin::new(get_uint_default(&config, heightfield_section, "seed", 0)?);
+
+                        let samples_side = resolution + 1;
+                        let samples = (0..samples_side)
+                            .flat_map(|j| (0..samples_side).map(move |i| (i, j)))
+                            .map(|(i, j)| {
+                                let point = Vec3::new(i as f64, 0.5, j as f64) * frequency
+                                    / resolution as f64;
+                                (perlin.fbm(point, octaves as u32) + 1.0) / 2.0
+                            })
+                            .collect::<Vec<f64>>();
+                        (samples, samples_side, samples_side)
+                    }
+                };
+
+                let position = get_vec3_default(
+                    &config,
+                    heightfield_section,
+                    "position",
+                    Vec3::new(0.0, 0.0, 0.0),
+                )?;
+                let width = get_float_fails(&config, heightfield_section, "width")?;
+                let depth = get_float_fails(&config, heightfield_section, "depth")?;
+                let height_scale = get_float_fails(&config, heightfield_section, "height_scale")?;
+                if width <= 0.0 || depth <= 0.0 {
+                    return Err(anyhow!(
+                        "In section '{}' the width and depth must be positive",
+                        heightfield_section
+                    ));
+                }
+
+                let params = get_params(&config, heightfield_section)?;
+
+                objects.push(Shape::Heightfield(Heightfield::new(
+                    &samples,
+                    samples_x,
+                    samples_z,
+                    position,
+                    width,
+                    depth,
+                    height_scale,
+                    params,
+                )));
+                Ok(())
+            });
+        }
+
+        // Grouped objects are moved along with their group before CSGs and instances use them
+        objects = objects
+            .into_iter()
+            .filter_map(|object| errors.check(|| apply_group(&config, object)))
+            .collect();
 
-            let params = get_params(&config, triangle_section)?;
+        // CSG sections consume the objects they reference, possibly other CSGs, so they're built
+        // as soon as both of their children are available
+        let sections = config.sections();
+        let mut pending_csgs: Vec<&String> = sections
+            .iter()
+            .filter(|s| s.len() >= 3 && &s[0..3] == "csg")
+            .collect();
+
+        while !pending_csgs.is_empty() {
+            let before = pending_csgs.len();
+
+            for csg_section in pending_csgs.clone() {
+                let built = errors.check(|| {
+                    let left = get_object_name(&config, csg_section, "left")?;
+                    let right = get_object_name(&config, csg_section, "right")?;
+
+                    if pending_csgs.iter().any(|s| **s == left || **s == right) {
+                        return Ok(false);
+                    }
+
+                    let left = take_csg_child(&mut objects, csg_section, &left)?;
+                    let right = take_csg_child(&mut objects, csg_section, &right)?;
+
+                    let operation = match config
+                        .get(csg_section, "operation")
+                        .as_deref()
+                        .map(str::to_lowercase)
+                        .as_deref()
+                    {
+                        Some("union") => CsgOperation::Union,
+                        Some("intersection") => CsgOperation::Intersection,
+                        Some("difference") => CsgOperation::Difference,
+                        Some(other) => {
+                            return Err(anyhow!(
+                        "In section '{}' the operation '{}' isn't valid, it must be one of: \
+                        union, intersection, difference",
+                        csg_section,
+                        other
+                    ))
+                        }
+                        None => {
+                            return Err(anyhow!(
+                                "Missing attribute 'operation' for {} in config file",
+                                csg_section
+                            ))
+                        }
+                    };
+
+                    let params = get_params(&config, csg_section)?;
+
+                    let csg = Shape::Csg(Csg::new(operation, left, right, params));
+                    objects.push(apply_group(&config, csg)?);
+                    Ok(true)
+                });
+
+                // failed ones are dropped too, their error is already kept
+                if built != Some(false) {
+                    pending_csgs.retain(|s| *s != csg_section);
+                }
+            }
+
+            if pending_csgs.len() == before {
+                errors.0.push(anyhow!(
+                    "The CSG sections {:?} reference each other in a cycle",
+                    pending_csgs
+                ));
+                break;
+            }
+        }
 
-            objects.push(Shape::Triangle(Triangle::new(a, b, c, params)));
+        // Instances copy other objects, which may be instances themselves
+        let mut pending_instances: Vec<&String> = sections
+            .iter()
+            .filter(|s| s.len() >= 8 && &s[0..8] == "instance")
+            .collect();
+
+        while !pending_instances.is_empty() {
+            let before = pending_instances.len();
+
+            for instance_section in pending_instances.clone() {
+                let built = errors.check(|| {
+                    let name = get_object_name(&config, instance_section, "object")?;
+
+                    if pending_instances.iter().any(|s| **s == name) {
+                        return Ok(false);
+                    }
+
+                    let shape = objects
+                        .iter()
+                        .find(|o| o.get_params().name == name)
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "In section '{}' the referenced object '{}' doesn't exist",
+                                instance_section,
+                                name
+                            )
+                        })?
+                        .clone();
+
+                    let transform = get_transform(&config, instance_section)?;
+
+                    let mut params = shape.get_params().clone();
+                    params.name = instance_section.clone();
+
+                    let instance = Instance::new(shape, transform, params).ok_or_else(|| {
+                        anyhow!(
+                            "In section '{}' the transformation can't be inverted",
+                            instance_section
+                        )
+                    })?;
+                    objects.push(apply_group(&config, Shape::Instance(instance))?);
+                    Ok(true)
+                });
+
+                if built != Some(false) {
+                    pending_instances.retain(|s| *s != instance_section);
+                }
+            }
+
+            if pending_instances.len() == before {
+                errors.0.push(anyhow!(
+                    "The instance sections {:?} reference each other in a cycle",
+                    pending_instances
+                ));
+                break;
+            }
         }
 
         // lights
@@ -141,53 +1039,316 @@ impl Scene {
             .iter()
             .filter(|s| s.len() >= 5 && &s[0..5] == "light")
         {
-            let position = get_vec3_fails(&config, light_section, "position")?;
-
-            let intensity = get_float_fails(&config, light_section, "intensity")
-                .or_else(|_| get_float_fails(&config, light_section, "I_p"))?
-                .max(0.0);
-
-            let c_1 = get_float_fails(&config, light_section, "c_1")
-                .or_else(|_| get_float_fails(&config, light_section, "C1"))?;
-            let c_2 = get_float_fails(&config, light_section, "c_2")
-                .or_else(|_| get_float_fails(&config, light_section, "C2"))?;
-            let c_3 = get_float_fails(&config, light_section, "c_3")
-                .or_else(|_| get_float_fails(&config, light_section, "C3"))?;
-
-            let color = get_color_default(&config, light_section, "color", DEFAULT_LIGHT_COLOR)?;
-
-            lights.push(Light {
-                position,
-                intensity,
-                c_1,
-                c_2,
-                c_3,
-                color,
-            })
+            errors.check(|| {
+                let position = get_vec3_fails(&config, light_section, "position")?;
+
+                let intensity = get_float_fails(&config, light_section, "intensity")
+                    .or_else(|_| get_float_fails(&config, light_section, "I_p"))?
+                    .max(0.0);
+
+                let (c_1, c_2, c_3) = get_attenuation_factors(&config, light_section)?;
+
+                let color =
+                    get_color_default(&config, light_section, "color", DEFAULT_LIGHT_COLOR)?;
+
+                let spot = get_spot(&config, light_section)?;
+                let ies = config
+                    .path(light_section, "ies")
+                    .map(|path| {
+                        IesProfile::open(&path).with_context(|| {
+                            format!("In attribute 'ies' in section '{}'", light_section)
+                        })
+                    })
+                    .transpose()?;
+
+                let radius = get_float_default(&config, light_section, "radius", 0.0)?;
+                if radius < 0.0 {
+                    return Err(anyhow!(
+                        "In section '{}' the radius can't be negative",
+                        light_section
+                    ));
+                }
+                let shadow_samples = get_uint_default(
+                    &config,
+                    light_section,
+                    "shadow_samples",
+                    DEFAULT_SHADOW_SAMPLES,
+                )?;
+                let shadow_samples = u32::try_from(shadow_samples).with_context(|| {
+                    format!(
+                        "In section '{}' the shadow_samples is too large",
+                        light_section
+                    )
+                })?;
+
+                let affects = match config.get(light_section, "affects") {
+                    Some(_) => Some(get_section_names(&config, light_section, "affects")?),
+                    None => None,
+                };
+                let excludes = get_section_names(&config, light_section, "excludes")?;
+
+                let cast_shadows = get_bool_default(&config, light_section, "cast_shadows", true)?;
+                let diffuse = get_bool_default(&config, light_section, "diffuse", true)?;
+                let specular = get_bool_default(&config, light_section, "specular", true)?;
+
+                lights.push(Light {
+                    name: light_section.to_string(),
+                    position,
+                    intensity,
+                    c_1,
+                    c_2,
+                    c_3,
+                    color,
+                    spot,
+                    ies,
+                    radius,
+                    shadow_samples,
+                    affects,
+                    excludes,
+                    cast_shadows,
+                    diffuse,
+                    specular,
+                });
+                Ok(())
+            });
         }
 
+        // The whole scene is scaled around the origin, attenuation and the medium are adjusted so
+        // that lights reach just as far and the fog looks the same relative to the scene's size
+        if let Some(scale) = errors.check(|| get_scene_scale(&config)) {
+            if scale != 1.0 {
+                let scaling = Matrix4::scaling(Vec3::new(scale, scale, scale));
+                let scaled = errors.check(|| {
+                    objects
+                        .drain(..)
+                        .map(|object| {
+                            let params = object.get_params().clone();
+                            Instance::new(object, scaling, params)
+                                .map(Shape::Instance)
+                                .ok_or_else(|| {
+                                    anyhow!("In section 'scene' the scale {} is too small", scale)
+                                })
+                        })
+                        .collect::<Result<Vec<Shape>>>()
+                });
+                objects = scaled.unwrap_or_default();
+
+                for light in &mut lights {
+                    light.position *= scale;
+                    light.radius *= scale;
+                    light.c_2 /= scale;
+                    light.c_3 /= scale * scale;
+                }
+
+                if let Some(medium) = &mut medium {
+                    medium.density /= scale;
+                    medium.distance *= scale;
+                }
+            }
+        }
+
+        errors.into_result()?;
+
+        let emissive = objects.iter().any(|object| !object.emission().is_black());
+        let bvh = Bvh::new(objects.iter().map(|object| object.bounds()));
+
         Ok(Scene {
             objects,
+            bvh,
             lights,
             ambient,
             bg_color,
+            bg_image,
+            sky,
+            medium,
             ambient_color,
+            ibl,
+            exposure,
+            light_units,
+            render,
+            emissive,
+            selected_layers: None,
+            quiet: false,
         })
     }
 }
 
+/// How the light that reaches a point adds up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum LightUnits {
+    /// Attenuation and the light summed over every light are limited to 1, so intensities are
+    /// meant to be between 0 and 1
+    Normalized,
+    /// Nothing is limited, so intensities can be as large as needed and the image's brightness is
+    /// set with the exposure
+    Physical,
+}
+
+impl LightUnits {
+    /// Limits the value to 1 if the units are normalized
+    pub fn limit(self, value: f64) -> f64 {
+        match self {
+            LightUnits::Normalized => value.min(1.0),
+            LightUnits::Physical => value,
+        }
+    }
+
+    /// Limits every channel of the light to 1 if the units are normalized
+    pub fn limit_color(self, light: Color) -> Color {
+        match self {
+            LightUnits::Normalized => light.min(1.0),
+            LightUnits::Physical => light,
+        }
+    }
+}
+
+/// How colors brighter than white are brought into the [0, 1] range of the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ToneMapping {
+    /// Colors are left as they are, brighter than white if they are, which only HDR images can
+    /// keep. 8 bit images clamp them anyway.
+    None,
+    /// Every channel is cut off at 1, which flattens highlights
+    Clamp,
+    /// Every channel is compressed by c / (1 + c), so bright colors fade into white without ever
+    /// reaching it
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve, which keeps more contrast in the midtones and
+    /// rolls highlights off smoothly
+    Aces,
+}
+
+impl ToneMapping {
+    pub fn apply(self, color: Color) -> Color {
+        let map = |c: f64| {
+            let c = c.max(0.0);
+            match self {
+                ToneMapping::None | ToneMapping::Clamp => c,
+                ToneMapping::Reinhard => c / (1.0 + c),
+                ToneMapping::Aces => (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14),
+            }
+        };
+        let mapped = Color {
+            r: map(color.r),
+            g: map(color.g),
+            b: map(color.b),
+        };
+        if self == ToneMapping::None {
+            mapped
+        } else {
+            mapped.clamp()
+        }
+    }
+}
+
+#[derive(Serialize)]
 pub struct Light {
+    /// Name of the section the light was defined in
+    pub name: String,
     pub position: Vec3,
     pub intensity: f64,
     c_1: f64,
     c_2: f64,
     c_3: f64,
     pub color: Color,
+    /// Cone the light is limited to, it shines in every direction if there's none
+    pub spot: Option<Spot>,
+    /// Photometric profile that sets how much light goes in each direction, pointing along the
+    /// spot's direction or straight down for lights that aren't spots
+    pub ies: Option<IesProfile>,
+    /// Radius of the sphere the light's shadows are cast from, 0 for sharp shadows
+    pub radius: f64,
+    /// Shadow rays sent towards lights with a radius
+    pub shadow_samples: u32,
+    /// Sections of the only objects the light lights, if it's limited to some
+    pub affects: Option<Vec<String>>,
+    /// Sections of objects the light doesn't light
+    pub excludes: Vec<String>,
+    /// Whether objects block this light
+    pub cast_shadows: bool,
+    /// Whether this light adds to the diffuse lighting of objects
+    pub diffuse: bool,
+    /// Whether this light makes specular highlights
+    pub specular: bool,
+}
+
+/// Cone of a spot light
+#[derive(Debug, Clone, Serialize)]
+pub struct Spot {
+    /// Direction of the cone's axis
+    pub direction: Vec3,
+    /// Cosine of the angle between the axis and the cone's side
+    pub cos_angle: f64,
+    /// How fast the light fades from the axis towards the side, 0 for a sharp even beam
+    pub falloff_exponent: f64,
 }
 
 impl Light {
-    pub fn get_attenuation(&self, distance: f64) -> f64 {
-        (1.0_f64 / (self.c_1 + self.c_2 * distance + self.c_3 * distance * distance)).min(1.0)
+    pub fn get_attenuation(&self, distance: f64, units: LightUnits) -> f64 {
+        units.limit(1.0_f64 / (self.c_1 + self.c_2 * distance + self.c_3 * distance * distance))
+    }
+
+    /// Whether the light lights the object, following the links between lights and objects set on
+    /// either of them
+    pub fn lights_object(&self, object: &ObjectParameters) -> bool {
+        self.affects
+            .as_ref()
+            .is_none_or(|affects| affects.contains(&object.name))
+            && !self.excludes.contains(&object.name)
+            && !object.ignored_lights.contains(&self.name)
+    }
+
+    /// Points of the light the shadow rays from the point are sent to, spread over the disc of the
+    /// light's sphere that faces the point
+    pub fn get_shadow_targets(
+        &self,
+        point: Vec3,
+        sampler: Sampler,
+        rng: &mut SampleRng,
+    ) -> Vec<Vec3> {
+        if self.radius <= 0.0 || self.shadow_samples == 0 {
+            return vec![self.position];
+        }
+
+        let facing = Onb::from_normal(point - self.position);
+        samples_2d(self.shadow_samples, sampler, rng)
+            .into_iter()
+            .map(|(u, v)| {
+                let (x, y) = square_to_disc(u, v);
+                self.position + self.radius * (x * facing.u + y * facing.v)
+            })
+            .collect()
+    }
+
+    /// Fraction of the light that reaches the point because of the spot's cone, 1 for lights that
+    /// aren't spots
+    pub fn get_spot_factor(&self, point: Vec3) -> f64 {
+        match &self.spot {
+            Some(spot) => {
+                let cos = (point - self.position).normalize().dot(spot.direction);
+                if cos < spot.cos_angle {
+                    0.0
+                } else {
+                    cos.powf(spot.falloff_exponent)
+                }
+            }
+            None => 1.0,
+        }
+    }
+
+    /// Fraction of the light sent towards the point according to the light's photometric profile,
+    /// 1 for lights without one
+    pub fn get_ies_factor(&self, point: Vec3) -> f64 {
+        match &self.ies {
+            Some(ies) => {
+                let axis = self
+                    .spot
+                    .as_ref()
+                    .map_or(Vec3::new(0.0, -1.0, 0.0), |spot| spot.direction);
+                ies.factor(axis, point - self.position)
+            }
+            None => 1.0,
+        }
     }
 
     pub fn get_l_vec(&self, intersection: Vec3) -> Vec3 {
@@ -196,134 +1357,2570 @@ impl Light {
 }
 
 /// Represents the camera + the projection plane used for the raytracer.
+#[derive(Clone, Serialize)]
 pub struct Observer {
     pub camera: Vec3,
 
-    /// minimum point of the projection plane
-    pub min_p: Vec3,
-    /// maximum point of the projection plane
-    pub max_p: Vec3,
+    /// point of the projection plane seen at the bottom left corner of the image
+    pub corner: Vec3,
+    /// span of the projection plane from the left to the right edge of the image
+    pub horizontal: Vec3,
+    /// span of the projection plane from the bottom to the top edge of the image
+    pub vertical: Vec3,
 
-    pub plane_z: f64,
+    pub projection: Projection,
+    pub lens: Lens,
+
+    /// Distance along the camera rays before which nothing is seen
+    pub near: f64,
+    /// Distance along the camera rays after which nothing is seen, they go on forever if there's
+    /// none
+    pub far: Option<f64>,
+
+    /// Width over height of the image, set by `fit_to_image`
+    pub aspect: f64,
+
+    /// Distance between the eyes of stereo images
+    pub eye_separation: f64,
+
+    pub animation: Option<Animation>,
 }
 
-impl Observer {
-    pub fn read_config<P: AsRef<Path>>(path: P) -> Result<Observer> {
-        let mut config = Ini::new();
+/// Path of the camera in an animation, which goes through every keyframe in order. Keyframes are
+/// evenly spaced in time, from the first one at time 0 to the last one at time 1.
+#[derive(Debug, Clone, Serialize)]
+pub struct Animation {
+    pub positions: Vec<Vec3>,
+    /// Points looked at, the camera keeps looking in the same direction if there's none
+    pub look_ats: Vec<Vec3>,
+    pub up: Vec3,
+}
+
+/// Point at time `t` (between 0 and 1) of the lines joining the keyframes, which can't be empty
+fn interpolate_keyframes(keyframes: &[Vec3], t: f64) -> Vec3 {
+    let s = t.clamp(0.0, 1.0) * (keyframes.len() - 1) as f64;
+    let i = (s.floor() as usize).min(keyframes.len() - 1);
+    match keyframes.get(i + 1) {
+        Some(&next) => keyframes[i] + (s - i as f64) * (next - keyframes[i]),
+        None => keyframes[i],
+    }
+}
 
-        config.load(path).map_err(|s| anyhow!(s))?;
+/// Lens of the camera, whose rays start anywhere on a disc around the camera's position. Only
+/// things at the focus distance come out sharp.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Lens {
+    /// Diameter of the disc, a pinhole camera with everything in focus has 0
+    pub aperture: f64,
+    /// Distance from the camera along the view direction to where the rays meet
+    pub focus_distance: f64,
+    /// Rays traced per pixel and averaged
+    pub samples: u32,
+}
 
-        let camera = get_vec3_fails(&config, "camera", "position")?;
+/// How the pixels of the image are turned into the directions of the rays
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Projection {
+    /// Rays go from the camera through the projection plane
+    Perspective,
+    /// The distance to the center of the image is proportional to the angle to the view direction,
+    /// up to half the field of view (in radians) at the edge of the inscribed circle
+    Fisheye { fov: f64 },
+    /// Equirectangular image of every direction, laid out like the `bg_image` of a scene: the
+    /// view direction at the center and up at the top row
+    Panorama,
+}
 
-        let plane_z = get_float_default(&config, "projection plane", "z", 0.0)?;
+/// Fields every object (and material) section may have
+const OBJECT_FIELDS: &[&str] = &[
+    "color",
+    "k_a",
+    "k_d",
+    "k_s",
+    "k_n",
+    "reflection",
+    "transparency",
+    "transparency_color",
+    "translucency",
+    "ior",
+    "bias",
+    "double_sided",
+    "visible_to",
+    "layer",
+    "fresnel",
+    "checkerboard",
+    "checkerboard_color2",
+    "material",
+    "group",
+    "emission",
+    "emission_intensity",
+    "ignore_lights",
+    "shading",
+    "metallic",
+    "roughness",
+    "base_color",
+    "uv_scale",
+    "uv_offset",
+    "uv_rotate",
+    "mapping",
+    "gradient",
+    "gradient_colors",
+    "gradient_axis",
+    "gradient_length",
+    "pattern",
+    "pattern_color",
+    "pattern_scale",
+    "pattern_seed",
+    "turbulence",
+    "bump_map",
+    "normal_map",
+    "bump_strength",
+    "map_size",
+    "wrap",
+];
+
+/// Kind of section of a config file, recognized by the start of its name
+struct SectionType {
+    prefix: &'static str,
+    /// Whether it also takes the OBJECT_FIELDS
+    is_object: bool,
+    /// `None` if it takes fields with any name
+    fields: Option<&'static [&'static str]>,
+}
 
-        let min_p = Vec3 {
-            x: get_float_fails(&config, "projection plane", "x_min")
-                .or_else(|_| get_float_fails(&config, "projection_plane", "x_min"))?,
-            y: get_float_fails(&config, "projection plane", "y_min")
-                .or_else(|_| get_float_fails(&config, "projection_plane", "x_min"))?,
-            z: plane_z,
-        };
+const fn section_type(
+    prefix: &'static str,
+    is_object: bool,
+    fields: Option<&'static [&'static str]>,
+) -> SectionType {
+    SectionType {
+        prefix,
+        is_object,
+        fields,
+    }
+}
 
-        let max_p = Vec3 {
-            x: get_float_fails(&config, "projection plane", "x_max")
-                .or_else(|_| get_float_fails(&config, "projection_plane", "x_max"))?,
-            y: get_float_fails(&config, "projection plane", "y_max")
-                .or_else(|_| get_float_fails(&config, "projection_plane", "x_max"))?,
-            z: plane_z,
+/// Every kind of section that's read, must be kept up to date with the fields read from them
+const SECTION_TYPES: &[SectionType] = &[
+    section_type(
+        "scene",
+        false,
+        Some(&[
+            "i_a",
+            "bg_color",
+            "bg_image",
+            "ambient_color",
+            "ibl",
+            "exposure",
+            "light_units",
+            "scale",
+        ]),
+    ),
+    section_type(
+        "render",
+        false,
+        Some(&[
+            "max_reflections",
+            "shadows",
+            "bias",
+            "emission_samples",
+            "aa_threshold",
+            "aa_samples",
+            "sampler",
+            "roulette_threshold",
+            "srgb",
+            "transparent",
+            "tone_mapping",
+            "firefly_clamp",
+            "denoise",
+            "denoise_radius",
+            "seed",
+        ]),
+    ),
+    section_type(
+        "sky",
+        false,
+        Some(&[
+            "sun_direction",
+            "sun_color",
+            "sun_size",
+            "zenith_color",
+            "horizon_color",
+            "ground_color",
+            "tint_ambient",
+        ]),
+    ),
+    section_type(
+        "medium",
+        false,
+        Some(&["density", "color", "steps", "distance"]),
+    ),
+    section_type("include", false, None),
+    section_type("vars", false, None),
+    section_type("material", true, Some(&[])),
+    section_type("defaults", true, Some(&[])),
+    section_type("animation", false, Some(&["position", "look_at", "up"])),
+    section_type(
+        "camera",
+        false,
+        Some(&[
+            "position",
+            "rotation",
+            "look_at",
+            "up",
+            "fov",
+            "projection",
+            "aperture",
+            "focus_distance",
+            "lens_samples",
+            "eye_separation",
+            "near",
+            "far",
+        ]),
+    ),
+    section_type(
+        "projection plane",
+        false,
+        Some(&["x_min", "y_min", "x_max", "y_max", "z"]),
+    ),
+    section_type(
+        "projection_plane",
+        false,
+        Some(&["x_min", "y_min", "x_max", "y_max", "z"]),
+    ),
+    section_type(
+        "light",
+        false,
+        Some(&[
+            "position",
+            "intensity",
+            "i_p",
+            "attenuation",
+            "c_1",
+            "c1",
+            "c_2",
+            "c2",
+            "c_3",
+            "c3",
+            "color",
+            "direction",
+            "angle",
+            "falloff_exponent",
+            "ies",
+            "radius",
+            "shadow_samples",
+            "affects",
+            "excludes",
+            "cast_shadows",
+            "diffuse",
+            "specular",
+        ]),
+    ),
+    section_type("sphere", true, Some(&["center", "radius", "r"])),
+    section_type(
+        "ellipsoid",
+        true,
+        Some(&["center", "rx", "ry", "rz", "rotation"]),
+    ),
+    section_type(
+        "cylinder",
+        true,
+        Some(&[
+            "anchor",
+            "dir",
+            "direction",
+            "radius",
+            "r",
+            "length",
+            "caps",
+        ]),
+    ),
+    section_type(
+        "cone",
+        true,
+        Some(&["anchor", "dir", "direction", "length", "k1", "k2"]),
+    ),
+    section_type("plane", true, Some(&["point", "normal"])),
+    section_type("disc", true, Some(&["center", "radius", "r", "normal"])),
+    section_type(
+        "ring",
+        true,
+        Some(&["center", "r_inner", "r_outer", "normal"]),
+    ),
+    section_type("triangle", true, Some(&["a", "b", "c", "na", "nb", "nc"])),
+    section_type("polygon", true, Some(&["vertices"])),
+    section_type(
+        "torus",
+        true,
+        Some(&["center", "axis", "major_radius", "minor_radius"]),
+    ),
+    section_type(
+        "obj",
+        true,
+        Some(&["path", "position", "scale", "rotation"]),
+    ),
+    section_type("patch", true, Some(&["points", "subdivisions"])),
+    section_type(
+        "sdf",
+        true,
+        Some(&[
+            "type",
+            "center",
+            "rotation",
+            "size",
+            "radius",
+            "major_radius",
+            "minor_radius",
+            "length",
+        ]),
+    ),
+    section_type("metaballs", true, Some(&["balls", "threshold"])),
+    section_type(
+        "heightfield",
+        true,
+        Some(&[
+            "image",
+            "position",
+            "width",
+            "depth",
+            "height_scale",
+            "resolution",
+            "noise_frequency",
+            "octaves",
+            "seed",
+        ]),
+    ),
+    section_type("csg", true, Some(&["operation", "left", "right"])),
+    section_type(
+        "instance",
+        false,
+        Some(&["object", "matrix", "position", "rotation", "scale", "group"]),
+    ),
+    section_type(
+        "group",
+        false,
+        Some(&["matrix", "position", "rotation", "scale", "group"]),
+    ),
+];
+
+/// Warnings about the sections and fields of a config file that aren't read by the raytracer,
+/// usually typos
+pub fn find_unknown_fields<P: AsRef<Path>>(
+    path: P,
+    overrides: &[ConfigOverride],
+) -> Result<Vec<String>> {
+    let path = path.as_ref();
+    let config = read_ini(path, ConfigFormat::from_path(path), overrides)?;
+    let mut warnings = Vec::new();
+
+    for section in config.sections().iter().sorted() {
+        let section_type = match SECTION_TYPES.iter().find(|t| section.starts_with(t.prefix)) {
+            Some(t) => t,
+            None => {
+                warnings.push(config.located(
+                    section,
+                    "",
+                    format!(
+                        "The section '{}' isn't of any known type, its name must start with one of: {}",
+                        section,
+                        SECTION_TYPES.iter().map(|t| t.prefix).join(", ")
+                    ),
+                ));
+                continue;
+            }
         };
 
-        Ok(Observer {
-            camera,
-            min_p,
-            max_p,
-            plane_z,
-        })
+        let fields = match section_type.fields {
+            Some(fields) => fields,
+            None => continue,
+        };
+        let keys = config.get_map_ref()[section].keys().sorted();
+        for key in keys {
+            let known = fields.contains(&key.as_str())
+                || (section_type.is_object && OBJECT_FIELDS.contains(&key.as_str()));
+            if !known {
+                warnings.push(config.located(
+                    section,
+                    key,
+                    format!(
+                        "The field '{}' in section '{}' isn't used by a {} section",
+                        key, section, section_type.prefix
+                    ),
+                ));
+            }
+        }
     }
-}
 
-fn get_float_default(config: &Ini, section: &str, key: &str, default: f64) -> Result<f64> {
-    Ok(config
-        .getfloat(section, key)
-        .map_err(|s| anyhow!(s))?
-        .unwrap_or(default))
+    Ok(warnings)
 }
 
-fn get_float_fails(config: &Ini, section: &str, key: &str) -> Result<f64> {
-    config
-        .getfloat(section, key)
-        .map_err(|s| anyhow!(s))?
-        .ok_or_else(|| anyhow!("Missing attribute '{}' for {} in config file", key, section))
+/// Errors found while reading the sections of a config file, kept so that all of them are reported
+/// at once instead of one per run
+#[derive(Debug, Default)]
+struct ConfigErrors(Vec<Error>);
+
+impl ConfigErrors {
+    /// Runs the reading of a section (or field), keeping its errors if it fails
+    fn check<T>(&mut self, read: impl FnOnce() -> Result<T>) -> Option<T> {
+        read()
+            .map_err(|e| match e.downcast::<ConfigErrors>() {
+                Ok(errors) => self.0.extend(errors.0),
+                Err(e) => self.0.push(e),
+            })
+            .ok()
+    }
+
+    fn into_result(mut self) -> Result<()> {
+        match self.0.len() {
+            0 => Ok(()),
+            1 => Err(self.0.remove(0)),
+            _ => Err(Error::new(self)),
+        }
+    }
 }
 
-fn get_color_fails(config: &Ini, section: &str) -> Result<Color> {
-    Color::from_hex(&config.get(section, "color").ok_or_else(|| {
-        anyhow!(
-            "Missing color attribute in section '{}' in config file",
-            section
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Found {} errors in the config file:\n{}",
+            self.0.len(),
+            self.0.iter().map(|e| format!("  - {:#}", e)).join("\n")
         )
-    })?)
+    }
 }
 
-fn get_color_default(config: &Ini, section: &str, key: &str, default: &str) -> Result<Color> {
-    Color::from_hex(config.get(section, key).as_deref().unwrap_or(default))
+impl std::error::Error for ConfigErrors {}
+
+/// Formats a config file can be written in, all of them describe the same sections and fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Ini,
+    /// Object of sections, each an object of fields
+    Json,
+    /// Tables as sections
+    Toml,
 }
 
-fn get_vec3_fails(config: &Ini, section: &str, key: &str) -> Result<Vec3> {
-    let mut vec_string = config.get(section, key).ok_or_else(|| {
-        anyhow!(
-            "Missing vector attribute '{}' in section {} of config file",
-            key,
-            section
-        )
-    })?;
+impl ConfigFormat {
+    /// Picked by the file's extension, any extension other than .json or .toml is read as INI
+    pub fn from_path<P: AsRef<Path>>(path: P) -> ConfigFormat {
+        match path
+            .as_ref()
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .as_deref()
+        {
+            Some("json") => ConfigFormat::Json,
+            Some("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Ini,
+        }
+    }
+}
+
+/// Field of a JSON or TOML config file, turned into the text an INI field would have
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "expected a bool, number, string or list")]
+enum FieldValue {
+    Bool(bool),
+    Number(f64),
+    Text(String),
+    /// A list of numbers is a vec and a list of lists is a list of vecs
+    List(Vec<FieldValue>),
+}
 
-    let first_char: char = vec_string.trim().chars().next().unwrap();
+impl FieldValue {
+    fn to_ini(&self) -> String {
+        match self {
+            FieldValue::Bool(b) => b.to_string(),
+            FieldValue::Number(n) => n.to_string(),
+            FieldValue::Text(t) => t.clone(),
+            FieldValue::List(items)
+                if !items.is_empty() && items.iter().all(|i| matches!(i, FieldValue::List(_))) =>
+            {
+                items.iter().map(FieldValue::to_ini).join(", ")
+            }
+            FieldValue::List(items) => {
+                format!("({})", items.iter().map(FieldValue::to_ini).join(", "))
+            }
+        }
+    }
+}
 
-    let valid_delimiters: Option<[&str; 2]> = match first_char {
-        '[' => Some(["[", "]"]),
-        '(' => Some(["(", ")"]),
-        '0'..='9' => None,
-        _ => return Err(anyhow!("In vector attribute '{}' in section {} the first element is not a valid delimiter or a valid number: {}", key, section, first_char)),
+/// Reads a JSON or TOML config file into the same structure an INI file is read into
+fn load_structured(path: &Path, format: ConfigFormat) -> Result<Ini> {
+    let contents = fs::read_to_string(path)?;
+    let sections: BTreeMap<String, BTreeMap<String, FieldValue>> = match format {
+        ConfigFormat::Json => serde_json::from_str(&contents)?,
+        ConfigFormat::Toml => toml::from_str(&contents)?,
+        ConfigFormat::Ini => unreachable!("INI files are read by configparser"),
     };
 
-    if let Some(delimiters) = valid_delimiters {
-        vec_string = vec_string
-            .trim()
-            .strip_prefix(delimiters[0])
-            .unwrap()
-            .strip_suffix(delimiters[1])
-            .ok_or_else(|| {
-                anyhow!(
-                    "In vector attribute '{}' in section {} the vector isn't terminated by the matching closing delimiter '{}'",
-                    key,
-                    section,
-                    delimiters[1]
-                )
-            })?.to_string();
+    let mut config = Ini::new();
+    for (section, fields) in sections {
+        for (key, value) in fields {
+            config.set(&section, &key, Some(value.to_ini()));
+        }
+    }
+
+    Ok(config)
+}
+
+/// Loads a config file with its includes, applies the overrides and replaces every
+/// `${expression}` in its values
+fn read_ini(path: &Path, format: ConfigFormat, overrides: &[ConfigOverride]) -> Result<Config> {
+    let mut config = load_scene_ini(path, format, &mut Vec::new(), &mut Vec::new())?;
+    for o in overrides {
+        config.set(&o.section, &o.key, Some(o.value.clone()));
+        config
+            .locations
+            .remove(&(o.section.to_lowercase(), o.key.to_lowercase()));
+    }
+    substitute_vars(&mut config)?;
+    Ok(config)
+}
+
+/// Fields of a config file along with the files and lines they were written in, the lines are
+/// only known for INI files
+struct Config {
+    ini: Ini,
+    /// Keyed by (section, key), where the key is empty for the section's header
+    locations: HashMap<(String, String), Location>,
+}
+
+#[derive(Debug, Clone)]
+struct Location {
+    file: PathBuf,
+    line: Option<usize>,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}", self.file.display(), line),
+            None => write!(f, "{}", self.file.display()),
+        }
+    }
+}
+
+impl Deref for Config {
+    type Target = Ini;
+
+    fn deref(&self) -> &Ini {
+        &self.ini
+    }
+}
+
+impl DerefMut for Config {
+    fn deref_mut(&mut self) -> &mut Ini {
+        &mut self.ini
+    }
+}
+
+impl Config {
+    /// Config of a JSON or TOML file, whose fields are only located by their file
+    fn without_lines(path: &Path, ini: Ini) -> Config {
+        let mut locations = HashMap::new();
+        for (section, keys) in ini.get_map_ref() {
+            for key in keys.keys().chain([&String::new()]) {
+                let location = Location {
+                    file: path.to_path_buf(),
+                    line: None,
+                };
+                locations.insert((section.clone(), key.clone()), location);
+            }
+        }
+        Config { ini, locations }
+    }
+
+    /// Reads the field, prefixing the error with the line of the field, or with the line of the
+    /// section if the field is missing
+    fn locate<T>(&self, section: &str, key: &str, read: impl FnOnce() -> Result<T>) -> Result<T> {
+        read().map_err(|e| {
+            let key = if self.get(section, key).is_some() {
+                key
+            } else {
+                ""
+            };
+            match self.location(section, key) {
+                Some(location) => anyhow!("{}: {:#}", location, e),
+                None => e,
+            }
+        })
+    }
+
+    /// Where the field was written, or the section's header for an empty key
+    fn location(&self, section: &str, key: &str) -> Option<&Location> {
+        self.locations
+            .get(&(section.to_lowercase(), key.to_lowercase()))
+    }
+
+    /// `message` starting with where the field was written, if it's known
+    fn located(&self, section: &str, key: &str, message: String) -> String {
+        match self.location(section, key) {
+            Some(location) => format!("{}: {}", location, message),
+            None => message,
+        }
+    }
+
+    /// Path given by the field, relative paths are relative to the file the field was written in
+    /// (or to the working directory if it was given from the command line)
+    fn path(&self, section: &str, key: &str) -> Option<PathBuf> {
+        let path = PathBuf::from(self.get(section, key)?.trim());
+        let dir = self
+            .location(section, key)
+            .and_then(|location| location.file.parent());
+        Some(match dir {
+            Some(dir) if path.is_relative() => dir.join(path),
+            _ => path,
+        })
+    }
+}
+
+/// Finds the lines of the sections and fields of an INI file, following the same rules as the
+/// parser: the first header of a section and the last line setting a field count
+fn ini_locations(path: &Path, contents: &str) -> HashMap<(String, String), Location> {
+    let mut locations = HashMap::new();
+    let mut section = "default".to_string();
+
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        let location = Location {
+            file: path.to_path_buf(),
+            line: Some(i + 1),
+        };
+
+        if line.is_empty() || line.starts_with(';') || line.starts_with('"') {
+            continue;
+        } else if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = header.trim().to_lowercase();
+            locations
+                .entry((section.clone(), String::new()))
+                .or_insert(location);
+        } else {
+            let key = line.split(['=', ':']).next().unwrap_or(line);
+            locations.insert((section.clone(), key.trim().to_lowercase()), location);
+        }
+    }
+
+    locations
+}
+
+/// Value given from the command line as `section.key=value` that replaces (or adds) a field of
+/// the config, like `--set "light a.intensity=0.5"`
+#[derive(Debug, Clone)]
+pub struct ConfigOverride {
+    section: String,
+    key: String,
+    value: String,
+}
+
+impl FromStr for ConfigOverride {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<ConfigOverride> {
+        let (field, value) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected 'section.key=value' but found '{}'", s))?;
+        let (section, key) = field.rsplit_once('.').ok_or_else(|| {
+            anyhow!(
+                "Expected 'section.key' before the '=' but found '{}'",
+                field
+            )
+        })?;
+
+        if section.trim().is_empty() || key.trim().is_empty() {
+            return Err(anyhow!("The section and key in '{}' can't be empty", field));
+        }
+
+        Ok(ConfigOverride {
+            section: section.trim().to_string(),
+            key: key.trim().to_string(),
+            value: value.trim().to_string(),
+        })
     }
+}
+
+/// The config file and every file it includes, the included files that can be read before an
+/// error is found are still listed
+pub fn config_files<P: AsRef<Path>>(path: P) -> Vec<PathBuf> {
+    let path = path.as_ref();
+    let mut loaded = Vec::new();
+    let _ = load_scene_ini(
+        path,
+        ConfigFormat::from_path(path),
+        &mut Vec::new(),
+        &mut loaded,
+    );
+
+    // the file itself couldn't be read
+    if loaded.is_empty() {
+        loaded.push(path.to_path_buf());
+    }
+    loaded
+}
+
+/// Fields of the `[vars]` section, which are expressions that may use other vars, evaluated
+/// lazily
+struct Vars {
+    expressions: HashMap<String, String>,
+    values: HashMap<String, f64>,
+    /// Vars being evaluated, to catch cycles
+    evaluating: Vec<String>,
+}
+
+impl Vars {
+    fn get(&mut self, name: &str) -> Result<f64> {
+        let name = name.to_lowercase();
+        if let Some(&value) = self.values.get(&name) {
+            return Ok(value);
+        }
 
-    let num_strs = vec_string.split(',');
-    let floats: Vec<f64> = num_strs
-        .map(|s| s.trim().parse::<f64>().map_err(Error::msg))
-        .collect::<Result<Vec<f64>>>().context(format!("In vector attribute '{}' in section {} the vector's elements aren't valid floating point numbers", key, section))?;
+        let expression = self
+            .expressions
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| anyhow!("The var '{}' isn't defined in the [vars] section", name))?;
+        if self.evaluating.contains(&name) {
+            return Err(anyhow!(
+                "The var '{}' depends on itself through: {}",
+                name,
+                self.evaluating.join(" -> ")
+            ));
+        }
+
+        self.evaluating.push(name.clone());
+        let value = evaluate(&expression, &mut |var| self.get(var))
+            .with_context(|| format!("In the var '{}'", name))?;
+        self.evaluating.pop();
 
-    if floats.len() != 3 {
-        return Err(anyhow!("In vector attribute '{}' in section {} the vector supplied should be 3-dimensional and it's currently {}-dimensional", key, section, floats.len()));
+        self.values.insert(name, value);
+        Ok(value)
     }
 
-    Ok(Vec3::new(floats[0], floats[1], floats[2]))
+    /// Replaces every `${expression}` in the string by the expression's value
+    fn substitute(&mut self, string: &str) -> Result<String> {
+        let mut substituted = String::new();
+        let mut rest = string;
+
+        while let Some(start) = rest.find("${") {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| anyhow!("'${{' without a matching '}}'"))?;
+            let value = evaluate(&rest[start + 2..start + end], &mut |var| self.get(var))?;
+
+            substituted.push_str(&rest[..start]);
+            substituted.push_str(&value.to_string());
+            rest = &rest[start + end + 1..];
+        }
+        substituted.push_str(rest);
+
+        Ok(substituted)
+    }
 }
 
-fn get_params(config: &Ini, section: &str) -> Result<ObjectParameters> {
-    let color = get_color_fails(config, section)?;
-    let k_d = get_float_fails(config, section, "k_d")?.clamp(0.0, 1.0);
-    let k_a = get_float_default(config, section, "k_a", 1.0)?.clamp(0.0, 1.0);
-    let k_s = get_float_fails(config, section, "k_s")?.clamp(0.0, 1.0);
-    let k_n = get_float_default(config, section, "k_n", DEFAULT_HARDNESS)?.max(1.0);
-    let reflection = get_float_default(config, section, "reflection", 0.0)?.clamp(0.0, 1.0);
-    let transparency = get_float_default(config, section, "transparency", 0.0)?.clamp(0.0, 1.0);
-    let checkerboard = get_float_default(config, section, "checkerboard", 0.0)?.max(0.0);
+fn substitute_vars(config: &mut Ini) -> Result<()> {
+    let mut vars = Vars {
+        expressions: config
+            .get_map_ref()
+            .get("vars")
+            .map(|keys| {
+                keys.iter()
+                    .filter_map(|(k, v)| Some((k.clone(), v.clone()?)))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        values: HashMap::new(),
+        evaluating: Vec::new(),
+    };
+
+    let mut substitutions = Vec::new();
+    for (section, keys) in config.get_map_ref() {
+        for (key, value) in keys {
+            match value {
+                Some(value) if section != "vars" && value.contains("${") => {
+                    let substituted = vars
+                        .substitute(value)
+                        .with_context(|| format!("In field '{}' of section '{}'", key, section))?;
+                    substitutions.push((section.clone(), key.clone(), substituted));
+                }
+                _ => (),
+            }
+        }
+    }
+
+    for (section, key, value) in substitutions {
+        config.set(&section, &key, Some(value));
+    }
+
+    Ok(())
+}
+
+/// Loads a config file along with the files listed in its `[include]` section, whose paths are
+/// relative to the file including them. Included files are merged key by key, without replacing
+/// the keys the including file already has, so a scene can override single keys of a library.
+/// `including` holds the files being loaded to catch cycles, and every file that gets read is added
+/// to `loaded`
+fn load_scene_ini(
+    path: &Path,
+    format: ConfigFormat,
+    including: &mut Vec<PathBuf>,
+    loaded: &mut Vec<PathBuf>,
+) -> Result<Config> {
+    let canonical = path
+        .canonicalize()
+        .with_context(|| format!("Couldn't read scene file '{}'", path.display()))?;
+    if !loaded.contains(&canonical) {
+        loaded.push(canonical.clone());
+    }
+    if including.contains(&canonical) {
+        return Err(anyhow!(
+            "Scene file '{}' includes itself through: {}",
+            path.display(),
+            including
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect::<Vec<String>>()
+                .join(" -> ")
+        ));
+    }
+
+    let mut config = match format {
+        ConfigFormat::Ini => fs::read_to_string(path)
+            .map_err(Error::msg)
+            .and_then(|contents| {
+                let mut ini = Ini::new();
+                ini.set_comment_symbols(&[';', '"']);
+                let locations = ini_locations(path, &contents);
+                ini.read(contents)
+                    .map_err(|s| anyhow!(s))
+                    .map(|_| Config { ini, locations })
+            }),
+        ConfigFormat::Json | ConfigFormat::Toml => {
+            load_structured(path, format).map(|ini| Config::without_lines(path, ini))
+        }
+    }
+    .with_context(|| format!("Couldn't read scene file '{}'", path.display()))?;
+
+    // sorted by key so that the first include wins when several set the same key
+    let includes: Vec<String> = config
+        .get_map_ref()
+        .get("include")
+        .map(|keys| {
+            keys.iter()
+                .sorted_by(|(k1, _), (k2, _)| k1.cmp(k2))
+                .filter_map(|(_, p)| p.as_ref().map(|p| p.trim().to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    including.push(canonical);
+    for include in includes {
+        let include_path = path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(&include);
+        let included = load_scene_ini(
+            &include_path,
+            ConfigFormat::from_path(&include_path),
+            including,
+            loaded,
+        )
+        .with_context(|| format!("In file included from '{}'", path.display()))?;
+
+        for (section, keys) in included.get_map_ref() {
+            if section == "include" {
+                continue;
+            }
+
+            let header = (section.clone(), String::new());
+            if let Some(location) = included.locations.get(&header) {
+                config
+                    .locations
+                    .entry(header)
+                    .or_insert_with(|| location.clone());
+            }
+
+            for (key, value) in keys {
+                if config.get(section, key).is_none() {
+                    config.set(section, key, value.clone());
+
+                    let field = (section.clone(), key.clone());
+                    match included.locations.get(&field) {
+                        Some(location) => config.locations.insert(field, location.clone()),
+                        None => config.locations.remove(&field),
+                    };
+                }
+            }
+        }
+    }
+    including.pop();
+
+    Ok(config)
+}
+
+impl Observer {
+    pub fn read_config<P: AsRef<Path>>(
+        path: P,
+        overrides: &[ConfigOverride],
+        camera: Option<&str>,
+    ) -> Result<Observer> {
+        Observer::read_file(path.as_ref(), ConfigFormat::Ini, overrides, camera)
+    }
+
+    pub fn read_json<P: AsRef<Path>>(
+        path: P,
+        overrides: &[ConfigOverride],
+        camera: Option<&str>,
+    ) -> Result<Observer> {
+        Observer::read_file(path.as_ref(), ConfigFormat::Json, overrides, camera)
+    }
+
+    pub fn read_toml<P: AsRef<Path>>(
+        path: P,
+        overrides: &[ConfigOverride],
+        camera: Option<&str>,
+    ) -> Result<Observer> {
+        Observer::read_file(path.as_ref(), ConfigFormat::Toml, overrides, camera)
+    }
+
+    /// Reads the camera with the given name, or the unnamed one if there's no name
+    fn read_file(
+        path: &Path,
+        format: ConfigFormat,
+        overrides: &[ConfigOverride],
+        camera: Option<&str>,
+    ) -> Result<Observer> {
+        let config = read_ini(path, format, overrides)?;
+        let (camera_section, plane_section) = get_camera_sections(&config, camera)?;
+
+        let camera = get_vec3_fails(&config, &camera_section, "position")?;
+        let projection = get_projection(&config, &camera_section)?;
+
+        let mut observer = if config.get(&camera_section, "look_at").is_some() {
+            if config.get(&camera_section, "rotation").is_some() {
+                return Err(anyhow!(
+                    "In section '{}' the camera can't have both a 'look_at' point and a 'rotation'",
+                    camera_section
+                ));
+            }
+            Observer::look_at(&config, &camera_section, camera, projection)?
+        } else {
+            let rotation = get_vec3_default(
+                &config,
+                &camera_section,
+                "rotation",
+                Vec3::new(0.0, 0.0, 0.0),
+            )?;
+            Observer::projection_plane(&config, &plane_section, camera, projection)?
+                .rotated(rotation)
+        };
+        observer.lens = get_lens(&config, &camera_section, &observer)?;
+        (observer.near, observer.far) = get_clipping(&config, &camera_section)?;
+        observer.eye_separation = get_float_default(
+            &config,
+            &camera_section,
+            "eye_separation",
+            observer.lens.focus_distance / EYE_SEPARATION_RATIO,
+        )?;
+        observer.animation = get_animation(&config, &camera_section)?;
+
+        Ok(observer)
+    }
+
+    /// Camera at time `t` (between 0 and 1) of its animation, with the same field of view. It's
+    /// the same camera if there's no animation.
+    pub fn at_time(&self, t: f64) -> Result<Observer> {
+        let animation = match &self.animation {
+            Some(animation) => animation,
+            None => return Ok(self.clone()),
+        };
+
+        let camera = interpolate_keyframes(&animation.positions, t);
+        let center = self.plane_point(0.5, 0.5);
+        let target = if animation.look_ats.is_empty() {
+            camera + (center - self.camera)
+        } else {
+            interpolate_keyframes(&animation.look_ats, t)
+        };
+
+        let forward = target - camera;
+        let right = animation.up.cross(forward);
+        if forward.near_zero() || right.near_zero() {
+            return Err(anyhow!(
+                "At time {:.3} of the animation the camera looks straight along 'up' or at its own position",
+                t
+            ));
+        }
+
+        // the plane goes through the target, as wide relative to its distance as it was
+        let scale = forward.norm() / (center - self.camera).norm();
+        let horizontal = scale * self.horizontal.norm() * right.normalize();
+        let vertical = scale * self.vertical.norm() * forward.cross(right).normalize();
+
+        Ok(Observer {
+            camera,
+            corner: target - horizontal / 2.0 - vertical / 2.0,
+            horizontal,
+            vertical,
+            ..self.clone()
+        })
+    }
+
+    /// Camera of one eye for stereo images, moved along the image's horizontal by `side` times
+    /// the eye separation (-0.5 for the left eye and 0.5 for the right one). The projection plane
+    /// stays put so both eyes see the same thing on it.
+    pub fn eye(&self, side: f64) -> Observer {
+        Observer {
+            camera: self.camera + side * self.eye_separation * self.horizontal.normalize(),
+            ..self.clone()
+        }
+    }
+
+    /// Camera looking through the projection plane given in its section
+    fn projection_plane(
+        config: &Config,
+        plane_section: &str,
+        camera: Vec3,
+        projection: Projection,
+    ) -> Result<Observer> {
+        let plane_z = get_float_default(config, plane_section, "z", 0.0)?;
+
+        let min_p = Vec3 {
+            x: get_float_fails(config, plane_section, "x_min")?,
+            y: get_float_fails(config, plane_section, "y_min")?,
+            z: plane_z,
+        };
+
+        let max_p = Vec3 {
+            x: get_float_fails(config, plane_section, "x_max")?,
+            y: get_float_fails(config, plane_section, "y_max")?,
+            z: plane_z,
+        };
+
+        Ok(Observer {
+            camera,
+            corner: min_p,
+            horizontal: Vec3::new(max_p.x - min_p.x, 0.0, 0.0),
+            vertical: Vec3::new(0.0, max_p.y - min_p.y, 0.0),
+            projection,
+            lens: Lens::default(),
+            near: 0.0,
+            far: None,
+            aspect: 1.0,
+            eye_separation: 0.0,
+            animation: None,
+        })
+    }
+
+    /// Same camera with its projection plane turned around it by the given angles (in degrees)
+    /// around the x, y and z axes, in that order
+    fn rotated(self, rotation: Vec3) -> Observer {
+        let basis = Onb::from_euler(
+            rotation.x.to_radians(),
+            rotation.y.to_radians(),
+            rotation.z.to_radians(),
+        );
+
+        Observer {
+            corner: self.camera + basis.local_to_world(self.corner - self.camera),
+            horizontal: basis.local_to_world(self.horizontal),
+            vertical: basis.local_to_world(self.vertical),
+            ..self
+        }
+    }
+
+    /// Camera pointed at the `look_at` point, with its projection plane going through that point
+    /// and as wide as the field of view. Only the plane's orientation matters for the other
+    /// projections, which read the field of view themselves.
+    fn look_at(
+        config: &Config,
+        section: &str,
+        camera: Vec3,
+        projection: Projection,
+    ) -> Result<Observer> {
+        let look_at = get_vec3_fails(config, section, "look_at")?;
+        let up = get_vec3_default(config, section, "up", Vec3::new(0.0, 1.0, 0.0))?;
+        let fov = match projection {
+            Projection::Perspective => get_float_default(config, section, "fov", DEFAULT_FOV)?,
+            _ => DEFAULT_FOV,
+        };
+
+        let forward = look_at - camera;
+        if forward.near_zero() {
+            return Err(anyhow!(
+                "In section '{}' the 'look_at' point must be different from the camera's 'position'",
+                section
+            ));
+        }
+        if fov <= 0.0 || fov >= 180.0 {
+            return Err(anyhow!(
+                "In section '{}' the 'fov' must be between 0 and 180 degrees",
+                section
+            ));
+        }
+
+        let right = up.cross(forward);
+        if right.near_zero() {
+            return Err(anyhow!(
+                "In section '{}' the 'up' vector can't be zero or parallel to the direction the camera looks in",
+                section
+            ));
+        }
+
+        let half_width = forward.norm() * (fov.to_radians() / 2.0).tan();
+        let horizontal = 2.0 * half_width * right.normalize();
+        let vertical = 2.0 * half_width * forward.cross(right).normalize();
+
+        Ok(Observer {
+            camera,
+            corner: look_at - horizontal / 2.0 - vertical / 2.0,
+            horizontal,
+            vertical,
+            projection,
+            lens: Lens::default(),
+            near: 0.0,
+            far: None,
+            aspect: 1.0,
+            eye_separation: 0.0,
+            animation: None,
+        })
+    }
+
+    /// Makes the projection plane as high as needed for the pixels of an image with the given
+    /// size to be square, keeping its width and center
+    pub fn fit_to_image(&mut self, width: u32, height: u32) {
+        self.aspect = f64::from(width) / f64::from(height);
+
+        let center = self.plane_point(0.5, 0.5);
+        self.vertical = self.vertical.normalize() * (self.horizontal.norm() / self.aspect);
+        self.corner = center - self.horizontal / 2.0 - self.vertical / 2.0;
+    }
+
+    /// Ray seen at (u, v) of the image, going from (0, 0) at the bottom left corner to (1, 1) at
+    /// the top right one. Fisheye images have no ray outside of their circle.
+    pub fn ray(&self, u: f64, v: f64) -> Option<Ray> {
+        let (forward, right, up) = self.frame();
+
+        let dir = match self.projection {
+            Projection::Perspective => {
+                return Some(Ray::from_2_points(self.camera, self.plane_point(u, v)))
+            }
+            Projection::Fisheye { fov } => {
+                // the circle fits in the image's shorter side
+                let (x, y) = (
+                    (2.0 * u - 1.0) * self.aspect.max(1.0),
+                    (2.0 * v - 1.0) / self.aspect.min(1.0),
+                );
+                let r = (x * x + y * y).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+                let theta = r * fov / 2.0;
+                let phi = y.atan2(x);
+                theta.cos() * forward + theta.sin() * (phi.cos() * right + phi.sin() * up)
+            }
+            Projection::Panorama => {
+                let phi = (u - 0.5) * 2.0 * PI;
+                let theta = (1.0 - v) * PI;
+                theta.sin() * (phi.sin() * right + phi.cos() * forward) + theta.cos() * up
+            }
+        };
+
+        Some(Ray {
+            anchor: self.camera,
+            dir: dir.normalize(),
+        })
+    }
+
+    /// Rays seen at (u, v) of the image from every sample of the lens, meeting at the focus
+    /// distance. A pinhole camera only has the one ray given by `ray`.
+    pub fn lens_rays(&self, u: f64, v: f64, sampler: Sampler, rng: &mut SampleRng) -> Vec<Ray> {
+        let ray = match self.ray(u, v) {
+            Some(ray) => ray,
+            None => return Vec::new(),
+        };
+        if self.lens.aperture <= 0.0 {
+            return vec![ray];
+        }
+
+        let (forward, right, up) = self.frame();
+
+        // perspective cameras focus on a plane, the others on a sphere around the camera
+        let distance = match self.projection {
+            Projection::Perspective => self.lens.focus_distance / ray.dir.dot(forward),
+            _ => self.lens.focus_distance,
+        };
+        let focus = ray.point_at_t(distance);
+
+        samples_2d(self.lens.samples, sampler, rng)
+            .into_iter()
+            .map(|(a, b)| {
+                let (x, y) = square_to_disc(a, b);
+                let offset = self.lens.aperture / 2.0 * (x * right + y * up);
+                Ray::from_2_points(self.camera + offset, focus)
+            })
+            .collect()
+    }
+
+    /// Camera ray moved forward to the near clipping distance, along with how far it can go from
+    /// there before the far one
+    pub fn clip(&self, ray: Ray) -> (Ray, f64) {
+        let far = self.far.map_or(f64::INFINITY, |far| far - self.near);
+        (ray.advance(self.near), far)
+    }
+
+    /// Directions the camera looks along and that are right and up in the image. The center of
+    /// the projection plane is straight ahead.
+    fn frame(&self) -> (Vec3, Vec3, Vec3) {
+        (
+            (self.plane_point(0.5, 0.5) - self.camera).normalize(),
+            self.horizontal.normalize(),
+            self.vertical.normalize(),
+        )
+    }
+
+    /// Point of the projection plane at (u, v), going from (0, 0) at the bottom left corner of
+    /// the image to (1, 1) at the top right one
+    pub fn plane_point(&self, u: f64, v: f64) -> Vec3 {
+        self.corner + u * self.horizontal + v * self.vertical
+    }
+}
+
+/// Names of sections given as a comma separated list, which must all exist
+fn get_section_names(config: &Config, section: &str, key: &str) -> Result<Vec<String>> {
+    let names: Vec<String> = config
+        .get(section, key)
+        .unwrap_or_default()
+        .split(',')
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect();
+
+    for name in &names {
+        if !config.sections().contains(name) {
+            return Err(anyhow!(
+                "In section '{}' the '{}' lists '{}', which isn't a section of the scene",
+                section,
+                key,
+                name
+            ));
+        }
+    }
+    Ok(names)
+}
+
+/// Factors c1, c2 and c3 of the light's attenuation 1 / (c1 + c2*d + c3*d²), given by a named
+/// `attenuation` model or written out for the custom one. Lights that give the factors without a
+/// model are custom and the ones that give neither aren't attenuated.
+fn get_attenuation_factors(config: &Config, section: &str) -> Result<(f64, f64, f64)> {
+    let keys = ["c_1", "c1", "c_2", "c2", "c_3", "c3"];
+    let has_factors = keys.iter().any(|key| config.get(section, key).is_some());
+
+    let model = config
+        .get(section, "attenuation")
+        .map(|model| model.to_lowercase())
+        .unwrap_or_else(|| if has_factors { "custom" } else { "none" }.to_string());
+
+    if model != "custom" && has_factors {
+        return Err(anyhow!(
+            "In section '{}' the attenuation factors c1, c2 and c3 are only used by the custom attenuation, not by '{}'",
+            section,
+            model
+        ));
+    }
+
+    match model.as_str() {
+        "none" => Ok((1.0, 0.0, 0.0)),
+        "linear" => Ok((0.0, 1.0, 0.0)),
+        "inverse_square" => Ok((0.0, 0.0, 1.0)),
+        "custom" => Ok((
+            get_float_fails(config, section, "c_1")
+                .or_else(|_| get_float_fails(config, section, "C1"))?,
+            get_float_fails(config, section, "c_2")
+                .or_else(|_| get_float_fails(config, section, "C2"))?,
+            get_float_fails(config, section, "c_3")
+                .or_else(|_| get_float_fails(config, section, "C3"))?,
+        )),
+        other => Err(anyhow!(
+            "In section '{}' the attenuation '{}' isn't valid, it must be one of: none, linear, inverse_square, custom",
+            section,
+            other
+        )),
+    }
+}
+
+/// Cone of a light with a `direction`, which makes it a spot light
+fn get_spot(config: &Config, section: &str) -> Result<Option<Spot>> {
+    if config.get(section, "direction").is_none() {
+        return Ok(None);
+    }
+
+    let direction = get_vec3_fails(config, section, "direction")?;
+    if direction.near_zero() {
+        return Err(anyhow!(
+            "In section '{}' the 'direction' can't be zero",
+            section
+        ));
+    }
+    let angle = get_float_default(config, section, "angle", DEFAULT_SPOT_ANGLE)?;
+    if angle <= 0.0 || angle > 180.0 {
+        return Err(anyhow!(
+            "In section '{}' the 'angle' must be between 0 and 180 degrees",
+            section
+        ));
+    }
+    let falloff_exponent = get_float_default(config, section, "falloff_exponent", 1.0)?.max(0.0);
+
+    Ok(Some(Spot {
+        direction: direction.normalize(),
+        cos_angle: angle.to_radians().cos(),
+        falloff_exponent,
+    }))
+}
+
+/// Animation given in the `[animation]` section, if there's one
+fn get_animation(config: &Config, camera_section: &str) -> Result<Option<Animation>> {
+    if !config.sections().contains(&"animation".to_string()) {
+        return Ok(None);
+    }
+
+    let keyframes = |key: &str| -> Result<Vec<Vec3>> {
+        get_tuples_fails(config, "animation", key)?
+            .into_iter()
+            .map(|point| match point[..] {
+                [x, y, z] => Ok(Vec3::new(x, y, z)),
+                _ => Err(anyhow!(
+                    "In attribute '{}' in section animation every keyframe must be 3-dimensional",
+                    key
+                )),
+            })
+            .collect()
+    };
+
+    let positions = keyframes("position")?;
+    let look_ats = match config.get("animation", "look_at") {
+        Some(_) => keyframes("look_at")?,
+        None => Vec::new(),
+    };
+    let up = get_vec3_default(
+        config,
+        "animation",
+        "up",
+        get_vec3_default(config, camera_section, "up", Vec3::new(0.0, 1.0, 0.0))?,
+    )?;
+
+    Ok(Some(Animation {
+        positions,
+        look_ats,
+        up,
+    }))
+}
+
+/// Lens of the camera, focused by default on the center of the projection plane (the `look_at`
+/// point of cameras that have one)
+fn get_lens(config: &Config, section: &str, observer: &Observer) -> Result<Lens> {
+    let aperture = get_float_default(config, section, "aperture", 0.0)?;
+    let default_focus = (observer.plane_point(0.5, 0.5) - observer.camera).norm();
+    let focus_distance = get_float_default(config, section, "focus_distance", default_focus)?;
+    let samples = get_uint_default(config, section, "lens_samples", DEFAULT_LENS_SAMPLES)?;
+
+    if aperture < 0.0 {
+        return Err(anyhow!(
+            "In section '{}' the 'aperture' can't be negative",
+            section
+        ));
+    }
+    if focus_distance <= 0.0 {
+        return Err(anyhow!(
+            "In section '{}' the 'focus_distance' must be greater than 0",
+            section
+        ));
+    }
+    if samples == 0 {
+        return Err(anyhow!(
+            "In section '{}' the 'lens_samples' must be at least 1",
+            section
+        ));
+    }
+
+    Ok(Lens {
+        aperture,
+        focus_distance,
+        samples: samples as u32,
+    })
+}
+
+/// Near and far clipping distances of the camera, which only sees what's between them
+fn get_clipping(config: &Config, section: &str) -> Result<(f64, Option<f64>)> {
+    let near = get_float_default(config, section, "near", 0.0)?;
+    let far = config
+        .get(section, "far")
+        .map(|_| get_float_fails(config, section, "far"))
+        .transpose()?;
+
+    if near < 0.0 {
+        return Err(anyhow!(
+            "In section '{}' the 'near' distance can't be negative",
+            section
+        ));
+    }
+    if far.is_some_and(|far| far <= near) {
+        return Err(anyhow!(
+            "In section '{}' the 'far' distance must be greater than the 'near' one",
+            section
+        ));
+    }
+
+    Ok((near, far))
+}
+
+fn get_projection(config: &Config, section: &str) -> Result<Projection> {
+    let projection = match config
+        .get(section, "projection")
+        .as_deref()
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        Some("perspective") | None => Projection::Perspective,
+        Some("fisheye") => {
+            let fov = get_float_default(config, section, "fov", DEFAULT_FISHEYE_FOV)?;
+            if fov <= 0.0 || fov > 360.0 {
+                return Err(anyhow!(
+                    "In section '{}' the 'fov' of a fisheye camera must be between 0 and 360 degrees",
+                    section
+                ));
+            }
+            Projection::Fisheye {
+                fov: fov.to_radians(),
+            }
+        }
+        Some("panorama") => Projection::Panorama,
+        Some(other) => return Err(anyhow!("In section '{}' the projection '{}' isn't valid, it must be one of: perspective, fisheye, panorama", section, other)),
+    };
+    Ok(projection)
+}
+
+fn get_light_units(config: &Config) -> Result<LightUnits> {
+    match config
+        .get("scene", "light_units")
+        .as_deref()
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        Some("normalized") | None => Ok(LightUnits::Normalized),
+        Some("physical") => Ok(LightUnits::Physical),
+        Some(other) => Err(anyhow!("In section 'scene' the light_units '{}' aren't valid, they must be one of: normalized, physical", other)),
+    }
+}
+
+/// Sections of the named (or unnamed) camera and of its projection plane. A camera without a
+/// projection plane of its own uses the unnamed one.
+fn get_camera_sections(config: &Config, name: Option<&str>) -> Result<(String, String)> {
+    let sections = config.sections();
+    let name = name.map(|name| name.trim().to_lowercase());
+
+    let camera = match &name {
+        Some(name) => format!("camera {}", name),
+        None => "camera".to_string(),
+    };
+    if !sections.contains(&camera) {
+        let named_cameras = sections
+            .iter()
+            .filter_map(|s| s.strip_prefix("camera "))
+            .sorted()
+            .join(", ");
+        match &name {
+            Some(name) => {
+                return Err(anyhow!(
+                    "There's no camera named '{}', it must be defined in a section named '[camera {}]'. The available named cameras are: {}",
+                    name,
+                    name,
+                    named_cameras
+                ))
+            }
+            None if !named_cameras.is_empty() => {
+                return Err(anyhow!(
+                    "There's no unnamed [camera] section, pick one of the named cameras with '--camera': {}",
+                    named_cameras
+                ))
+            }
+            // the missing fields are reported when reading them
+            None => {}
+        }
+    }
+
+    let mut plane_candidates = Vec::new();
+    if let Some(name) = &name {
+        plane_candidates.push(format!("projection plane {}", name));
+        plane_candidates.push(format!("projection_plane {}", name));
+    }
+    plane_candidates.push("projection plane".to_string());
+    plane_candidates.push("projection_plane".to_string());
+
+    let plane = plane_candidates
+        .into_iter()
+        .find(|s| sections.contains(s))
+        .unwrap_or_else(|| "projection plane".to_string());
+
+    Ok((camera, plane))
+}
+
+fn get_float_default(config: &Config, section: &str, key: &str, default: f64) -> Result<f64> {
+    config.locate(section, key, || {
+        Ok(config
+            .getfloat(section, key)
+            .map_err(|s| anyhow!(s))?
+            .unwrap_or(default))
+    })
+}
+
+fn get_float_fails(config: &Config, section: &str, key: &str) -> Result<f64> {
+    config.locate(section, key, || {
+        config
+            .getfloat(section, key)
+            .map_err(|s| anyhow!(s))?
+            .ok_or_else(|| anyhow!("Missing attribute '{}' for {} in config file", key, section))
+    })
+}
+
+fn get_uint_default(config: &Config, section: &str, key: &str, default: u64) -> Result<u64> {
+    config.locate(section, key, || {
+        Ok(config
+            .getuint(section, key)
+            .map_err(|s| anyhow!(s))?
+            .unwrap_or(default))
+    })
+}
+
+fn get_bool_default(config: &Config, section: &str, key: &str, default: bool) -> Result<bool> {
+    config.locate(section, key, || {
+        Ok(config
+            .getboolcoerce(section, key)
+            .map_err(|s| anyhow!(s))?
+            .unwrap_or(default))
+    })
+}
+
+fn get_color_fails(config: &Config, section: &str) -> Result<Color> {
+    config.locate(section, "color", || {
+        Color::from_hex(&config.get(section, "color").ok_or_else(|| {
+            anyhow!(
+                "Missing color attribute in section '{}' in config file",
+                section
+            )
+        })?)
+        .with_context(|| format!("In color attribute in section '{}'", section))
+    })
+}
+
+fn get_color_default(config: &Config, section: &str, key: &str, default: &str) -> Result<Color> {
+    config.locate(section, key, || {
+        Color::from_hex(config.get(section, key).as_deref().unwrap_or(default))
+            .with_context(|| format!("In color attribute '{}' in section '{}'", key, section))
+    })
+}
+
+fn get_vec3_fails(config: &Config, section: &str, key: &str) -> Result<Vec3> {
+    config.locate(section, key, || {
+        let mut vec_string = config.get(section, key).ok_or_else(|| {
+            anyhow!(
+                "Missing vector attribute '{}' in section {} of config file",
+                key,
+                section
+            )
+        })?;
+
+        // 'spherical(r, theta, phi)' and 'cylindrical(r, phi, y)' are read like '(...)' and
+        // converted at the end
+        let coordinates = match vec_string.trim().split_once('(') {
+            Some((name, rest)) if ["spherical", "cylindrical"].contains(&name.trim()) => {
+                let name = name.trim().to_string();
+                vec_string = format!("({}", rest);
+                Some(name)
+            }
+            _ => None,
+        };
+
+        let first_char: char = vec_string.trim().chars().next().ok_or_else(|| {
+            anyhow!(
+                "In vector attribute '{}' in section {} the vector is empty",
+                key,
+                section
+            )
+        })?;
+
+        let valid_delimiters: Option<[&str; 2]> = match first_char {
+            '[' => Some(["[", "]"]),
+            '(' => Some(["(", ")"]),
+            '0'..='9' | '-' | '+' | '.' => None,
+            _ => return Err(anyhow!("In vector attribute '{}' in section {} the first element is not a valid delimiter or a valid number: {}", key, section, first_char)),
+        };
+
+        if let Some(delimiters) = valid_delimiters {
+            vec_string = vec_string
+                .trim()
+                .strip_prefix(delimiters[0])
+                .unwrap()
+                .strip_suffix(delimiters[1])
+                .ok_or_else(|| {
+                    anyhow!(
+                        "In vector attribute '{}' in section {} the vector isn't terminated by the matching closing delimiter '{}'",
+                        key,
+                        section,
+                        delimiters[1]
+                    )
+                })?.to_string();
+        }
+
+        let num_strs = vec_string.split(',');
+        let floats: Vec<f64> = num_strs
+            .map(|s| s.trim().parse::<f64>().map_err(Error::msg))
+            .collect::<Result<Vec<f64>>>().context(format!("In vector attribute '{}' in section {} the vector's elements aren't valid floating point numbers", key, section))?;
+
+        if floats.len() != 3 {
+            return Err(anyhow!("In vector attribute '{}' in section {} the vector supplied should be 3-dimensional and it's currently {}-dimensional", key, section, floats.len()));
+        }
+
+        Ok(match coordinates.as_deref() {
+            Some("spherical") => Vec3::from_spherical(
+                floats[0],
+                floats[1].to_radians(),
+                floats[2].to_radians(),
+            ),
+            Some(_) => Vec3::from_cylindrical(floats[0], floats[1].to_radians(), floats[2]),
+            None => Vec3::new(floats[0], floats[1], floats[2]),
+        })
+    })
+}
+
+/// Reads a list of parenthesized tuples of floats like `(1, 2, 3), (4, 5, 6)`, optionally wrapped
+/// in square brackets
+fn get_tuples_fails(config: &Config, section: &str, key: &str) -> Result<Vec<Vec<f64>>> {
+    config.locate(section, key, || {
+        let list_string = config.get(section, key).ok_or_else(|| {
+            anyhow!(
+                "Missing list attribute '{}' in section {} of config file",
+                key,
+                section
+            )
+        })?;
+
+        let list = list_string.trim();
+        let mut rest = list
+            .strip_prefix('[')
+            .and_then(|l| l.strip_suffix(']'))
+            .unwrap_or(list)
+            .trim();
+
+        let mut tuples = Vec::new();
+        while !rest.is_empty() {
+            let (tuple, remaining) = rest
+                .strip_prefix('(')
+                .and_then(|r| r.split_once(')'))
+                .ok_or_else(|| anyhow!("In list attribute '{}' in section {} every element must be enclosed in parentheses '()'", key, section))?;
+
+            let floats = tuple
+                .split(',')
+                .map(|s| s.trim().parse::<f64>().map_err(Error::msg))
+                .collect::<Result<Vec<f64>>>()
+                .context(format!("In list attribute '{}' in section {} the elements aren't valid floating point numbers", key, section))?;
+            tuples.push(floats);
+
+            let remaining = remaining.trim_start();
+            rest = remaining
+                .strip_prefix(',')
+                .unwrap_or(remaining)
+                .trim_start();
+        }
+
+        if tuples.is_empty() {
+            return Err(anyhow!(
+                "In list attribute '{}' in section {} the list is empty",
+                key,
+                section
+            ));
+        }
+
+        Ok(tuples)
+    })
+}
+
+/// Reads a value that names another section
+fn get_object_name(config: &Config, section: &str, key: &str) -> Result<String> {
+    config.locate(section, key, || {
+        config
+            .get(section, key)
+            .map(|name| name.trim().to_lowercase())
+            .ok_or_else(|| anyhow!("Missing attribute '{}' for {} in config file", key, section))
+    })
+}
+
+/// Removes the named object from the scene so that it only exists as part of the CSG
+fn take_csg_child(objects: &mut Vec<Shape>, section: &str, name: &str) -> Result<Shape> {
+    let index = objects
+        .iter()
+        .position(|o| o.get_params().name == name)
+        .ok_or_else(|| {
+            anyhow!(
+                "In section '{}' the referenced object '{}' doesn't exist or is already used by another CSG",
+                section,
+                name
+            )
+        })?;
+
+    if !objects[index].is_solid() {
+        return Err(anyhow!(
+            "In section '{}' the referenced object '{}' isn't a solid, only spheres, ellipsoids, tori, planes, capped cylinders and other CSGs can be combined",
+            section,
+            name
+        ));
+    }
+
+    Ok(objects.remove(index))
+}
+
+/// Either a full row-major `matrix` or the composition of the optional `position`, `rotation`
+/// (euler angles in degrees) and `scale` (uniform or per axis) keys
+fn get_transform(config: &Config, section: &str) -> Result<Matrix4> {
+    if let Some(matrix) = config.get(section, "matrix") {
+        return config.locate(section, "matrix", || {
+            let values = matrix
+                .split(|c: char| c == ',' || c.is_whitespace() || "()[]".contains(c))
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse::<f64>().map_err(Error::msg))
+                .collect::<Result<Vec<f64>>>()
+                .context(format!(
+                    "In attribute 'matrix' in section {} the elements aren't valid floating point numbers",
+                    section
+                ))?;
+
+            if values.len() != 16 {
+                return Err(anyhow!(
+                    "In attribute 'matrix' in section {} the matrix must have 16 elements and it has {}",
+                    section,
+                    values.len()
+                ));
+            }
+
+            let mut rows = [[0.0; 4]; 4];
+            for (i, value) in values.into_iter().enumerate() {
+                rows[i / 4][i % 4] = value;
+            }
+            Ok(Matrix4(rows))
+        });
+    }
+
+    let position = get_vec3_default(config, section, "position", Vec3::new(0.0, 0.0, 0.0))?;
+    let rotation = get_vec3_default(config, section, "rotation", Vec3::new(0.0, 0.0, 0.0))?;
+    let basis = Onb::from_euler(
+        rotation.x.to_radians(),
+        rotation.y.to_radians(),
+        rotation.z.to_radians(),
+    );
+
+    let scale = match get_float_default(config, section, "scale", 1.0) {
+        Ok(s) => Vec3::new(s, s, s),
+        Err(_) => get_vec3_fails(config, section, "scale")?,
+    };
+
+    Ok(Matrix4::from_trs(position, &basis, scale))
+}
+
+fn get_scene_scale(config: &Config) -> Result<f64> {
+    let scale = get_float_default(config, "scene", "scale", 1.0)?;
+    if !(scale > 0.0 && scale.is_finite()) {
+        return Err(anyhow!(
+            "In section 'scene' the scale must be a finite number greater than 0, it's {}",
+            scale
+        ));
+    }
+    Ok(scale)
+}
+
+fn read_render_settings(config: &Config) -> Result<RenderSettings> {
+    let defaults = RenderSettings::default();
+
+    let max_reflections = get_uint_default(
+        config,
+        "render",
+        "max_reflections",
+        u64::from(defaults.max_reflections),
+    )?;
+    let shadows = get_bool_default(config, "render", "shadows", defaults.shadows)?;
+    let srgb = get_bool_default(config, "render", "srgb", defaults.srgb)?;
+    let transparent = get_bool_default(config, "render", "transparent", defaults.transparent)?;
+    let denoise = get_bool_default(config, "render", "denoise", defaults.denoise)?;
+    let seed = get_uint_default(config, "render", "seed", defaults.seed)?;
+    let denoise_radius = get_uint_default(
+        config,
+        "render",
+        "denoise_radius",
+        u64::from(defaults.denoise_radius),
+    )?;
+    if denoise_radius == 0 {
+        return Err(anyhow!(
+            "In section 'render' the denoise_radius must be at least 1"
+        ));
+    }
+    let bias = get_float_default(config, "render", "bias", defaults.bias)?;
+    if bias < 0.0 {
+        return Err(anyhow!(
+            "In section 'render' the bias must be positive, it's {}",
+            bias
+        ));
+    }
+
+    let emission_samples = get_uint_default(
+        config,
+        "render",
+        "emission_samples",
+        u64::from(defaults.emission_samples),
+    )?;
+
+    let aa_threshold = config
+        .get("render", "aa_threshold")
+        .map(|_| get_float_fails(config, "render", "aa_threshold"))
+        .transpose()?;
+    if aa_threshold.is_some_and(|threshold| threshold < 0.0) {
+        return Err(anyhow!(
+            "In section 'render' the aa_threshold must be positive"
+        ));
+    }
+    let aa_samples = get_uint_default(
+        config,
+        "render",
+        "aa_samples",
+        u64::from(defaults.aa_samples),
+    )?;
+    if aa_samples == 0 {
+        return Err(anyhow!(
+            "In section 'render' the aa_samples must be at least 1"
+        ));
+    }
+
+    let sampler = match config
+        .get("render", "sampler")
+        .as_deref()
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        None => defaults.sampler,
+        Some("random") => Sampler::Random,
+        Some("jittered") => Sampler::Jittered,
+        Some("halton") => Sampler::Halton,
+        Some(other) => {
+            return Err(anyhow!(
+                "In section 'render' the sampler '{}' isn't valid, it must be one of: random, jittered, halton",
+                other
+            ))
+        }
+    };
+
+    let roulette_threshold = config
+        .get("render", "roulette_threshold")
+        .map(|_| get_float_fails(config, "render", "roulette_threshold"))
+        .transpose()?;
+    if roulette_threshold.is_some_and(|threshold| !(threshold > 0.0 && threshold <= 1.0)) {
+        return Err(anyhow!(
+            "In section 'render' the roulette_threshold must be between 0 and 1"
+        ));
+    }
+
+    let tone_mapping = match config
+        .get("render", "tone_mapping")
+        .as_deref()
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        None => defaults.tone_mapping,
+        Some("none") => ToneMapping::None,
+        Some("clamp") => ToneMapping::Clamp,
+        Some("reinhard") => ToneMapping::Reinhard,
+        Some("aces") => ToneMapping::Aces,
+        Some(other) => {
+            return Err(anyhow!(
+                "In section 'render' the tone_mapping '{}' isn't valid, it must be one of: none, clamp, reinhard, aces",
+                other
+            ))
+        }
+    };
+
+    let firefly_clamp = config
+        .get("render", "firefly_clamp")
+        .map(|_| get_float_fails(config, "render", "firefly_clamp"))
+        .transpose()?;
+    if firefly_clamp.is_some_and(|clamp| clamp <= 0.0) {
+        return Err(anyhow!(
+            "In section 'render' the firefly_clamp must be positive"
+        ));
+    }
+
+    Ok(RenderSettings {
+        max_reflections: u32::try_from(max_reflections)
+            .context("In section 'render' the max_reflections is too large")?,
+        shadows,
+        bias,
+        emission_samples: u32::try_from(emission_samples)
+            .context("In section 'render' the emission_samples is too large")?,
+        aa_threshold,
+        aa_samples: u32::try_from(aa_samples)
+            .context("In section 'render' the aa_samples is too large")?,
+        sampler,
+        roulette_threshold,
+        srgb,
+        transparent,
+        tone_mapping,
+        firefly_clamp,
+        denoise,
+        denoise_radius: u32::try_from(denoise_radius)
+            .context("In section 'render' the denoise_radius is too large")?,
+        seed,
+    })
+}
+
+/// Sky given in the `[sky]` section, if there's one
+fn read_sky(config: &Config) -> Result<Option<Sky>> {
+    if !config.sections().contains(&"sky".to_string()) {
+        return Ok(None);
+    }
+
+    let sun_direction = get_vec3_default(config, "sky", "sun_direction", Vec3::new(0.3, 1.0, 0.5))?;
+    if sun_direction.near_zero() {
+        return Err(anyhow!(
+            "In section 'sky' the 'sun_direction' can't be zero"
+        ));
+    }
+    let sun_size = get_float_default(config, "sky", "sun_size", DEFAULT_SUN_SIZE)?;
+    if !(0.0..90.0).contains(&sun_size) {
+        return Err(anyhow!(
+            "In section 'sky' the 'sun_size' must be between 0 and 90 degrees"
+        ));
+    }
+
+    Ok(Some(Sky {
+        sun_direction: sun_direction.normalize(),
+        sun_color: get_color_default(config, "sky", "sun_color", "#FFF4D6")?,
+        sun_cos_size: sun_size.to_radians().cos(),
+        zenith_color: get_color_default(config, "sky", "zenith_color", "#3A6FC4")?,
+        horizon_color: get_color_default(config, "sky", "horizon_color", "#BCD4E6")?,
+        ground_color: get_color_default(config, "sky", "ground_color", "#5A5046")?,
+    }))
+}
+
+/// Participating medium given in the `[medium]` section, if there's one
+fn read_medium(config: &Config) -> Result<Option<Medium>> {
+    if !config.sections().contains(&"medium".to_string()) {
+        return Ok(None);
+    }
+
+    let density = get_float_fails(config, "medium", "density")?;
+    if density <= 0.0 {
+        return Err(anyhow!(
+            "In section 'medium' the 'density' must be positive"
+        ));
+    }
+    let steps = get_uint_default(config, "medium", "steps", u64::from(MEDIUM_STEPS))?;
+    if steps == 0 {
+        return Err(anyhow!(
+            "In section 'medium' the 'steps' must be at least 1"
+        ));
+    }
+    let distance = get_float_default(config, "medium", "distance", MEDIUM_DISTANCE)?;
+    if distance <= 0.0 {
+        return Err(anyhow!(
+            "In section 'medium' the 'distance' must be positive"
+        ));
+    }
+
+    Ok(Some(Medium {
+        density,
+        color: get_color_default(config, "medium", "color", "#FFFFFF")?,
+        steps: u32::try_from(steps).context("In section 'medium' the 'steps' is too large")?,
+        distance,
+    }))
+}
+
+/// Wraps the object in an instance with the transform of its group, if it has a `group` key
+fn apply_group(config: &Config, shape: Shape) -> Result<Shape> {
+    let section = shape.get_params().name.clone();
+    let transform = match get_group_transform(config, &section, &mut Vec::new())? {
+        Some(transform) => transform,
+        None => return Ok(shape),
+    };
+
+    let params = shape.get_params().clone();
+    Instance::new(shape, transform, params)
+        .map(Shape::Instance)
+        .ok_or_else(|| {
+            anyhow!(
+                "In section '{}' the transformation of its group can't be inverted",
+                section
+            )
+        })
+}
+
+/// Transform of the group the section is in, composed with those of the groups containing it
+fn get_group_transform(
+    config: &Config,
+    section: &str,
+    visited: &mut Vec<String>,
+) -> Result<Option<Matrix4>> {
+    let name = match config.get(section, "group") {
+        Some(name) => name.trim().to_lowercase(),
+        None => return Ok(None),
+    };
+
+    let group = format!("group {}", name);
+    if !config.sections().contains(&group) {
+        return Err(anyhow!(
+            "In section '{}' the group '{}' doesn't exist, it must be defined in a section named '[group {}]'",
+            section,
+            name,
+            name
+        ));
+    }
+    if visited.contains(&group) {
+        return Err(anyhow!(
+            "In section '{}' the group '{}' ends up containing itself",
+            section,
+            name
+        ));
+    }
+    visited.push(group.clone());
+
+    let transform = get_transform(config, &group)?;
+    Ok(Some(match get_group_transform(config, &group, visited)? {
+        Some(parent) => parent * transform,
+        None => transform,
+    }))
+}
+
+fn get_vec3_default(config: &Config, section: &str, key: &str, default: Vec3) -> Result<Vec3> {
+    if config.get(section, key).is_some() {
+        get_vec3_fails(config, section, key)
+    } else {
+        Ok(default)
+    }
+}
+
+/// Section of the material an object references through its `material` key, or the one set in
+/// the `[defaults]` section, if any
+fn get_material_section(config: &Config, section: &str) -> Result<Option<String>> {
+    let (source, name) = match config.get(section, "material") {
+        Some(name) => (section, name),
+        None => match config.get("defaults", "material") {
+            Some(name) => ("defaults", name),
+            None => return Ok(None),
+        },
+    };
+
+    let material = format!("material {}", name.trim().to_lowercase());
+    if config.sections().contains(&material) {
+        Ok(Some(material))
+    } else {
+        Err(anyhow!(
+            "In section '{}' the material '{}' doesn't exist, it must be defined in a section named '[material {}]'",
+            source,
+            name.trim(),
+            name.trim()
+        ))
+    }
+}
+
+/// Section an object's parameter is read from, the object's own values override its material's,
+/// which override those in the `[defaults]` section
+fn param_section<'a>(
+    config: &Config,
+    section: &'a str,
+    material: Option<&'a str>,
+    key: &str,
+) -> &'a str {
+    [Some(section), material, Some("defaults")]
+        .into_iter()
+        .flatten()
+        .find(|s| config.get(s, key).is_some())
+        .unwrap_or(section)
+}
+
+/// Pair of numbers written as 'x, y', or as a single number for both
+fn get_pair_default(
+    config: &Config,
+    section: &str,
+    key: &str,
+    default: (f64, f64),
+) -> Result<(f64, f64)> {
+    config.locate(section, key, || {
+        let Some(value) = config.get(section, key) else {
+            return Ok(default);
+        };
+
+        let numbers = value
+            .trim()
+            .trim_start_matches(['(', '['])
+            .trim_end_matches([')', ']'])
+            .split(',')
+            .map(|n| n.trim().parse::<f64>())
+            .collect::<std::result::Result<Vec<f64>, _>>()
+            .ok();
+        match numbers.as_deref() {
+            Some(&[n]) => Ok((n, n)),
+            Some(&[x, y]) => Ok((x, y)),
+            _ => Err(anyhow!(
+                "In section '{}' the '{}' must be one number or two separated by a comma, it's '{}'",
+                section,
+                key,
+                value
+            )),
+        }
+    })
+}
+
+fn get_uv_transform<'a>(
+    config: &Config,
+    from: &impl Fn(&'static str) -> &'a str,
+) -> Result<UvTransform> {
+    let scale = get_pair_default(config, from("uv_scale"), "uv_scale", (1.0, 1.0))?;
+    if scale.0 == 0.0 || scale.1 == 0.0 {
+        return Err(anyhow!(
+            "In section '{}' the uv_scale can't be 0",
+            from("uv_scale")
+        ));
+    }
+
+    Ok(UvTransform {
+        scale,
+        rotation: get_float_default(config, from("uv_rotate"), "uv_rotate", 0.0)?.to_radians(),
+        offset: get_pair_default(config, from("uv_offset"), "uv_offset", (0.0, 0.0))?,
+    })
+}
+
+fn get_mapping<'a>(config: &Config, from: &impl Fn(&'static str) -> &'a str) -> Result<Mapping> {
+    match config
+        .get(from("mapping"), "mapping")
+        .as_deref()
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        Some("uv") | None => Ok(Mapping::Uv),
+        Some("triplanar") => Ok(Mapping::Triplanar),
+        Some(other) => Err(anyhow!(
+            "In section '{}' the mapping '{}' isn't valid, it must be one of: uv, triplanar",
+            from("mapping"),
+            other
+        )),
+    }
+}
+
+/// Stable number of the object of the section, the 32 bit FNV-1a hash of its name
+fn object_id(section: &str) -> u32 {
+    section.bytes().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Kinds of rays listed in the object's `visible_to`, every kind if it isn't set
+fn get_visibility<'a>(
+    config: &Config,
+    from: &impl Fn(&'static str) -> &'a str,
+) -> Result<Visibility> {
+    let Some(kinds) = config.get(from("visible_to"), "visible_to") else {
+        return Ok(Visibility::ALL);
+    };
+
+    let mut visibility = Visibility {
+        camera: false,
+        shadows: false,
+        reflections: false,
+    };
+    for kind in kinds
+        .split(',')
+        .map(|kind| kind.trim().to_lowercase())
+        .filter(|kind| !kind.is_empty())
+    {
+        match kind.as_str() {
+            "camera" => visibility.camera = true,
+            "shadows" => visibility.shadows = true,
+            "reflections" => visibility.reflections = true,
+            "none" => (),
+            other => {
+                return Err(anyhow!(
+                    "In section '{}' the visible_to lists '{}', which isn't one of: camera, shadows, reflections, none",
+                    from("visible_to"),
+                    other
+                ))
+            }
+        }
+    }
+    Ok(visibility)
+}
+
+/// Procedural pattern of the object, if it has one
+fn get_pattern<'a>(
+    config: &Config,
+    from: &impl Fn(&'static str) -> &'a str,
+) -> Result<Option<Pattern>> {
+    let kind = match config
+        .get(from("pattern"), "pattern")
+        .as_deref()
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        None => return Ok(None),
+        Some("marble") => PatternKind::Marble,
+        Some("wood") => PatternKind::Wood,
+        Some("noise") => PatternKind::Noise,
+        Some(other) => {
+            return Err(anyhow!(
+            "In section '{}' the pattern '{}' isn't valid, it must be one of: marble, wood, noise",
+            from("pattern"),
+            other
+        ))
+        }
+    };
+
+    let scale = get_float_default(
+        config,
+        from("pattern_scale"),
+        "pattern_scale",
+        DEFAULT_PATTERN_SCALE,
+    )?;
+    if scale <= 0.0 {
+        return Err(anyhow!(
+            "In section '{}' the pattern_scale must be positive",
+            from("pattern_scale")
+        ));
+    }
+    let default_turbulence = match kind {
+        PatternKind::Marble => 5.0,
+        PatternKind::Wood => 1.0,
+        PatternKind::Noise => 0.0,
+    };
+
+    Ok(Some(Pattern {
+        kind,
+        color: get_color_default(config, from("pattern_color"), "pattern_color", "#000000")?,
+        scale,
+        turbulence: get_float_default(
+            config,
+            from("turbulence"),
+            "turbulence",
+            default_turbulence,
+        )?,
+        perlin: Perlin::new(get_uint_default(
+            config,
+            from("pattern_seed"),
+            "pattern_seed",
+            0,
+        )?),
+    }))
+}
+
+/// Gradient of the object, if it has one
+fn get_gradient<'a>(
+    config: &Config,
+    from: &impl Fn(&'static str) -> &'a str,
+) -> Result<Option<Gradient>> {
+    let kind = match config
+        .get(from("gradient"), "gradient")
+        .as_deref()
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        None => return Ok(None),
+        Some("linear") => GradientKind::Linear,
+        Some("radial") => GradientKind::Radial,
+        Some(other) => {
+            return Err(anyhow!(
+                "In section '{}' the gradient '{}' isn't valid, it must be one of: linear, radial",
+                from("gradient"),
+                other
+            ))
+        }
+    };
+
+    let section = from("gradient_colors");
+    let colors = config.locate(section, "gradient_colors", || {
+        let colors = config
+            .get(section, "gradient_colors")
+            .ok_or_else(|| {
+                anyhow!(
+                    "Missing attribute 'gradient_colors' for {} in config file",
+                    section
+                )
+            })?
+            .split(',')
+            .map(|hex| Color::from_hex(hex.trim()))
+            .collect::<Result<Vec<Color>>>()
+            .with_context(|| {
+                format!(
+                    "In color list attribute 'gradient_colors' in section '{}'",
+                    section
+                )
+            })?;
+        if colors.len() < 2 {
+            return Err(anyhow!(
+                "In section '{}' the gradient_colors must have at least two colors",
+                section
+            ));
+        }
+        Ok(colors)
+    })?;
+
+    let (x, y) = get_pair_default(config, from("gradient_axis"), "gradient_axis", (1.0, 0.0))?;
+    let axis = Vec3::new(x, y, 0.0);
+    if axis.near_zero() {
+        return Err(anyhow!(
+            "In section '{}' the gradient_axis can't be zero",
+            from("gradient_axis")
+        ));
+    }
+    let axis = axis.normalize();
+
+    let length = get_float_default(
+        config,
+        from("gradient_length"),
+        "gradient_length",
+        DEFAULT_GRADIENT_LENGTH,
+    )?;
+    if length <= 0.0 {
+        return Err(anyhow!(
+            "In section '{}' the gradient_length must be positive",
+            from("gradient_length")
+        ));
+    }
+
+    Ok(Some(Gradient {
+        kind,
+        colors,
+        axis: (axis.x, axis.y),
+        length,
+    }))
+}
+
+/// Bump or normal map of the object, if it has one
+fn get_bump<'a>(
+    config: &Config,
+    section: &str,
+    from: &impl Fn(&'static str) -> &'a str,
+) -> Result<Option<Bump>> {
+    let (kind, key) = match (
+        config.get(from("bump_map"), "bump_map"),
+        config.get(from("normal_map"), "normal_map"),
+    ) {
+        (None, None) => return Ok(None),
+        (Some(_), None) => (BumpKind::Height, "bump_map"),
+        (None, Some(_)) => (BumpKind::Normal, "normal_map"),
+        (Some(_), Some(_)) => {
+            return Err(anyhow!(
+                "In section '{}' there can't be both a 'bump_map' and a 'normal_map'",
+                section
+            ))
+        }
+    };
+
+    let path = config
+        .path(from(key), key)
+        .expect("the map's key was just found");
+    // heights and normals are stored as they are, not as colors
+    let texture = ImageTexture::open(&path, false)
+        .with_context(|| format!("In attribute '{}' in section '{}'", key, from(key)))?;
+    let size = get_float_default(config, from("map_size"), "map_size", DEFAULT_MAP_SIZE)?;
+    if size <= 0.0 {
+        return Err(anyhow!(
+            "In section '{}' the map_size must be positive",
+            from("map_size")
+        ));
+    }
+
+    Ok(Some(Bump {
+        kind,
+        path: path.display().to_string(),
+        strength: get_float_default(config, from("bump_strength"), "bump_strength", 1.0)?,
+        size,
+        wrap: get_wrap(config, from)?,
+        texture: Arc::new(texture),
+    }))
+}
+
+fn get_wrap<'a>(config: &Config, from: &impl Fn(&'static str) -> &'a str) -> Result<Wrap> {
+    match config
+        .get(from("wrap"), "wrap")
+        .as_deref()
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        Some("repeat") | None => Ok(Wrap::Repeat),
+        Some("clamp") => Ok(Wrap::Clamp),
+        Some("mirror") => Ok(Wrap::Mirror),
+        Some(other) => Err(anyhow!(
+            "In section '{}' the wrap '{}' isn't valid, it must be one of: repeat, clamp, mirror",
+            from("wrap"),
+            other
+        )),
+    }
+}
+
+/// Shading model of the object, with the parameters of the PBR one
+fn get_shading(config: &Config, section: &str, material: Option<&str>) -> Result<Shading> {
+    let from = |key| param_section(config, section, material, key);
+    match config
+        .get(from("shading"), "shading")
+        .as_deref()
+        .map(str::to_lowercase)
+        .as_deref()
+    {
+        Some("phong") | None => Ok(Shading::Phong),
+        Some("pbr") => Ok(Shading::Pbr {
+            metallic: get_float_default(config, from("metallic"), "metallic", 0.0)?.clamp(0.0, 1.0),
+            roughness: get_float_default(config, from("roughness"), "roughness", 0.5)?
+                .clamp(0.0, 1.0),
+        }),
+        Some(other) => Err(anyhow!(
+            "In section '{}' the shading '{}' isn't valid, it must be one of: phong, pbr",
+            section,
+            other
+        )),
+    }
+}
+
+fn get_params(config: &Config, section: &str) -> Result<ObjectParameters> {
+    let material = get_material_section(config, section)?;
+    let from = |key| param_section(config, section, material.as_deref(), key);
+
+    // every parameter is checked so that all of their errors are reported together
+    let mut errors = ConfigErrors::default();
+    let shading = errors
+        .check(|| get_shading(config, section, material.as_deref()))
+        .unwrap_or(Shading::Phong);
+    let pbr = shading != Shading::Phong;
+    // PBR materials may call their color a base color and don't use the Phong coefficients
+    let color = errors
+        .check(|| match config.get(from("base_color"), "base_color") {
+            Some(_) if pbr => get_color_default(config, from("base_color"), "base_color", ""),
+            _ => get_color_fails(config, from("color")),
+        })
+        .unwrap_or(colors::BLACK);
+    let k_d = errors
+        .check(|| match pbr {
+            true => get_float_default(config, from("k_d"), "k_d", 0.0),
+            false => get_float_fails(config, from("k_d"), "k_d"),
+        })
+        .unwrap_or(0.0)
+        .clamp(0.0, 1.0);
+    let k_a = errors
+        .check(|| get_float_default(config, from("k_a"), "k_a", 1.0))
+        .unwrap_or(0.0)
+        .clamp(0.0, 1.0);
+    let k_s = errors
+        .check(|| match pbr {
+            true => get_float_default(config, from("k_s"), "k_s", 0.0),
+            false => get_float_fails(config, from("k_s"), "k_s"),
+        })
+        .unwrap_or(0.0)
+        .clamp(0.0, 1.0);
+    let k_n = errors
+        .check(|| get_float_default(config, from("k_n"), "k_n", DEFAULT_HARDNESS))
+        .unwrap_or(DEFAULT_HARDNESS)
+        .max(1.0);
+    let reflection = errors
+        .check(|| get_float_default(config, from("reflection"), "reflection", 0.0))
+        .unwrap_or(0.0)
+        .clamp(0.0, 1.0);
+    let transparency = errors
+        .check(|| get_float_default(config, from("transparency"), "transparency", 0.0))
+        .unwrap_or(0.0)
+        .clamp(0.0, 1.0);
+    let translucency = errors
+        .check(|| get_float_default(config, from("translucency"), "translucency", 0.0))
+        .unwrap_or(0.0)
+        .clamp(0.0, 1.0);
+    let transparency_color = errors
+        .check(|| {
+            get_color_default(
+                config,
+                from("transparency_color"),
+                "transparency_color",
+                "#FFFFFF",
+            )
+        })
+        .unwrap_or(colors::WHITE);
+    let ior = errors
+        .check(|| get_float_default(config, from("ior"), "ior", 1.0))
+        .unwrap_or(1.0)
+        .max(1.0);
+    let fresnel = errors
+        .check(|| get_bool_default(config, from("fresnel"), "fresnel", false))
+        .unwrap_or(false);
+    let double_sided = errors
+        .check(|| get_bool_default(config, from("double_sided"), "double_sided", true))
+        .unwrap_or(true);
+    let visibility = errors
+        .check(|| get_visibility(config, &from))
+        .unwrap_or(Visibility::ALL);
+    let layer = config
+        .get(from("layer"), "layer")
+        .map(|layer| layer.trim().to_string())
+        .filter(|layer| !layer.is_empty())
+        .unwrap_or_else(|| DEFAULT_LAYER.to_string());
+    let bias = errors
+        .check(|| {
+            config
+                .get(from("bias"), "bias")
+                .map(|_| get_float_fails(config, from("bias"), "bias"))
+                .transpose()
+        })
+        .flatten();
+    if bias.is_some_and(|bias| bias < 0.0) {
+        errors.check(|| -> Result<()> {
+            Err(anyhow!(
+                "In section '{}' the bias can't be negative",
+                section
+            ))
+        });
+    }
+    let checkerboard = errors
+        .check(|| get_float_default(config, from("checkerboard"), "checkerboard", 0.0))
+        .unwrap_or(0.0)
+        .max(0.0);
+    let emission = errors
+        .check(|| get_color_default(config, from("emission"), "emission", "#000000"))
+        .unwrap_or(colors::BLACK);
+    let emission_intensity = errors
+        .check(|| {
+            get_float_default(
+                config,
+                from("emission_intensity"),
+                "emission_intensity",
+                1.0,
+            )
+        })
+        .unwrap_or(1.0)
+        .max(0.0);
+    let checkerboard_color2 = errors
+        .check(|| {
+            get_color_default(
+                config,
+                from("checkerboard_color2"),
+                "checkerboard_color2",
+                "#000000",
+            )
+        })
+        .unwrap_or(colors::BLACK);
+    let uv_transform = errors
+        .check(|| get_uv_transform(config, &from))
+        .unwrap_or_default();
+    let mapping = errors
+        .check(|| get_mapping(config, &from))
+        .unwrap_or(Mapping::Uv);
+    let pattern = errors.check(|| get_pattern(config, &from)).flatten();
+    let gradient = errors.check(|| get_gradient(config, &from)).flatten();
+    let bump = errors.check(|| get_bump(config, section, &from)).flatten();
+    let ignored_lights = errors
+        .check(|| get_section_names(config, from("ignore_lights"), "ignore_lights"))
+        .unwrap_or_default();
+    errors.into_result()?;
 
     if reflection + transparency > 1.0 {
         return Err(anyhow!("In section '{}' the transparency+reflection > 1. The transparecy + reflection must not sum to more than 1, please lower the values.", section));
@@ -332,7 +3929,10 @@ fn get_params(config: &Ini, section: &str) -> Result<ObjectParameters> {
     let o1 = 1.0 - (reflection + transparency);
 
     Ok(ObjectParameters {
+        name: section.to_string(),
+        id: object_id(section),
         color,
+        shading,
         k_d,
         k_a,
         k_s,
@@ -340,6 +3940,22 @@ fn get_params(config: &Ini, section: &str) -> Result<ObjectParameters> {
         o1,
         reflection,
         transparency,
+        translucency,
+        transparency_color,
+        ior,
+        fresnel,
         checkerboard,
+        checkerboard_color2,
+        uv_transform,
+        mapping,
+        emission: emission * emission_intensity,
+        pattern,
+        gradient,
+        bump,
+        ignored_lights,
+        bias,
+        double_sided,
+        visibility,
+        layer,
     })
 }
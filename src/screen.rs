@@ -1,14 +1,218 @@
-use anyhow::{Error, Result};
-use image::{ImageBuffer, Rgb, RgbImage};
+use anyhow::{anyhow, Context, Error, Result};
+use image::codecs::hdr::{HdrDecoder, HdrEncoder};
+use image::codecs::png::PngEncoder;
+use image::codecs::pnm::{PNMSubtype, PnmEncoder, SampleEncoding};
+use image::{ImageBuffer, ImageEncoder, Rgb, Rgba};
+use std::fmt;
+use std::io::{BufReader, BufWriter, Write};
 use std::path::Path;
+use std::str::FromStr;
+use std::time::Instant;
+
+use crate::constants::STDOUT_IMAGE;
+use crate::exr::write_exr;
+use crate::srgb;
+
+/// Size in pixels of the rendered image, written as `WIDTHxHEIGHT` or as a single number for
+/// square images
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Resolution {
+    pub fn square(side: u32) -> Resolution {
+        Resolution {
+            width: side,
+            height: side,
+        }
+    }
+}
+
+impl FromStr for Resolution {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Resolution> {
+        let parse = |n: &str| {
+            n.trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "'{}' isn't a valid resolution, it must be a positive number of pixels or WIDTHxHEIGHT, like 1920x1080",
+                        s
+                    )
+                })
+        };
+
+        match s.split_once(['x', 'X']) {
+            Some((width, height)) => Ok(Resolution {
+                width: parse(width)?,
+                height: parse(height)?,
+            }),
+            None => parse(s).map(Resolution::square),
+        }
+    }
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.width == self.height {
+            write!(f, "{}", self.width)
+        } else {
+            write!(f, "{}x{}", self.width, self.height)
+        }
+    }
+}
+
+/// Bits each channel of 8 bit images is saved with, for the formats that can also store 16
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitDepth {
+    Eight,
+    Sixteen,
+}
+
+impl FromStr for BitDepth {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<BitDepth> {
+        match s.trim() {
+            "8" => Ok(BitDepth::Eight),
+            "16" => Ok(BitDepth::Sixteen),
+            _ => Err(anyhow!(
+                "'{}' isn't a valid bit depth, it must be 8 or 16",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for BitDepth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BitDepth::Eight => write!(f, "8"),
+            BitDepth::Sixteen => write!(f, "16"),
+        }
+    }
+}
+
+/// Rectangle of the image in pixels, from its top left corner
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// The whole image
+    pub fn full(size: Resolution) -> Region {
+        Region {
+            x: 0,
+            y: 0,
+            width: size.width,
+            height: size.height,
+        }
+    }
+
+    pub fn fits_in(&self, size: Resolution) -> bool {
+        u64::from(self.x) + u64::from(self.width) <= u64::from(size.width)
+            && u64::from(self.y) + u64::from(self.height) <= u64::from(size.height)
+    }
+}
+
+impl FromStr for Region {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Region> {
+        let numbers = s
+            .split(',')
+            .map(|n| n.trim().parse::<u32>())
+            .collect::<std::result::Result<Vec<u32>, _>>()
+            .ok()
+            .filter(|n| n.len() == 4 && n[2] > 0 && n[3] > 0)
+            .ok_or_else(|| {
+                anyhow!(
+                    "'{}' isn't a valid region, it must be X,Y,WIDTH,HEIGHT in pixels from the image's top left corner, like 100,50,200,200",
+                    s
+                )
+            })?;
+
+        Ok(Region {
+            x: numbers[0],
+            y: numbers[1],
+            width: numbers[2],
+            height: numbers[3],
+        })
+    }
+}
+
+/// Whether the image at the path is saved in floating point, keeping the colors brighter than
+/// white, instead of with 8 bits per channel
+pub fn is_hdr<P: AsRef<Path>>(path: P) -> bool {
+    matches!(extension(path.as_ref()).as_deref(), Some("hdr" | "exr"))
+}
+
+pub fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+}
+
+/// Reads a Radiance HDR image, returning its size and its linear pixels row by row from the top
+pub fn read_hdr(path: &Path) -> Result<(u32, u32, Vec<Rgb<f32>>)> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Couldn't read the image '{}'", path.display()))?;
+    let decoder = HdrDecoder::new(BufReader::new(file))
+        .with_context(|| format!("Couldn't read the image '{}'", path.display()))?;
+    let (width, height) = (decoder.metadata().width, decoder.metadata().height);
+    let pixels = decoder
+        .read_image_hdr()
+        .with_context(|| format!("Couldn't read the image '{}'", path.display()))?;
+    Ok((width, height, pixels))
+}
+
+/// PNG image with a tEXt chunk for each of the entries added after its header
+fn add_text_chunks(png: &[u8], entries: &[(String, String)]) -> Vec<u8> {
+    // the signature and the header chunk, which must come first
+    let header_end = 8 + 4 + 4 + 13 + 4;
+    let mut with_text = png[..header_end].to_vec();
+
+    for (keyword, text) in entries {
+        let mut chunk = b"tEXt".to_vec();
+        chunk.extend(keyword.as_bytes());
+        chunk.push(0);
+        chunk.extend(text.as_bytes());
+
+        with_text.extend((chunk.len() as u32 - 4).to_be_bytes());
+        with_text.extend(&chunk);
+        with_text.extend(crc32fast::hash(&chunk).to_be_bytes());
+    }
+
+    with_text.extend(&png[header_end..]);
+    with_text
+}
 
 /// This struct abstracts away any direct interaction with the SDL module, so that the user may
 /// only need to call the provided methods without `use`ing any sdl modules.
 pub struct ScreenContextManager {
-    framebuffer: RgbImage,
-    color: Rgb<u8>,
+    /// Linear colors, which are only encoded and quantized when the image is saved
+    framebuffer: ImageBuffer<Rgba<f64>, Vec<f64>>,
+    color: Rgba<f64>,
     height: u32,
     width: u32,
+    /// Whether the image is saved with its alpha channel
+    transparent: bool,
+    /// Whether the colors of 8 bit images are encoded as sRGB when saved
+    srgb: bool,
+    /// Bits per channel of the images that aren't saved in floating point
+    bit_depth: BitDepth,
+    /// Text entries written into the PNG and EXR images, along with the time taken to render them
+    metadata: Vec<(String, String)>,
+    /// When the screen was created, the render time written with the metadata is counted from it
+    started: Instant,
 }
 
 impl ScreenContextManager {
@@ -16,13 +220,52 @@ impl ScreenContextManager {
     pub fn new(width: u32, height: u32) -> ScreenContextManager {
         ScreenContextManager {
             // Create empty framebuffer
-            framebuffer: ImageBuffer::new(width, height),
-            color: Rgb([0, 0, 0]),
+            framebuffer: ImageBuffer::from_pixel(width, height, Rgba([0.0, 0.0, 0.0, 1.0])),
+            color: Rgba([0.0, 0.0, 0.0, 1.0]),
             height,
             width,
+            transparent: false,
+            srgb: false,
+            bit_depth: BitDepth::Eight,
+            metadata: Vec::new(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Empty screen of the given size, saved like this one and with its metadata
+    pub fn blank(&self, width: u32, height: u32) -> ScreenContextManager {
+        ScreenContextManager {
+            transparent: self.transparent,
+            srgb: self.srgb,
+            bit_depth: self.bit_depth,
+            metadata: self.metadata.clone(),
+            started: self.started,
+            ..ScreenContextManager::new(width, height)
         }
     }
 
+    /// Makes the image be saved as RGBA, keeping the alpha of its pixels, instead of as RGB
+    pub fn set_transparent(&mut self, transparent: bool) {
+        self.transparent = transparent;
+    }
+
+    /// Makes 8 bit images be saved with their colors encoded as sRGB instead of linear. Floating
+    /// point images are always linear.
+    pub fn set_srgb(&mut self, srgb: bool) {
+        self.srgb = srgb;
+    }
+
+    /// Makes the images that aren't saved in floating point have this many bits per channel
+    pub fn set_bit_depth(&mut self, bit_depth: BitDepth) {
+        self.bit_depth = bit_depth;
+    }
+
+    /// Makes PNG and EXR images be saved with these text entries, named by the first string of
+    /// each, and with the time taken to render them
+    pub fn set_metadata(&mut self, metadata: Vec<(String, String)>) {
+        self.metadata = metadata;
+    }
+
     pub fn get_width(&self) -> u32 {
         self.width
     }
@@ -31,13 +274,10 @@ impl ScreenContextManager {
     }
 
     /// Sets the color to be used for drawing operations.
-    /// Parameters correspond to RGB colors and must be real numbers in the range [0, 1].
-    pub fn set_color(&mut self, r: f32, g: f32, b: f32) {
-        self.color = Rgb([
-            (r * 255.0).round() as u8,
-            (g * 255.0).round() as u8,
-            (b * 255.0).round() as u8,
-        ]);
+    /// Parameters correspond to linear RGBA colors, which may go over 1 except for the alpha. An
+    /// alpha of 0 is fully transparent.
+    pub fn set_color(&mut self, r: f64, g: f64, b: f64, a: f64) {
+        self.color = Rgba([r, g, b, a]);
     }
 
     /// Plots a single pixel on the framebuffer.
@@ -45,37 +285,243 @@ impl ScreenContextManager {
         self.framebuffer.put_pixel(x, y, self.color);
     }
 
+    /// Fills the framebuffer with the image at the path, which must be of the same size
+    pub fn load_img<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let (width, height, pixels) = match extension(path).as_deref() {
+            Some("exr") => {
+                return Err(anyhow!(
+                    "Existing EXR images can't be read, so the image '{}' can't be rendered by regions",
+                    path.display()
+                ));
+            }
+            Some("hdr") => {
+                let (width, height, pixels) = read_hdr(path)?;
+                let pixels = pixels
+                    .into_iter()
+                    .map(|p| Rgba([p[0], p[1], p[2], 1.0].map(f64::from)))
+                    .collect::<Vec<Rgba<f64>>>();
+                (width, height, pixels)
+            }
+            _ => {
+                let image = image::open(path)
+                    .with_context(|| format!("Couldn't read the image '{}'", path.display()))?
+                    .to_rgba16();
+                let decode = |c: u16| {
+                    let c = f64::from(c) / 65535.0;
+                    if self.srgb {
+                        srgb::decode(c)
+                    } else {
+                        c
+                    }
+                };
+                let pixels = image
+                    .pixels()
+                    .map(|p| {
+                        Rgba([
+                            decode(p[0]),
+                            decode(p[1]),
+                            decode(p[2]),
+                            f64::from(p[3]) / 65535.0,
+                        ])
+                    })
+                    .collect::<Vec<Rgba<f64>>>();
+                (image.width(), image.height(), pixels)
+            }
+        };
+
+        if (width, height) != (self.width, self.height) {
+            return Err(anyhow!(
+                "The image '{}' is {}x{} but the one being rendered is {}x{}",
+                path.display(),
+                width,
+                height,
+                self.width,
+                self.height
+            ));
+        }
+
+        for (pixel, loaded) in self.framebuffer.pixels_mut().zip(pixels) {
+            *pixel = loaded;
+        }
+        Ok(())
+    }
+
+    /// Red-cyan anaglyph of two images of the same size, with the red channel of the left one and
+    /// the green and blue channels of the right one
+    pub fn anaglyph(
+        left: &ScreenContextManager,
+        right: &ScreenContextManager,
+    ) -> ScreenContextManager {
+        let mut screen = left.blank(left.width, left.height);
+        screen.transparent = left.transparent || right.transparent;
+        for (x, y, pixel) in screen.framebuffer.enumerate_pixels_mut() {
+            let (l, r) = (
+                left.framebuffer.get_pixel(x, y),
+                right.framebuffer.get_pixel(x, y),
+            );
+            *pixel = Rgba([l[0], r[1], r[2], l[3].max(r[3])]);
+        }
+        screen
+    }
+
+    /// Both images one next to the other, in an image twice as wide
+    pub fn side_by_side(
+        left: &ScreenContextManager,
+        right: &ScreenContextManager,
+    ) -> ScreenContextManager {
+        let mut screen = left.blank(left.width + right.width, left.height);
+        screen.transparent = left.transparent || right.transparent;
+        for (x, y, pixel) in screen.framebuffer.enumerate_pixels_mut() {
+            *pixel = if x < left.width {
+                *left.framebuffer.get_pixel(x, y)
+            } else {
+                *right.framebuffer.get_pixel(x - left.width, y)
+            };
+        }
+        screen
+    }
+
     #[allow(dead_code)]
     /// Clears the entire framebuffer with a grey shadow given by a real number in the range [0,
     /// 1].
     pub fn clear(&mut self, shadow: f32) {
-        let shadow = (shadow * 255.0).round() as u8;
-        self.framebuffer.fill(shadow);
+        self.clear_with_rgb(shadow, shadow, shadow);
     }
 
     #[allow(dead_code)]
     /// Clears the entire framebuffer with the given color.
     /// Parameters correspond to RGB colors and must be real numbers in the range [0, 1].
     pub fn clear_with_rgb(&mut self, r: f32, g: f32, b: f32) {
-        let color = Rgb([
-            (r * 255.0).round() as u8,
-            (g * 255.0).round() as u8,
-            (b * 255.0).round() as u8,
-        ]);
+        let color = Rgba([f64::from(r), f64::from(g), f64::from(b), 1.0]);
 
         self.framebuffer = ImageBuffer::from_pixel(self.width, self.height, color);
     }
 
-    /// Saves the current framebuffer as an image whose format is derived from the file extension.
+    /// Saves the current framebuffer as an image whose format is derived from the file extension,
+    /// with its alpha channel if it's transparent. `.hdr` and `.exr` images are saved in floating
+    /// point, the others with the screen's bit depth. The path `-` writes a PPM to stdout. PNG and
+    /// EXR images get the metadata.
     pub fn save_img<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let buffer = bytemuck::cast_slice(&self.framebuffer);
-        image::save_buffer(
-            path,
-            buffer,
-            self.width,
-            self.height,
-            image::ColorType::Rgb8,
-        )
-        .map_err(Error::msg)
+        let path = path.as_ref();
+        if path == Path::new(STDOUT_IMAGE) {
+            return self.write_ppm(std::io::stdout().lock());
+        }
+        let channels = if self.transparent { 4 } else { 3 };
+
+        match extension(path).as_deref() {
+            Some("exr") => {
+                let pixels = self
+                    .framebuffer
+                    .pixels()
+                    .flat_map(|p| p.0[..channels].iter().map(|&c| c as f32))
+                    .collect::<Vec<f32>>();
+                write_exr(
+                    path,
+                    self.width,
+                    self.height,
+                    &["R", "G", "B", "A"][..channels],
+                    &pixels,
+                    &self.metadata_entries(),
+                )
+            }
+            Some("png") if !self.metadata.is_empty() => {
+                let (buffer, color_type) = self.quantized();
+                let mut png = Vec::new();
+                PngEncoder::new(&mut png)
+                    .write_image(&buffer, self.width, self.height, color_type)
+                    .map_err(Error::msg)?;
+                std::fs::write(path, add_text_chunks(&png, &self.metadata_entries()))
+                    .with_context(|| format!("Couldn't write the image '{}'", path.display()))
+            }
+            // Radiance images have no alpha channel
+            Some("hdr") => {
+                let pixels = self
+                    .framebuffer
+                    .pixels()
+                    .map(|p| Rgb([p[0] as f32, p[1] as f32, p[2] as f32]))
+                    .collect::<Vec<Rgb<f32>>>();
+                let file = std::fs::File::create(path)
+                    .with_context(|| format!("Couldn't write the image '{}'", path.display()))?;
+                HdrEncoder::new(BufWriter::new(file))
+                    .encode(&pixels, self.width as usize, self.height as usize)
+                    .map_err(Error::msg)
+            }
+            _ => {
+                let (buffer, color_type) = self.quantized();
+                image::save_buffer(path, &buffer, self.width, self.height, color_type)
+                    .map_err(Error::msg)
+            }
+        }
+    }
+
+    /// The metadata along with the render time, none if there's no metadata
+    fn metadata_entries(&self) -> Vec<(String, String)> {
+        if self.metadata.is_empty() {
+            return Vec::new();
+        }
+        let mut entries = self.metadata.clone();
+        entries.push((
+            "Render time".to_string(),
+            format!("{:.3} s", self.started.elapsed().as_secs_f64()),
+        ));
+        entries
+    }
+
+    /// Writes the image as a binary PPM, which has no alpha channel
+    fn write_ppm<W: Write>(&self, writer: W) -> Result<()> {
+        if self.transparent {
+            return Err(anyhow!(
+                "PPM images have no alpha channel, so transparent images can't be written as PPM"
+            ));
+        }
+
+        let (buffer, color_type) = self.quantized();
+        PnmEncoder::new(writer)
+            .with_subtype(PNMSubtype::Pixmap(SampleEncoding::Binary))
+            .write_image(&buffer, self.width, self.height, color_type)
+            .map_err(Error::msg)
+    }
+
+    /// The image's channels between 0 and 1 quantized to its bit depth, as the bytes of an image of
+    /// the returned color type
+    fn quantized(&self) -> (Vec<u8>, image::ColorType) {
+        let channels = if self.transparent { 4 } else { 3 };
+        let quantize = |max: f32| {
+            self.framebuffer
+                .pixels()
+                .flat_map(move |p| {
+                    let encode = |c: f64| {
+                        let c = if self.srgb { srgb::encode(c) } else { c };
+                        (c.clamp(0.0, 1.0) as f32 * max).round()
+                    };
+                    let alpha = (p[3] as f32 * max).round();
+                    [encode(p[0]), encode(p[1]), encode(p[2]), alpha]
+                        .into_iter()
+                        .take(channels)
+                })
+                .collect::<Vec<f32>>()
+        };
+
+        let color_type = match (self.bit_depth, self.transparent) {
+            (BitDepth::Eight, false) => image::ColorType::Rgb8,
+            (BitDepth::Eight, true) => image::ColorType::Rgba8,
+            (BitDepth::Sixteen, false) => image::ColorType::Rgb16,
+            (BitDepth::Sixteen, true) => image::ColorType::Rgba16,
+        };
+        let buffer = match self.bit_depth {
+            BitDepth::Eight => quantize(255.0)
+                .into_iter()
+                .map(|c| c as u8)
+                .collect::<Vec<u8>>(),
+            BitDepth::Sixteen => {
+                let buffer = quantize(65535.0)
+                    .into_iter()
+                    .map(|c| c as u16)
+                    .collect::<Vec<u16>>();
+                bytemuck::cast_slice(&buffer).to_vec()
+            }
+        };
+        (buffer, color_type)
     }
 }
@@ -1,12 +1,24 @@
 use anyhow::{anyhow, Result};
 use enum_dispatch::enum_dispatch;
+use serde::Serialize;
 use std::iter::Sum;
 use std::ops;
-
-use crate::constants::TOLERANCE;
-use crate::vec3::Vec3;
-
-#[derive(Debug, Clone)]
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::constants::{
+    BUMP_STEP, METABALL_STEP, SDF_HIT_DISTANCE, SDF_MAX_STEPS, TOLERANCE, TOLERANCE_MUL,
+    TRIPLANAR_SHARPNESS,
+};
+use crate::noise::Pattern;
+use crate::obj::ObjData;
+use crate::solvers::{solve_quadratic, solve_quartic};
+use crate::texture::{Gradient, ImageTexture, Wrap};
+use crate::transform::Matrix4;
+use crate::vec3::{Onb, Vec3};
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Ray {
     pub anchor: Vec3,
     pub dir: Vec3,
@@ -51,7 +63,7 @@ pub mod colors {
     };
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Serialize)]
 pub struct Color {
     pub r: f64,
     pub g: f64,
@@ -90,6 +102,20 @@ impl Color {
         }
     }
 
+    /// Whether every channel is 0 (or below)
+    pub fn is_black(&self) -> bool {
+        self.r <= 0.0 && self.g <= 0.0 && self.b <= 0.0
+    }
+
+    /// Clamps every channel to the displayable [0, 1] range
+    pub fn clamp(self) -> Color {
+        Self {
+            r: self.r.clamp(0.0, 1.0),
+            g: self.g.clamp(0.0, 1.0),
+            b: self.b.clamp(0.0, 1.0),
+        }
+    }
+
     pub fn min(self, min_val: f64) -> Color {
         Self {
             r: self.r.min(min_val),
@@ -121,15 +147,17 @@ impl ops::Mul<Color> for f64 {
     }
 }
 
+// Colors carry linear radiance, so arithmetic is left unclamped to keep the energy of bright or
+// recursive contributions. Clamping to [0, 1] only happens at output, see `Color::clamp`.
 macro_rules! color_op_vec {
     ($($path:ident)::+, $fn:ident) => {
         impl $($path)::+ for Color {
             type Output = Self;
             fn $fn(self, other: Self) -> Self::Output {
                 Color {
-                    r: self.r.$fn(other.r).clamp(0.0, 1.0),
-                    g: self.g.$fn(other.g).clamp(0.0, 1.0),
-                    b: self.b.$fn(other.b).clamp(0.0, 1.0),
+                    r: self.r.$fn(other.r),
+                    g: self.g.$fn(other.g),
+                    b: self.b.$fn(other.b),
                 }
             }
         }
@@ -137,9 +165,20 @@ macro_rules! color_op_vec {
 }
 
 color_op_vec!(ops::Add, add);
-color_op_vec!(ops::Sub, sub);
 color_op_vec!(ops::Mul, mul);
 
+// Radiance can't be negative
+impl ops::Sub for Color {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self::Output {
+        Color {
+            r: (self.r - other.r).max(0.0),
+            g: (self.g - other.g).max(0.0),
+            b: (self.b - other.b).max(0.0),
+        }
+    }
+}
+
 impl Sum<Self> for Color {
     fn sum<I>(iter: I) -> Self
     where
@@ -150,10 +189,10 @@ impl Sum<Self> for Color {
 }
 
 fn is_hex_format(hex: &str) -> bool {
-    hex.starts_with('#') && hex.len() == 7 && hex[1..].chars().all(|d| d.is_digit(16))
+    hex.starts_with('#') && hex.len() == 7 && hex[1..].chars().all(|d| d.is_ascii_hexdigit())
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Plane {
     normal: Vec3,
     anchor: Vec3,
@@ -196,17 +235,31 @@ impl ShapeCalculations for Plane {
     }
 
     fn get_texture_coords(&self, intersection: Vec3) -> TextureCoords {
-        let mut x_axis = self.normal.cross(Vec3::new(0.0, 0.0, 1.0));
-        if x_axis.norm() == 0.0 {
-            x_axis = self.normal.cross(Vec3::new(0.0, 1.0, 0.0));
-        }
-        let y_axis = self.normal.cross(x_axis);
+        planar_texture_coords(&Onb::from_normal(self.normal), intersection - self.anchor)
+    }
 
-        let plane_vec = intersection - self.anchor;
+    // The plane is treated as the half-space behind its normal
+    fn is_solid(&self) -> bool {
+        true
+    }
 
-        TextureCoords {
-            x: plane_vec.dot(x_axis),
-            y: plane_vec.dot(y_axis),
+    fn get_spans(&self, ray: &Ray) -> Vec<Span> {
+        let distance = (ray.anchor - self.anchor).dot(self.normal);
+        let approach = ray.dir.dot(self.normal);
+
+        if approach.abs() < TOLERANCE {
+            if distance < 0.0 {
+                vec![Span::new(f64::NEG_INFINITY, f64::INFINITY)]
+            } else {
+                vec![]
+            }
+        } else {
+            let t = -distance / approach;
+            if approach > 0.0 {
+                vec![Span::new(f64::NEG_INFINITY, t)]
+            } else {
+                vec![Span::new(t, f64::INFINITY)]
+            }
         }
     }
 
@@ -215,7 +268,7 @@ impl ShapeCalculations for Plane {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Disc {
     normal: Vec3,
     center: Vec3,
@@ -245,7 +298,7 @@ impl ShapeCalculations for Disc {
         } else {
             let t = 1.0 * (self.center - ray.anchor).dot(normal) / denominator;
             // Check it's in front of camera + inside radius
-            if t > 0.0 && (ray.point_at_t(t) - self.center).norm() <= self.r {
+            if t > 0.0 && (ray.point_at_t(t) - self.center).length_squared() <= self.r * self.r {
                 Some(t)
             } else {
                 None
@@ -258,86 +311,292 @@ impl ShapeCalculations for Disc {
     }
 
     fn get_texture_coords(&self, intersection: Vec3) -> TextureCoords {
-        let mut x_axis = self.normal.cross(Vec3::new(0.0, 0.0, 1.0));
-        if x_axis.norm() == 0.0 {
-            x_axis = self.normal.cross(Vec3::new(0.0, 1.0, 0.0));
+        planar_texture_coords(&Onb::from_normal(self.normal), intersection - self.center)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::around(self.center, self.r))
+    }
+
+    fn get_params(&self) -> &ObjectParameters {
+        &self.params
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Ring {
+    normal: Vec3,
+    center: Vec3,
+    r_inner: f64,
+    r_outer: f64,
+    params: ObjectParameters,
+}
+
+impl Ring {
+    pub fn new(
+        normal: Vec3,
+        center: Vec3,
+        r_inner: f64,
+        r_outer: f64,
+        params: ObjectParameters,
+    ) -> Ring {
+        Ring {
+            center,
+            normal: normal.normalize(),
+            r_inner,
+            r_outer,
+            params,
         }
-        let y_axis = self.normal.cross(x_axis);
+    }
+}
 
-        let plane_vec = intersection - self.center;
+impl ShapeCalculations for Ring {
+    /// Returns the distance "t" from the camera to the point
+    fn get_intersection(&self, ray: &Ray) -> Option<f64> {
+        let normal = self.normal;
+        let denominator = normal.dot(ray.dir);
 
-        TextureCoords {
-            x: plane_vec.dot(x_axis),
-            y: plane_vec.dot(y_axis),
+        if denominator.abs() < TOLERANCE {
+            None
+        } else {
+            let t = 1.0 * (self.center - ray.anchor).dot(normal) / denominator;
+            let distance2 = (ray.point_at_t(t) - self.center).length_squared();
+            // Check it's in front of camera + between both radii
+            if t > 0.0
+                && distance2 >= self.r_inner * self.r_inner
+                && distance2 <= self.r_outer * self.r_outer
+            {
+                Some(t)
+            } else {
+                None
+            }
         }
     }
 
+    fn get_normal_vec(&self, _: Vec3) -> Vec3 {
+        self.normal
+    }
+
+    fn get_texture_coords(&self, intersection: Vec3) -> TextureCoords {
+        planar_texture_coords(&Onb::from_normal(self.normal), intersection - self.center)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::around(self.center, self.r_outer))
+    }
+
     fn get_params(&self) -> &ObjectParameters {
         &self.params
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Triangle {
     normal: Vec3,
     a: Vec3,
     b: Vec3,
     c: Vec3,
+    /// Normals at a, b and c that get interpolated for smooth shading
+    vertex_normals: Option<[Vec3; 3]>,
     params: ObjectParameters,
 }
 
 impl Triangle {
-    pub fn new(a: Vec3, b: Vec3, c: Vec3, params: ObjectParameters) -> Triangle {
+    pub fn new(
+        a: Vec3,
+        b: Vec3,
+        c: Vec3,
+        vertex_normals: Option<[Vec3; 3]>,
+        params: ObjectParameters,
+    ) -> Triangle {
         let normal = (b - a).cross(c - a).normalize();
         Triangle {
             a,
             b,
             c,
             normal,
+            vertex_normals: vertex_normals.map(|normals| normals.map(|n| n.normalize())),
             params,
         }
     }
 }
 
+/// Intersection of a ray with the triangle (a, b, c) whose plane has the given normal
+fn triangle_intersection(a: Vec3, b: Vec3, c: Vec3, normal: Vec3, ray: &Ray) -> Option<f64> {
+    let denominator = normal.dot(ray.dir);
+
+    if denominator.abs() < TOLERANCE {
+        None
+    } else {
+        let t = 1.0 * (a - ray.anchor).dot(normal) / denominator;
+
+        // Check it's in front of camera and inside the triangle
+        if t > 0.0 && Barycentric::of(a, b, c, ray.point_at_t(t)).is_inside() {
+            Some(t)
+        } else {
+            None
+        }
+    }
+}
+
+/// Barycentric coordinates of a point relative to the vertices (a, b, c) of a triangle, where
+/// `alpha`, `beta` and `gamma` are the weights of a, b and c respectively.
+#[derive(Debug, Clone, Copy)]
+pub struct Barycentric {
+    pub alpha: f64,
+    pub beta: f64,
+    pub gamma: f64,
+}
+
+impl Barycentric {
+    /// Barycentric coordinates of a point lying on the plane of the triangle (a, b, c)
+    pub fn of(a: Vec3, b: Vec3, c: Vec3, p: Vec3) -> Barycentric {
+        // ref: https://math.stackexchange.com/a/544947
+        let u = b - a;
+        let v = c - a;
+
+        let n = u.cross(v);
+        let w = p - a;
+
+        let n2 = n.dot(n);
+        let gamma = (u.cross(w).dot(n)) / n2;
+        let beta = (w.cross(v).dot(n)) / n2;
+
+        Barycentric {
+            alpha: 1.0 - gamma - beta,
+            beta,
+            gamma,
+        }
+    }
+
+    pub fn is_inside(&self) -> bool {
+        (0.0..=1.0).contains(&self.alpha)
+            && (0.0..=1.0).contains(&self.beta)
+            && (0.0..=1.0).contains(&self.gamma)
+    }
+
+    /// Interpolates per-vertex attributes (normals, texture coords, colors...)
+    pub fn interpolate<T>(&self, a: T, b: T, c: T) -> T
+    where
+        T: ops::Add<Output = T> + ops::Mul<f64, Output = T>,
+    {
+        a * self.alpha + b * self.beta + c * self.gamma
+    }
+}
+
 impl ShapeCalculations for Triangle {
     /// Returns the distance "t" from the camera to the point
     fn get_intersection(&self, ray: &Ray) -> Option<f64> {
-        let normal = self.normal;
-        let denominator = normal.dot(ray.dir);
+        triangle_intersection(self.a, self.b, self.c, self.normal, ray)
+    }
 
-        if denominator.abs() < TOLERANCE {
-            None
-        } else {
-            let t = 1.0 * (self.a - ray.anchor).dot(normal) / denominator;
+    fn get_normal_vec(&self, intersection: Vec3) -> Vec3 {
+        match self.vertex_normals {
+            Some([n_a, n_b, n_c]) => Barycentric::of(self.a, self.b, self.c, intersection)
+                .interpolate(n_a, n_b, n_c)
+                .normalize(),
+            None => self.normal,
+        }
+    }
+
+    fn get_texture_coords(&self, intersection: Vec3) -> TextureCoords {
+        planar_texture_coords(&Onb::from_normal(self.normal), intersection - self.a)
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::from_points([self.a, self.b, self.c]))
+    }
 
-            // get barycentric coords
-            // ref: https://math.stackexchange.com/a/544947
-            let p = ray.point_at_t(t);
+    fn get_params(&self) -> &ObjectParameters {
+        &self.params
+    }
+}
 
-            let u = self.b - self.a;
-            let v = self.c - self.a;
+/// Planar convex polygon, its normal follows the right hand rule over the order of the vertices
+#[derive(Clone, Debug, Serialize)]
+pub struct Polygon {
+    normal: Vec3,
+    vertices: Vec<Vec3>,
+    params: ObjectParameters,
+}
 
-            let n = u.cross(v);
-            let w = p - self.a;
+impl Polygon {
+    pub fn new(vertices: Vec<Vec3>, params: ObjectParameters) -> Result<Polygon> {
+        if vertices.len() < 3 {
+            return Err(anyhow!(
+                "the polygon must have at least 3 vertices and it has {}",
+                vertices.len()
+            ));
+        }
 
-            let n2 = n.dot(n);
-            let gamma = (u.cross(w).dot(n)) / n2;
-            let beta = (w.cross(v).dot(n)) / n2;
-            let alpha = 1.0 - gamma - beta;
+        // Newell's method, robust to collinear consecutive vertices
+        let normal = vertices
+            .iter()
+            .zip(vertices.iter().cycle().skip(1))
+            .fold(Vec3::new(0.0, 0.0, 0.0), |n, (&a, &b)| n + a.cross(b));
+        if normal.near_zero() {
+            return Err(anyhow!(
+                "the polygon's vertices are collinear, it has no area"
+            ));
+        }
+        let normal = normal.normalize();
+
+        let tolerance = TOLERANCE * TOLERANCE_MUL;
+        if vertices
+            .iter()
+            .any(|&v| (v - vertices[0]).dot(normal).abs() > tolerance)
+        {
+            return Err(anyhow!(
+                "the polygon's vertices don't lie on the same plane"
+            ));
+        }
 
-            // Check it's in front of camera
-            if t > 0.0
-                && 0.0 <= alpha
-                && alpha <= 1.0
-                && 0.0 <= beta
-                && beta <= 1.0
-                && 0.0 <= gamma
-                && gamma <= 1.0
-            {
-                Some(t)
-            } else {
-                None
-            }
+        let polygon = Polygon {
+            normal,
+            vertices,
+            params,
+        };
+        // every vertex must be on the inner side of every edge, this also rules out stars
+        if polygon.vertices.iter().any(|&v| {
+            polygon
+                .edges()
+                .any(|(a, b)| (b - a).cross(v - a).dot(normal) < -tolerance)
+        }) {
+            return Err(anyhow!("the polygon isn't convex"));
+        }
+
+        Ok(polygon)
+    }
+
+    fn edges(&self) -> impl Iterator<Item = (Vec3, Vec3)> + '_ {
+        self.vertices
+            .iter()
+            .copied()
+            .zip(self.vertices.iter().copied().cycle().skip(1))
+    }
+}
+
+impl ShapeCalculations for Polygon {
+    /// Returns the distance "t" from the camera to the point
+    fn get_intersection(&self, ray: &Ray) -> Option<f64> {
+        let denominator = self.normal.dot(ray.dir);
+
+        if denominator.abs() < TOLERANCE {
+            return None;
+        }
+
+        let t = (self.vertices[0] - ray.anchor).dot(self.normal) / denominator;
+        let point = ray.point_at_t(t);
+
+        // Check it's in front of camera and on the inner side of every edge
+        if t > 0.0
+            && self
+                .edges()
+                .all(|(a, b)| (b - a).cross(point - a).dot(self.normal) >= 0.0)
+        {
+            Some(t)
+        } else {
+            None
         }
     }
 
@@ -346,26 +605,196 @@ impl ShapeCalculations for Triangle {
     }
 
     fn get_texture_coords(&self, intersection: Vec3) -> TextureCoords {
-        let mut x_axis = self.normal.cross(Vec3::new(0.0, 0.0, 1.0));
-        if x_axis.norm() == 0.0 {
-            x_axis = self.normal.cross(Vec3::new(0.0, 1.0, 0.0));
+        planar_texture_coords(
+            &Onb::from_normal(self.normal),
+            intersection - self.vertices[0],
+        )
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::from_points(self.vertices.iter().copied()))
+    }
+
+    fn get_params(&self) -> &ObjectParameters {
+        &self.params
+    }
+}
+
+#[derive(Clone, Debug)]
+struct MeshFace {
+    a: Vec3,
+    b: Vec3,
+    c: Vec3,
+    normal: Vec3,
+    vertex_normals: Option<[Vec3; 3]>,
+    vertex_texture_coords: Option<[TextureCoords; 3]>,
+}
+
+impl MeshFace {
+    fn barycentric(&self, p: Vec3) -> Barycentric {
+        Barycentric::of(self.a, self.b, self.c, p)
+    }
+
+    fn get_intersection(&self, ray: &Ray) -> Option<f64> {
+        triangle_intersection(self.a, self.b, self.c, self.normal, ray)
+    }
+}
+
+/// Triangle mesh, usually loaded from an OBJ file
+#[derive(Clone, Debug, Serialize)]
+pub struct Mesh {
+    /// Left out of scene dumps, where the bounds are enough to tell where the mesh ended up
+    #[serde(skip)]
+    faces: Vec<MeshFace>,
+    /// Hierarchy over the faces, so neither the rays nor the lookups of the face a point lies on
+    /// go through all of them
+    #[serde(skip)]
+    bvh: Bvh,
+    bounds: Aabb,
+    params: ObjectParameters,
+}
+
+impl Mesh {
+    /// Builds the mesh out of the OBJ's geometry, scaled, then rotated into `basis` and then
+    /// moved to `position`. Degenerate faces are dropped, and it fails if that leaves none.
+    pub fn from_obj(
+        data: &ObjData,
+        scale: f64,
+        basis: &Onb,
+        position: Vec3,
+        params: ObjectParameters,
+    ) -> Result<Mesh> {
+        let transform = |p: Vec3| basis.local_to_world(p * scale) + position;
+
+        let faces: Vec<MeshFace> = data
+            .triangles
+            .iter()
+            .filter_map(|[a, b, c]| {
+                let (p_a, p_b, p_c) = (
+                    transform(data.positions[a.position]),
+                    transform(data.positions[b.position]),
+                    transform(data.positions[c.position]),
+                );
+
+                let cross = (p_b - p_a).cross(p_c - p_a);
+                if cross.near_zero() {
+                    return None;
+                }
+
+                let vertex_normals = match (a.normal, b.normal, c.normal) {
+                    (Some(n_a), Some(n_b), Some(n_c)) => Some(
+                        [n_a, n_b, n_c].map(|n| basis.local_to_world(data.normals[n]).normalize()),
+                    ),
+                    _ => None,
+                };
+
+                let vertex_texture_coords =
+                    match (a.texture_coords, b.texture_coords, c.texture_coords) {
+                        (Some(t_a), Some(t_b), Some(t_c)) => Some([t_a, t_b, t_c].map(|t| {
+                            let (x, y) = data.texture_coords[t];
+                            TextureCoords { x, y }
+                        })),
+                        _ => None,
+                    };
+
+                Some(MeshFace {
+                    a: p_a,
+                    b: p_b,
+                    c: p_c,
+                    normal: cross.normalize(),
+                    vertex_normals,
+                    vertex_texture_coords,
+                })
+            })
+            .collect();
+        if faces.is_empty() {
+            return Err(anyhow!("The mesh has no faces with an area"));
         }
-        let y_axis = self.normal.cross(x_axis);
 
-        let plane_vec = intersection - self.a;
+        let bounds = Aabb::from_points(faces.iter().flat_map(|f| [f.a, f.b, f.c]));
+        let bvh = Bvh::new(
+            faces
+                .iter()
+                .map(|f| Some(Aabb::from_points([f.a, f.b, f.c]))),
+        );
+
+        Ok(Mesh {
+            faces,
+            bvh,
+            bounds,
+            params,
+        })
+    }
 
-        TextureCoords {
-            x: plane_vec.dot(x_axis),
-            y: plane_vec.dot(y_axis),
+    /// Face on which a point of the mesh' surface lies
+    fn face_at(&self, point: Vec3) -> &MeshFace {
+        let inside_distance = |face: &MeshFace| {
+            if face.barycentric(point).is_inside() {
+                (point - face.a).dot(face.normal).abs()
+            } else {
+                f64::INFINITY
+            }
+        };
+
+        // only the faces whose boxes contain the point can have it, the first face stands in for
+        // points that fell out of all of them
+        self.bvh
+            .objects_at(point)
+            .into_iter()
+            .map(|i| &self.faces[i])
+            .min_by(|f1, f2| inside_distance(f1).total_cmp(&inside_distance(f2)))
+            .unwrap_or(&self.faces[0])
+    }
+}
+
+impl ShapeCalculations for Mesh {
+    /// Returns the distance "t" from the camera to the point
+    fn get_intersection(&self, ray: &Ray) -> Option<f64> {
+        if !self.bounds.hit(ray) {
+            return None;
+        }
+
+        self.bvh
+            .closest_hit(
+                ray,
+                f64::INFINITY,
+                |i| self.faces[i].get_intersection(ray),
+                |_, _| true,
+            )
+            .map(|(t, _)| t)
+    }
+
+    fn get_normal_vec(&self, intersection: Vec3) -> Vec3 {
+        let face = self.face_at(intersection);
+
+        match face.vertex_normals {
+            Some([n_a, n_b, n_c]) => face
+                .barycentric(intersection)
+                .interpolate(n_a, n_b, n_c)
+                .normalize(),
+            None => face.normal,
+        }
+    }
+
+    fn get_texture_coords(&self, intersection: Vec3) -> TextureCoords {
+        let face = self.face_at(intersection);
+
+        match face.vertex_texture_coords {
+            Some([t_a, t_b, t_c]) => face.barycentric(intersection).interpolate(t_a, t_b, t_c),
+            None => planar_texture_coords(&Onb::from_normal(face.normal), intersection - face.a),
         }
     }
 
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+
     fn get_params(&self) -> &ObjectParameters {
         &self.params
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Sphere {
     center: Vec3,
     r: f64,
@@ -381,19 +810,10 @@ impl Sphere {
 impl ShapeCalculations for Sphere {
     /// Returns the distance "t" from the camera to the point
     fn get_intersection(&self, ray: &Ray) -> Option<f64> {
-        let anchor = ray.anchor;
-        let dir = ray.dir;
-        let center = self.center;
-
-        let b = 2.0
-            * (dir.x * (anchor.x - center.x)
-                + dir.y * (anchor.y - center.y)
-                + dir.z * (anchor.z - center.z));
+        let oc = ray.anchor - self.center;
 
-        let c = (anchor.x - center.x).powi(2)
-            + (anchor.y - center.y).powi(2)
-            + (anchor.z - center.z).powi(2)
-            - (self.r * self.r);
+        let b = 2.0 * ray.dir.dot(oc);
+        let c = oc.length_squared() - (self.r * self.r);
 
         let determinant = (b * b - 4.0 * c).sqrt();
 
@@ -407,9 +827,8 @@ impl ShapeCalculations for Sphere {
             } else if t2 < 0.0 {
                 None
             } else {
+                // the ray starts inside the sphere
                 Some(t2)
-                // panic!("No está implementado el caso de la cámara dentro de una esfera");
-                // Normalmente se retornaría t2
             }
         }
     }
@@ -422,26 +841,131 @@ impl ShapeCalculations for Sphere {
         let spherical_vec = intersection - self.center;
         //let circumference = 2.0 * PI * self.r;
         TextureCoords {
-            x: 2.0 * self.r * (1.0 + (spherical_vec.z.atan2(spherical_vec.x) as f64)),
-            y: 2.0 * self.r * (spherical_vec.y / self.r).acos() as f64,
+            x: 2.0 * self.r * (1.0 + spherical_vec.z.atan2(spherical_vec.x)),
+            y: 2.0 * self.r * (spherical_vec.y / self.r).acos(),
         }
     }
 
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn get_spans(&self, ray: &Ray) -> Vec<Span> {
+        let oc = ray.anchor - self.center;
+        spans_from_roots(&solve_quadratic(
+            ray.dir.length_squared(),
+            2.0 * ray.dir.dot(oc),
+            oc.length_squared() - self.r * self.r,
+        ))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::around(self.center, self.r))
+    }
+
     fn get_params(&self) -> &ObjectParameters {
         &self.params
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
+pub struct Ellipsoid {
+    center: Vec3,
+    /// Orientation of the ellipsoid's axes
+    basis: Onb,
+    /// Radii along each of the basis' axes
+    radii: Vec3,
+    params: ObjectParameters,
+}
+
+impl Ellipsoid {
+    pub fn new(center: Vec3, radii: Vec3, basis: Onb, params: ObjectParameters) -> Ellipsoid {
+        Ellipsoid {
+            center,
+            basis,
+            radii,
+            params,
+        }
+    }
+
+    /// Every "t" where the ray's line crosses the surface, sorted
+    fn get_roots(&self, ray: &Ray) -> Vec<f64> {
+        // Same as the sphere but with the ray scaled into unit sphere space. The direction isn't
+        // renormalized so that "t" stays the same in both spaces.
+        let anchor = self.to_unit_space(ray.anchor);
+        let dir = self.basis.world_to_local(ray.dir) / self.radii;
+
+        solve_quadratic(
+            dir.length_squared(),
+            2.0 * anchor.dot(dir),
+            anchor.length_squared() - 1.0,
+        )
+    }
+
+    /// Takes a world point to the space where the ellipsoid is a unit sphere at the origin
+    fn to_unit_space(&self, point: Vec3) -> Vec3 {
+        self.basis.world_to_local(point - self.center) / self.radii
+    }
+}
+
+impl ShapeCalculations for Ellipsoid {
+    /// Returns the distance "t" from the camera to the point
+    fn get_intersection(&self, ray: &Ray) -> Option<f64> {
+        self.get_roots(ray).into_iter().find(|t| *t > 0.0)
+    }
+
+    fn get_normal_vec(&self, intersection: Vec3) -> Vec3 {
+        // gradient of the implicit function, x^2/rx^2 + y^2/ry^2 + z^2/rz^2
+        let local = self.to_unit_space(intersection) / self.radii;
+        self.basis.local_to_world(local).normalize()
+    }
+
+    fn get_texture_coords(&self, intersection: Vec3) -> TextureCoords {
+        let unit = self.to_unit_space(intersection);
+        let r = (self.radii.x + self.radii.y + self.radii.z) / 3.0;
+        TextureCoords {
+            x: 2.0 * r * (1.0 + unit.z.atan2(unit.x)),
+            y: 2.0 * r * unit.y.clamp(-1.0, 1.0).acos(),
+        }
+    }
+
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn get_spans(&self, ray: &Ray) -> Vec<Span> {
+        spans_from_roots(&self.get_roots(ray))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let r = self.radii.x.max(self.radii.y).max(self.radii.z);
+        Some(Aabb::around(self.center, r))
+    }
+
+    fn get_params(&self) -> &ObjectParameters {
+        &self.params
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub struct Cylinder {
     ray: Ray,
     r: f64,
     length: f64,
+    /// Whether both ends are closed, making the cylinder a solid
+    caps: bool,
     params: ObjectParameters,
 }
 
 impl Cylinder {
-    pub fn new(anchor: Vec3, dir: Vec3, r: f64, length: f64, params: ObjectParameters) -> Cylinder {
+    pub fn new(
+        anchor: Vec3,
+        dir: Vec3,
+        r: f64,
+        length: f64,
+        caps: bool,
+        params: ObjectParameters,
+    ) -> Cylinder {
         Cylinder {
             ray: Ray {
                 anchor,
@@ -449,6 +973,7 @@ impl Cylinder {
             },
             r,
             length,
+            caps,
             params,
         }
     }
@@ -457,22 +982,41 @@ impl Cylinder {
         let l = intersection - self.ray.anchor;
         l.dot(self.ray.dir)
     }
+
+    /// Whether a point of the surface lies on one of the caps rather than the tube
+    fn is_on_cap(&self, intersection: Vec3) -> bool {
+        let d = self.get_length_at_inter(intersection);
+        self.caps && (d < TOLERANCE * TOLERANCE_MUL || d > self.length - TOLERANCE * TOLERANCE_MUL)
+    }
 }
 
 impl ShapeCalculations for Cylinder {
     /// Returns the distance "t" from the camera to the point
     fn get_intersection(&self, ray: &Ray) -> Option<f64> {
-        //// First we displace the ray's anchor to align with the origin
+        if self.caps {
+            // the capped cylinder is a solid so the first crossing of its inside is the hit
+            return self
+                .get_spans(ray)
+                .iter()
+                .flat_map(|span| [span.enter, span.exit])
+                .find(|t| *t > 0.0);
+        }
+
+        //// First we displace the ray's anchor to align with the origin
         let displaced_anchor =
             ray.anchor
                 .translation(-self.ray.anchor.x, -self.ray.anchor.y, -self.ray.anchor.z);
 
         // then, figure out rotation matrix
-        let rotation = self.ray.dir.to_align(Vec3 {
-            x: 0.0,
-            y: 1.0,
-            z: 0.0,
-        });
+        let rotation = self
+            .ray
+            .dir
+            .to_align(Vec3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            })
+            .expect("the direction is checked to not be zero when parsed");
 
         // then rotate
         let rotated_dir = ray.dir.apply_matrix(rotation);
@@ -525,38 +1069,123 @@ impl ShapeCalculations for Cylinder {
     fn get_normal_vec(&self, intersection: Vec3) -> Vec3 {
         //println!("Normal intersection at: {:?}", intersection);
         let d = self.get_length_at_inter(intersection);
+
+        if self.is_on_cap(intersection) {
+            return if d < self.length / 2.0 {
+                -self.ray.dir
+            } else {
+                self.ray.dir
+            };
+        }
+
         let v_m = self.ray.point_at_t(d);
 
         (intersection - v_m).normalize()
     }
 
     fn get_texture_coords(&self, intersection: Vec3) -> TextureCoords {
+        if self.is_on_cap(intersection) {
+            let d = self.get_length_at_inter(intersection);
+            return planar_texture_coords(
+                &Onb::from_normal(self.ray.dir),
+                intersection - self.ray.point_at_t(d),
+            );
+        }
+
         //// First we displace the ray's anchor to align with the origin
         let displaced_intersection =
             intersection.translation(-self.ray.anchor.x, -self.ray.anchor.y, -self.ray.anchor.z);
 
         // then, figure out rotation matrix
-        let rotation = self.ray.dir.to_align(Vec3 {
-            x: 0.0,
-            y: 1.0,
-            z: 0.0,
-        });
+        let rotation = self
+            .ray
+            .dir
+            .to_align(Vec3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            })
+            .expect("the direction is checked to not be zero when parsed");
 
         // then rotate
         let rotated_intersection = displaced_intersection.apply_matrix(rotation);
 
         TextureCoords {
-            x: self.r * (1.0 + rotated_intersection.z.atan2(rotated_intersection.x) as f64),
+            x: self.r * (1.0 + rotated_intersection.z.atan2(rotated_intersection.x)),
             y: rotated_intersection.y,
         }
     }
 
+    fn is_solid(&self) -> bool {
+        self.caps
+    }
+
+    // an open tube can be seen from within too
+    fn has_inside(&self) -> bool {
+        true
+    }
+
+    fn get_spans(&self, ray: &Ray) -> Vec<Span> {
+        if !self.caps {
+            return Vec::new();
+        }
+
+        let axis = self.ray.dir;
+        let oc = ray.anchor - self.ray.anchor;
+
+        // inside the infinite tube: distance to the axis below the radius
+        let dir_perp = ray.dir - ray.dir.dot(axis) * axis;
+        let oc_perp = oc - oc.dot(axis) * axis;
+        let a = dir_perp.length_squared();
+        let c = oc_perp.length_squared() - self.r * self.r;
+        let tube = if a < TOLERANCE {
+            if c < 0.0 {
+                Span::new(f64::NEG_INFINITY, f64::INFINITY)
+            } else {
+                return Vec::new();
+            }
+        } else {
+            match solve_quadratic(a, 2.0 * dir_perp.dot(oc_perp), c)[..] {
+                [t1, t2] => Span::new(t1, t2),
+                _ => return Vec::new(),
+            }
+        };
+
+        // between the two caps
+        let along = ray.dir.dot(axis);
+        let start = oc.dot(axis);
+        let slab = if along.abs() < TOLERANCE {
+            if (0.0..=self.length).contains(&start) {
+                Span::new(f64::NEG_INFINITY, f64::INFINITY)
+            } else {
+                return Vec::new();
+            }
+        } else {
+            let t1 = -start / along;
+            let t2 = (self.length - start) / along;
+            Span::new(t1.min(t2), t1.max(t2))
+        };
+
+        let enter = tube.enter.max(slab.enter);
+        let exit = tube.exit.min(slab.exit);
+        if enter < exit {
+            vec![Span::new(enter, exit)]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let end = self.ray.point_at_t(self.length);
+        Some(Aabb::around(self.ray.anchor, self.r).union(&Aabb::around(end, self.r)))
+    }
+
     fn get_params(&self) -> &ObjectParameters {
         &self.params
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Cone {
     ray: Ray,
     length: f64,
@@ -603,11 +1232,15 @@ impl ShapeCalculations for Cone {
                 .translation(-self.ray.anchor.x, -self.ray.anchor.y, -self.ray.anchor.z);
 
         // then, figure out rotation matrix
-        let rotation = self.ray.dir.to_align(Vec3 {
-            x: 0.0,
-            y: 1.0,
-            z: 0.0,
-        });
+        let rotation = self
+            .ray
+            .dir
+            .to_align(Vec3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            })
+            .expect("the direction is checked to not be zero when parsed");
 
         // then rotate
         let rotated_dir = ray.dir.apply_matrix(rotation);
@@ -672,47 +1305,1193 @@ impl ShapeCalculations for Cone {
             intersection.translation(-self.ray.anchor.x, -self.ray.anchor.y, -self.ray.anchor.z);
 
         // then, figure out rotation matrix
-        let rotation = self.ray.dir.to_align(Vec3 {
-            x: 0.0,
-            y: 1.0,
-            z: 0.0,
-        });
+        let rotation = self
+            .ray
+            .dir
+            .to_align(Vec3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            })
+            .expect("the direction is checked to not be zero when parsed");
 
         // then rotate
         let rotated_intersection = displaced_intersection.apply_matrix(rotation);
 
         TextureCoords {
             x: self.r_at(self.get_length_at_inter(intersection))
-                * (1.0 + rotated_intersection.z.atan2(rotated_intersection.x) as f64),
+                * (1.0 + rotated_intersection.z.atan2(rotated_intersection.x)),
             y: rotated_intersection.y,
         }
     }
 
+    fn bounds(&self) -> Option<Aabb> {
+        let end = self.ray.point_at_t(self.length);
+        Some(
+            Aabb::from_points([self.ray.anchor])
+                .union(&Aabb::around(end, self.r_at(self.length).abs())),
+        )
+    }
+
+    fn get_params(&self) -> &ObjectParameters {
+        &self.params
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Torus {
+    center: Vec3,
+    /// Frame whose `w` is the axis of revolution
+    basis: Onb,
+    /// Distance from the center to the middle of the tube
+    major_r: f64,
+    /// Radius of the tube
+    minor_r: f64,
+    params: ObjectParameters,
+}
+
+impl Torus {
+    pub fn new(
+        center: Vec3,
+        axis: Vec3,
+        major_r: f64,
+        minor_r: f64,
+        params: ObjectParameters,
+    ) -> Torus {
+        Torus {
+            center,
+            basis: Onb::from_normal(axis),
+            major_r,
+            minor_r,
+            params,
+        }
+    }
+
+    /// Every "t" where the ray's line crosses the surface, sorted
+    fn get_roots(&self, ray: &Ray) -> Vec<f64> {
+        // Work in the torus' frame, where it's centered at the origin and revolves around 'z'
+        let anchor = self.basis.world_to_local(ray.anchor - self.center);
+        let dir = self.basis.world_to_local(ray.dir);
+
+        // (|p|^2 + R^2 - r^2)^2 = 4R^2(x^2 + y^2) with p = anchor + t*dir
+        let r2 = self.major_r * self.major_r;
+        let m = dir.length_squared();
+        let n = anchor.dot(dir);
+        let k = anchor.length_squared() + r2 - self.minor_r * self.minor_r;
+
+        let a4 = m * m;
+        let a3 = 4.0 * m * n;
+        let a2 = 4.0 * n * n + 2.0 * m * k - 4.0 * r2 * (dir.x * dir.x + dir.y * dir.y);
+        let a1 = 4.0 * n * k - 8.0 * r2 * (anchor.x * dir.x + anchor.y * dir.y);
+        let a0 = k * k - 4.0 * r2 * (anchor.x * anchor.x + anchor.y * anchor.y);
+
+        solve_quartic(a4, a3, a2, a1, a0)
+    }
+}
+
+impl ShapeCalculations for Torus {
+    /// Returns the distance "t" from the camera to the point
+    fn get_intersection(&self, ray: &Ray) -> Option<f64> {
+        // roots are sorted, so the first one in front of the camera is the closest
+        self.get_roots(ray).into_iter().find(|t| *t > TOLERANCE)
+    }
+
+    fn get_normal_vec(&self, intersection: Vec3) -> Vec3 {
+        // The normal points away from the closest point on the tube's center circle
+        let rel = intersection - self.center;
+        let in_plane = rel - rel.dot(self.basis.w) * self.basis.w;
+        let circle_point = self.center + self.major_r * in_plane.normalize();
+
+        (intersection - circle_point).normalize()
+    }
+
+    fn get_texture_coords(&self, intersection: Vec3) -> TextureCoords {
+        let local = self.basis.world_to_local(intersection - self.center);
+        let around_axis = local.y.atan2(local.x);
+        let around_tube = local
+            .z
+            .atan2((local.x * local.x + local.y * local.y).sqrt() - self.major_r);
+
+        TextureCoords {
+            x: self.major_r * (1.0 + around_axis),
+            y: self.minor_r * (1.0 + around_tube),
+        }
+    }
+
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn get_spans(&self, ray: &Ray) -> Vec<Span> {
+        spans_from_roots(&self.get_roots(ray))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::around(self.center, self.major_r + self.minor_r))
+    }
+
+    fn get_params(&self) -> &ObjectParameters {
+        &self.params
+    }
+}
+
+/// Shapes described by a signed distance function, defined around the origin with 'y' as their
+/// main axis
+#[derive(Clone, Debug, Serialize)]
+pub enum SdfPrimitive {
+    /// Box whose edges are rounded off by `radius`, `half_size` includes the rounding
+    RoundedBox {
+        half_size: Vec3,
+        radius: f64,
+    },
+    Torus {
+        major_r: f64,
+        minor_r: f64,
+    },
+    /// Segment along 'y' of length `2 * half_length` inflated by `radius`
+    Capsule {
+        half_length: f64,
+        radius: f64,
+    },
+}
+
+impl SdfPrimitive {
+    /// Signed distance from the point to the surface, negative when inside
+    /// ref: https://iquilezles.org/articles/distfunctions/
+    fn distance(&self, p: Vec3) -> f64 {
+        match self {
+            SdfPrimitive::RoundedBox { half_size, radius } => {
+                let q = p.abs() - *half_size + Vec3::new(*radius, *radius, *radius);
+                q.max(Vec3::new(0.0, 0.0, 0.0)).norm() + q.x.max(q.y).max(q.z).min(0.0) - radius
+            }
+            SdfPrimitive::Torus { major_r, minor_r } => {
+                let ring_distance = (p.x * p.x + p.z * p.z).sqrt() - major_r;
+                (ring_distance * ring_distance + p.y * p.y).sqrt() - minor_r
+            }
+            SdfPrimitive::Capsule {
+                half_length,
+                radius,
+            } => {
+                let segment_point = Vec3::new(0.0, p.y.clamp(-half_length, *half_length), 0.0);
+                (p - segment_point).norm() - radius
+            }
+        }
+    }
+
+    /// Radius of a sphere around the origin that contains the whole shape
+    fn bounding_radius(&self) -> f64 {
+        match self {
+            SdfPrimitive::RoundedBox { half_size, .. } => half_size.norm(),
+            SdfPrimitive::Torus { major_r, minor_r } => major_r + minor_r,
+            SdfPrimitive::Capsule {
+                half_length,
+                radius,
+            } => half_length + radius,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Sdf {
+    primitive: SdfPrimitive,
+    center: Vec3,
+    basis: Onb,
+    params: ObjectParameters,
+}
+
+impl Sdf {
+    pub fn new(primitive: SdfPrimitive, center: Vec3, basis: Onb, params: ObjectParameters) -> Sdf {
+        Sdf {
+            primitive,
+            center,
+            basis,
+            params,
+        }
+    }
+
+    fn distance(&self, point: Vec3) -> f64 {
+        self.primitive
+            .distance(self.basis.world_to_local(point - self.center))
+    }
+}
+
+impl ShapeCalculations for Sdf {
+    /// Returns the distance "t" from the camera to the point
+    fn get_intersection(&self, ray: &Ray) -> Option<f64> {
+        // Only march inside the bounding sphere
+        let oc = ray.anchor - self.center;
+        let bound = self.primitive.bounding_radius() + SDF_HIT_DISTANCE;
+        let (t_enter, t_exit) = match solve_quadratic(
+            1.0,
+            2.0 * ray.dir.dot(oc),
+            oc.length_squared() - bound * bound,
+        )[..]
+        {
+            [t1, t2] if t2 > 0.0 => (t1.max(0.0), t2),
+            _ => return None,
+        };
+
+        // Sphere tracing, the distance is taken as absolute so that rays from inside (refracted or
+        // shadow rays leaving the surface) also work. Steps are at least SDF_HIT_DISTANCE long so
+        // that rays starting on the surface move off of it.
+        let mut t = t_enter;
+        for _ in 0..SDF_MAX_STEPS {
+            let distance = self.distance(ray.point_at_t(t)).abs();
+
+            if distance < SDF_HIT_DISTANCE && t > 10.0 * SDF_HIT_DISTANCE {
+                return Some(t);
+            }
+
+            t += distance.max(SDF_HIT_DISTANCE);
+            if t > t_exit {
+                return None;
+            }
+        }
+
+        None
+    }
+
+    fn get_normal_vec(&self, intersection: Vec3) -> Vec3 {
+        // gradient by central differences
+        let h = SDF_HIT_DISTANCE;
+        let axes = [
+            Vec3::new(h, 0.0, 0.0),
+            Vec3::new(0.0, h, 0.0),
+            Vec3::new(0.0, 0.0, h),
+        ];
+        let [dx, dy, dz] = axes.map(|offset| {
+            self.distance(intersection + offset) - self.distance(intersection - offset)
+        });
+
+        Vec3::new(dx, dy, dz).normalize()
+    }
+
+    fn get_texture_coords(&self, intersection: Vec3) -> TextureCoords {
+        box_texture_coords(
+            self.basis.world_to_local(intersection - self.center),
+            self.basis.world_to_local(self.get_normal_vec(intersection)),
+        )
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::around(self.center, self.primitive.bounding_radius()))
+    }
+
+    fn get_params(&self) -> &ObjectParameters {
+        &self.params
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Metaball {
+    center: Vec3,
+    /// Radius after which the ball stops contributing to the field
+    influence_r: f64,
+    weight: f64,
+}
+
+impl Metaball {
+    /// Contribution to the field at the point, (1 - d²/R²)² inside the radius of influence
+    fn field(&self, point: Vec3) -> f64 {
+        let s = (point - self.center).length_squared() / (self.influence_r * self.influence_r);
+        if s >= 1.0 {
+            0.0
+        } else {
+            self.weight * (1.0 - s) * (1.0 - s)
+        }
+    }
+
+    fn gradient(&self, point: Vec3) -> Vec3 {
+        let offset = point - self.center;
+        let r_squared = self.influence_r * self.influence_r;
+        let s = offset.length_squared() / r_squared;
+        if s >= 1.0 {
+            Vec3::new(0.0, 0.0, 0.0)
+        } else {
+            offset * (-4.0 * self.weight * (1.0 - s) / r_squared)
+        }
+    }
+}
+
+/// Iso-surface where the summed field of a set of balls reaches the threshold
+#[derive(Clone, Debug, Serialize)]
+pub struct Metaballs {
+    balls: Vec<Metaball>,
+    threshold: f64,
+    params: ObjectParameters,
+}
+
+impl Metaballs {
+    /// Each ball is given as (center, radius, weight), a lone ball of weight 1 is rendered with
+    /// the given radius
+    pub fn new(balls: &[(Vec3, f64, f64)], threshold: f64, params: ObjectParameters) -> Metaballs {
+        let influence_scale = 1.0 / (1.0 - threshold.sqrt()).sqrt();
+        Metaballs {
+            balls: balls
+                .iter()
+                .map(|&(center, radius, weight)| Metaball {
+                    center,
+                    influence_r: radius * influence_scale,
+                    weight,
+                })
+                .collect(),
+            threshold,
+            params,
+        }
+    }
+
+    /// Positive inside the surface, negative outside
+    fn field(&self, point: Vec3) -> f64 {
+        self.balls.iter().map(|ball| ball.field(point)).sum::<f64>() - self.threshold
+    }
+}
+
+impl ShapeCalculations for Metaballs {
+    /// Returns the distance "t" from the camera to the point
+    fn get_intersection(&self, ray: &Ray) -> Option<f64> {
+        // The field is 0 outside every ball's radius of influence so only march inside of them
+        let (mut t_start, mut t_end) = (f64::INFINITY, f64::NEG_INFINITY);
+        for ball in &self.balls {
+            let oc = ray.anchor - ball.center;
+            if let [t1, t2] = solve_quadratic(
+                1.0,
+                2.0 * ray.dir.dot(oc),
+                oc.length_squared() - ball.influence_r * ball.influence_r,
+            )[..]
+            {
+                t_start = t_start.min(t1);
+                t_end = t_end.max(t2);
+            }
+        }
+
+        let step = self
+            .balls
+            .iter()
+            .map(|ball| ball.influence_r)
+            .fold(f64::INFINITY, f64::min)
+            * METABALL_STEP;
+
+        // Start a bit ahead so that rays leaving the surface don't hit it again
+        let mut t = t_start.max(10.0 * SDF_HIT_DISTANCE);
+        let mut field = self.field(ray.point_at_t(t));
+        while t < t_end {
+            let next_t = t + step;
+            let next_field = self.field(ray.point_at_t(next_t));
+
+            if (field > 0.0) != (next_field > 0.0) {
+                // refine the crossing by bisection
+                let (mut low, mut high) = (t, next_t);
+                while high - low > SDF_HIT_DISTANCE {
+                    let mid = (low + high) / 2.0;
+                    if (self.field(ray.point_at_t(mid)) > 0.0) == (field > 0.0) {
+                        low = mid;
+                    } else {
+                        high = mid;
+                    }
+                }
+                return Some(high);
+            }
+
+            t = next_t;
+            field = next_field;
+        }
+
+        None
+    }
+
+    fn get_normal_vec(&self, intersection: Vec3) -> Vec3 {
+        // the field grows towards the inside
+        -self
+            .balls
+            .iter()
+            .fold(Vec3::new(0.0, 0.0, 0.0), |gradient, ball| {
+                gradient + ball.gradient(intersection)
+            })
+            .normalize()
+    }
+
+    fn get_texture_coords(&self, intersection: Vec3) -> TextureCoords {
+        box_texture_coords(intersection, self.get_normal_vec(intersection))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.balls
+            .iter()
+            .map(|ball| Aabb::around(ball.center, ball.influence_r))
+            .reduce(|a, b| a.union(&b))
+    }
+
+    fn get_params(&self) -> &ObjectParameters {
+        &self.params
+    }
+}
+
+/// Grid of heights over the 'xz' plane, each cell is split in two triangles
+#[derive(Clone, Debug, Serialize)]
+pub struct Heightfield {
+    /// Corner of the grid with the lowest 'x' and 'z', at height 0
+    origin: Vec3,
+    cell_x: f64,
+    cell_z: f64,
+    /// Number of cells along 'x' and 'z'
+    columns: usize,
+    rows: usize,
+    /// Heights of the `(columns + 1) * (rows + 1)` vertices, row by row
+    #[serde(skip)]
+    heights: Vec<f64>,
+    #[serde(skip)]
+    normals: Vec<Vec3>,
+    bounds: Aabb,
+    params: ObjectParameters,
+}
+
+impl Heightfield {
+    /// `samples` holds the heights between 0 and 1 of a grid of `samples_x` by `samples_z`
+    /// vertices, row by row, which is stretched over `width` by `depth` units
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        samples: &[f64],
+        samples_x: usize,
+        samples_z: usize,
+        origin: Vec3,
+        width: f64,
+        depth: f64,
+        height_scale: f64,
+        params: ObjectParameters,
+    ) -> Heightfield {
+        let columns = samples_x - 1;
+        let rows = samples_z - 1;
+        let cell_x = width / columns as f64;
+        let cell_z = depth / rows as f64;
+        let heights: Vec<f64> = samples.iter().map(|h| h * height_scale).collect();
+
+        // vertex normals from the slopes between the neighbouring vertices
+        let height_at = |i: usize, j: usize| heights[j * samples_x + i];
+        let normals = (0..samples_z)
+            .flat_map(|j| (0..samples_x).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                let (i0, i1) = (i.saturating_sub(1), (i + 1).min(columns));
+                let (j0, j1) = (j.saturating_sub(1), (j + 1).min(rows));
+                let slope_x = (height_at(i1, j) - height_at(i0, j)) / ((i1 - i0) as f64 * cell_x);
+                let slope_z = (height_at(i, j1) - height_at(i, j0)) / ((j1 - j0) as f64 * cell_z);
+                Vec3::new(-slope_x, 1.0, -slope_z).normalize()
+            })
+            .collect();
+
+        let (min_h, max_h) = heights
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &h| {
+                (min.min(h), max.max(h))
+            });
+        // padded so that flat heightfields don't have an infinitely thin box
+        let pad = TOLERANCE * TOLERANCE_MUL;
+        let bounds = Aabb {
+            min: origin + Vec3::new(0.0, min_h - pad, 0.0),
+            max: origin + Vec3::new(width, max_h + pad, depth),
+        };
+
+        Heightfield {
+            origin,
+            cell_x,
+            cell_z,
+            columns,
+            rows,
+            heights,
+            normals,
+            bounds,
+            params,
+        }
+    }
+
+    fn index(&self, i: usize, j: usize) -> usize {
+        j * (self.columns + 1) + i
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> Vec3 {
+        self.origin
+            + Vec3::new(
+                i as f64 * self.cell_x,
+                self.heights[self.index(i, j)],
+                j as f64 * self.cell_z,
+            )
+    }
+
+    /// Cell that contains the point's 'x' and 'z' and the point's position inside of it
+    fn cell_at(&self, point: Vec3) -> (usize, usize, f64, f64) {
+        let gx = (point.x - self.origin.x) / self.cell_x;
+        let gz = (point.z - self.origin.z) / self.cell_z;
+        let i = (gx.floor().max(0.0) as usize).min(self.columns - 1);
+        let j = (gz.floor().max(0.0) as usize).min(self.rows - 1);
+
+        (i, j, gx - i as f64, gz - j as f64)
+    }
+
+    fn cell_intersection(&self, i: usize, j: usize, ray: &Ray) -> Option<f64> {
+        let v00 = self.vertex(i, j);
+        let v10 = self.vertex(i + 1, j);
+        let v01 = self.vertex(i, j + 1);
+        let v11 = self.vertex(i + 1, j + 1);
+
+        [(v00, v10, v11), (v00, v11, v01)]
+            .into_iter()
+            .filter_map(|(a, b, c)| triangle_intersection(a, b, c, (b - a).cross(c - a), ray))
+            .min_by(f64::total_cmp)
+    }
+}
+
+impl ShapeCalculations for Heightfield {
+    /// Returns the distance "t" from the camera to the point
+    fn get_intersection(&self, ray: &Ray) -> Option<f64> {
+        let (t_enter, t_exit) = self.bounds.hit_range(ray)?;
+
+        // 2D DDA over the cells crossed by the ray
+        let (mut i, mut j, _, _) = self.cell_at(ray.point_at_t(t_enter));
+        let step_i = if ray.dir.x > 0.0 { 1 } else { -1 };
+        let step_j = if ray.dir.z > 0.0 { 1 } else { -1 };
+        let t_delta_x = self.cell_x / ray.dir.x.abs();
+        let t_delta_z = self.cell_z / ray.dir.z.abs();
+        let next_boundary =
+            |index: usize, step: isize, cell: f64, origin: f64, anchor: f64, dir: f64| {
+                if dir == 0.0 {
+                    f64::INFINITY
+                } else {
+                    let boundary_index = if step > 0 { index + 1 } else { index };
+                    (boundary_index as f64 * cell + origin - anchor) / dir
+                }
+            };
+        let mut t_next_x = next_boundary(
+            i,
+            step_i,
+            self.cell_x,
+            self.origin.x,
+            ray.anchor.x,
+            ray.dir.x,
+        );
+        let mut t_next_z = next_boundary(
+            j,
+            step_j,
+            self.cell_z,
+            self.origin.z,
+            ray.anchor.z,
+            ray.dir.z,
+        );
+
+        loop {
+            if let Some(t) = self.cell_intersection(i, j, ray) {
+                return Some(t);
+            }
+
+            if t_next_x < t_next_z {
+                if t_next_x > t_exit {
+                    return None;
+                }
+                i = i.checked_add_signed(step_i).filter(|&i| i < self.columns)?;
+                t_next_x += t_delta_x;
+            } else {
+                if t_next_z > t_exit {
+                    return None;
+                }
+                j = j.checked_add_signed(step_j).filter(|&j| j < self.rows)?;
+                t_next_z += t_delta_z;
+            }
+        }
+    }
+
+    fn get_normal_vec(&self, intersection: Vec3) -> Vec3 {
+        // interpolate the vertex normals of the triangle the point is on
+        let (i, j, fx, fz) = self.cell_at(intersection);
+        let n00 = self.normals[self.index(i, j)];
+        let n11 = self.normals[self.index(i + 1, j + 1)];
+
+        if fx >= fz {
+            let n10 = self.normals[self.index(i + 1, j)];
+            (n00 * (1.0 - fx) + n10 * (fx - fz) + n11 * fz).normalize()
+        } else {
+            let n01 = self.normals[self.index(i, j + 1)];
+            (n00 * (1.0 - fz) + n11 * fx + n01 * (fz - fx)).normalize()
+        }
+    }
+
+    fn get_texture_coords(&self, intersection: Vec3) -> TextureCoords {
+        TextureCoords {
+            x: intersection.x - self.origin.x,
+            y: intersection.z - self.origin.z,
+        }
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+
+    fn get_params(&self) -> &ObjectParameters {
+        &self.params
+    }
+}
+
+/// Interval of a ray's line, in terms of "t", that lies inside a solid
+#[derive(Debug, Clone, Copy)]
+pub struct Span {
+    pub enter: f64,
+    pub exit: f64,
+}
+
+impl Span {
+    pub fn new(enter: f64, exit: f64) -> Span {
+        Span { enter, exit }
+    }
+}
+
+/// Pairs up the sorted crossings of a closed surface, since the ray's line alternates between
+/// entering and exiting it
+fn spans_from_roots(roots: &[f64]) -> Vec<Span> {
+    roots
+        .chunks_exact(2)
+        .map(|pair| Span::new(pair[0], pair[1]))
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    /// Left minus right
+    Difference,
+}
+
+impl CsgOperation {
+    fn apply(&self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// Constructive solid geometry, combines the volumes of two solid shapes. The result uses its own
+/// material parameters rather than those of its children.
+#[derive(Clone, Debug, Serialize)]
+pub struct Csg {
+    operation: CsgOperation,
+    left: Box<Shape>,
+    right: Box<Shape>,
+    params: ObjectParameters,
+}
+
+impl Csg {
+    pub fn new(
+        operation: CsgOperation,
+        left: Shape,
+        right: Shape,
+        params: ObjectParameters,
+    ) -> Csg {
+        Csg {
+            operation,
+            left: Box::new(left),
+            right: Box::new(right),
+            params,
+        }
+    }
+
+    /// Child whose surface the point lies on and whether its normal has to be flipped (the
+    /// subtracted shape of a difference is seen from the inside)
+    fn surface_at(&self, point: Vec3) -> (&Shape, bool) {
+        if is_on_surface(&self.left, point) {
+            (&self.left, false)
+        } else {
+            (&self.right, self.operation == CsgOperation::Difference)
+        }
+    }
+}
+
+/// Probes the shape along its normal at the point and checks if it crosses the surface right there
+fn is_on_surface(shape: &Shape, point: Vec3) -> bool {
+    const PROBE: f64 = TOLERANCE * TOLERANCE_MUL;
+
+    let normal = shape.get_normal_vec(point);
+    let probe = Ray {
+        anchor: point - PROBE * normal,
+        dir: normal,
+    };
+
+    shape.get_spans(&probe).iter().any(|span| {
+        (span.enter - PROBE).abs() < PROBE / 2.0 || (span.exit - PROBE).abs() < PROBE / 2.0
+    })
+}
+
+/// Applies the boolean operation to the sorted spans of the two operands
+fn combine_spans(left: &[Span], right: &[Span], operation: CsgOperation) -> Vec<Span> {
+    let mut bounds: Vec<f64> = left
+        .iter()
+        .chain(right)
+        .flat_map(|span| [span.enter, span.exit])
+        .collect();
+    bounds.sort_by(f64::total_cmp);
+    bounds.dedup();
+
+    let contains = |spans: &[Span], t: f64| spans.iter().any(|s| s.enter <= t && t <= s.exit);
+
+    // Each consecutive pair of bounds delimits a segment that's either fully inside or outside of
+    // each operand, so testing a single point per segment is enough
+    let mut result: Vec<Span> = Vec::new();
+    for pair in bounds.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        let sample = match (start.is_finite(), end.is_finite()) {
+            (true, true) => (start + end) / 2.0,
+            (true, false) => start + 1.0,
+            (false, true) => end - 1.0,
+            (false, false) => 0.0,
+        };
+
+        if operation.apply(contains(left, sample), contains(right, sample)) {
+            match result.last_mut() {
+                Some(last) if last.exit == start => last.exit = end,
+                _ => result.push(Span::new(start, end)),
+            }
+        }
+    }
+
+    result
+}
+
+impl ShapeCalculations for Csg {
+    /// Returns the distance "t" from the camera to the point
+    fn get_intersection(&self, ray: &Ray) -> Option<f64> {
+        self.get_spans(ray)
+            .iter()
+            .flat_map(|span| [span.enter, span.exit])
+            .find(|t| *t > TOLERANCE && t.is_finite())
+    }
+
+    fn get_normal_vec(&self, intersection: Vec3) -> Vec3 {
+        let (child, flipped) = self.surface_at(intersection);
+        let normal = child.get_normal_vec(intersection);
+        if flipped {
+            -normal
+        } else {
+            normal
+        }
+    }
+
+    fn get_texture_coords(&self, intersection: Vec3) -> TextureCoords {
+        self.surface_at(intersection)
+            .0
+            .get_texture_coords(intersection)
+    }
+
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn get_spans(&self, ray: &Ray) -> Vec<Span> {
+        combine_spans(
+            &self.left.get_spans(ray),
+            &self.right.get_spans(ray),
+            self.operation,
+        )
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let (left, right) = (self.left.bounds(), self.right.bounds());
+        match self.operation {
+            CsgOperation::Union => Some(left?.union(&right?)),
+            // unbounded operands like planes don't limit the intersection
+            CsgOperation::Intersection => match (left, right) {
+                (Some(left), Some(right)) => Some(left.intersection(&right)),
+                (left, right) => left.or(right),
+            },
+            CsgOperation::Difference => left,
+        }
+    }
+
     fn get_params(&self) -> &ObjectParameters {
         &self.params
     }
 }
 
+/// Copy of another shape placed in the scene with an arbitrary affine transformation. Rays are
+/// taken into the shape's own space to be intersected and normals are brought back.
+#[derive(Clone, Debug, Serialize)]
+pub struct Instance {
+    shape: Box<Shape>,
+    /// world to object space
+    inverse: Matrix4,
+    /// normals transform with the inverse transpose to stay perpendicular under non-uniform
+    /// scaling
+    normal_matrix: Matrix4,
+    params: ObjectParameters,
+}
+
+impl Instance {
+    /// Returns `None` if the transformation can't be inverted
+    pub fn new(shape: Shape, transform: Matrix4, params: ObjectParameters) -> Option<Instance> {
+        let inverse = transform.inverse()?;
+        Some(Instance {
+            shape: Box::new(shape),
+            inverse,
+            normal_matrix: inverse.transpose(),
+            params,
+        })
+    }
+
+    /// The ray in object space along with the factor that takes object space "t"s to world ones
+    fn to_object_space(&self, ray: &Ray) -> (Ray, f64) {
+        let dir = self.inverse.transform_vector(ray.dir);
+        let scale = dir.norm();
+
+        (
+            Ray {
+                anchor: self.inverse.transform_point(ray.anchor),
+                dir: dir / scale,
+            },
+            1.0 / scale,
+        )
+    }
+}
+
+impl ShapeCalculations for Instance {
+    /// Returns the distance "t" from the camera to the point
+    fn get_intersection(&self, ray: &Ray) -> Option<f64> {
+        let (object_ray, scale) = self.to_object_space(ray);
+        self.shape.get_intersection(&object_ray).map(|t| t * scale)
+    }
+
+    fn get_normal_vec(&self, intersection: Vec3) -> Vec3 {
+        let normal = self
+            .shape
+            .get_normal_vec(self.inverse.transform_point(intersection));
+
+        self.normal_matrix.transform_vector(normal).normalize()
+    }
+
+    fn get_texture_coords(&self, intersection: Vec3) -> TextureCoords {
+        self.shape
+            .get_texture_coords(self.inverse.transform_point(intersection))
+    }
+
+    fn get_local_point(&self, point: Vec3) -> Vec3 {
+        self.shape
+            .get_local_point(self.inverse.transform_point(point))
+    }
+
+    fn get_local_normal(&self, point: Vec3) -> Vec3 {
+        self.shape
+            .get_local_normal(self.inverse.transform_point(point))
+    }
+
+    fn is_solid(&self) -> bool {
+        self.shape.is_solid()
+    }
+
+    fn has_inside(&self) -> bool {
+        self.shape.has_inside()
+    }
+
+    fn get_spans(&self, ray: &Ray) -> Vec<Span> {
+        let (object_ray, scale) = self.to_object_space(ray);
+        self.shape
+            .get_spans(&object_ray)
+            .into_iter()
+            .map(|span| Span::new(span.enter * scale, span.exit * scale))
+            .collect()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let transform = self.inverse.inverse()?;
+        let bounds = self.shape.bounds()?;
+        Some(Aabb::from_points(
+            bounds
+                .corners()
+                .map(|corner| transform.transform_point(corner)),
+        ))
+    }
+
+    fn get_params(&self) -> &ObjectParameters {
+        &self.params
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct TextureCoords {
     pub x: f64,
     pub y: f64,
 }
 
-fn checker_pattern<T: ShapeCalculations>(coords: TextureCoords, object: &T) -> Color {
+impl ops::Add for TextureCoords {
+    type Output = Self;
+    fn add(self, other: Self) -> Self::Output {
+        TextureCoords {
+            x: self.x + other.x,
+            y: self.y + other.y,
+        }
+    }
+}
+
+impl ops::Mul<f64> for TextureCoords {
+    type Output = Self;
+    fn mul(self, other: f64) -> Self::Output {
+        TextureCoords {
+            x: self.x * other,
+            y: self.y * other,
+        }
+    }
+}
+
+/// Scaling, rotation and offset applied to the texture coordinates of an object, in that order,
+/// before textures are looked up
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct UvTransform {
+    pub scale: (f64, f64),
+    /// Counterclockwise, in radians
+    pub rotation: f64,
+    pub offset: (f64, f64),
+}
+
+impl Default for UvTransform {
+    fn default() -> Self {
+        UvTransform {
+            scale: (1.0, 1.0),
+            rotation: 0.0,
+            offset: (0.0, 0.0),
+        }
+    }
+}
+
+impl UvTransform {
+    pub fn apply(&self, coords: TextureCoords) -> TextureCoords {
+        let (x, y) = (coords.x * self.scale.0, coords.y * self.scale.1);
+        let (sin, cos) = self.rotation.sin_cos();
+        TextureCoords {
+            x: x * cos - y * sin + self.offset.0,
+            y: x * sin + y * cos + self.offset.1,
+        }
+    }
+}
+
+/// Texture coordinates of a vector lying on a plane, expressed along the basis' tangent axes
+fn planar_texture_coords(basis: &Onb, plane_vec: Vec3) -> TextureCoords {
+    TextureCoords {
+        x: plane_vec.dot(basis.u),
+        y: plane_vec.dot(basis.v),
+    }
+}
+
+/// Texture coordinates of a point projected along the axis that's most aligned with its normal
+fn box_texture_coords(point: Vec3, normal: Vec3) -> TextureCoords {
+    let normal = normal.abs();
+
+    if normal.x >= normal.y && normal.x >= normal.z {
+        axis_texture_coords(0, point)
+    } else if normal.y >= normal.z {
+        axis_texture_coords(1, point)
+    } else {
+        axis_texture_coords(2, point)
+    }
+}
+
+/// Texture coordinates of a point projected along the x (0), y (1) or z (2) axis
+fn axis_texture_coords(axis: usize, point: Vec3) -> TextureCoords {
+    match axis {
+        0 => TextureCoords {
+            x: point.z,
+            y: point.y,
+        },
+        1 => TextureCoords {
+            x: point.x,
+            y: point.z,
+        },
+        _ => TextureCoords {
+            x: point.x,
+            y: point.y,
+        },
+    }
+}
+
+/// How much each of the projections along the x, y and z axes shows on a surface with the given
+/// normal, adding up to 1
+fn triplanar_weights(normal: Vec3) -> [f64; 3] {
+    let weights = [
+        normal.x.abs().powf(TRIPLANAR_SHARPNESS),
+        normal.y.abs().powf(TRIPLANAR_SHARPNESS),
+        normal.z.abs().powf(TRIPLANAR_SHARPNESS),
+    ];
+    let total: f64 = weights.iter().sum();
+    weights.map(|weight| weight / total)
+}
+
+/// Color of the checkerboard with tiles of the given size at the coordinates, alternating between
+/// both colors
+fn checker_pattern(coords: TextureCoords, size: f64, color1: Color, color2: Color) -> Color {
     type Int = i32;
-    let int_x = (((coords.x / object.checkerboard()).floor()) % Int::MAX as f64) as Int;
-    let int_y = (((coords.y / object.checkerboard()).floor()) % Int::MAX as f64) as Int;
+    let int_x = (((coords.x / size).floor()) % Int::MAX as f64) as Int;
+    let int_y = (((coords.y / size).floor()) % Int::MAX as f64) as Int;
 
     if (int_x + int_y) % 2 == 0 {
-        object.color()
+        color1
     } else {
-        colors::BLACK
+        color2
     }
 }
 
-#[derive(Clone, Debug)]
+/// Image that perturbs the normals of an object to give its surface detail without changing its
+/// shape
+#[derive(Clone, Debug, Serialize)]
+pub struct Bump {
+    pub kind: BumpKind,
+    pub path: String,
+    /// Height of the bumps of a bump map, or how far a normal map tilts the normals
+    pub strength: f64,
+    /// Length in texture coordinates of one repetition of the image
+    pub size: f64,
+    pub wrap: Wrap,
+    #[serde(skip)]
+    pub texture: Arc<ImageTexture>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum BumpKind {
+    /// Grayscale heights, where white is highest
+    Height,
+    /// Tangent space normals in the RGB channels, with green pointing up the image
+    Normal,
+}
+
+impl Bump {
+    /// Normal of the surface at the point after being perturbed by the image, `texture_coords`
+    /// being the coordinates of the object the image is laid over
+    pub fn perturb(
+        &self,
+        point: Vec3,
+        normal: Vec3,
+        texture_coords: impl Fn(Vec3) -> TextureCoords,
+    ) -> Vec3 {
+        // directions along the surface in which each texture coordinate grows fastest, scaled by
+        // how fast it does, found by moving slightly along the tangent plane
+        let tangent_plane = Onb::from_normal(normal);
+        let step = BUMP_STEP * point.norm().max(1.0);
+        let derivative = |dir: Vec3| {
+            let (ahead, behind) = (
+                texture_coords(point + step * dir),
+                texture_coords(point - step * dir),
+            );
+            (
+                (ahead.x - behind.x) / (2.0 * step),
+                (ahead.y - behind.y) / (2.0 * step),
+            )
+        };
+        let (du_x, du_y) = derivative(tangent_plane.u);
+        let (dv_x, dv_y) = derivative(tangent_plane.v);
+        let gradient_x = du_x * tangent_plane.u + dv_x * tangent_plane.v;
+        let gradient_y = du_y * tangent_plane.u + dv_y * tangent_plane.v;
+
+        let coords = texture_coords(point) * (1.0 / self.size);
+        let perturbed = match self.kind {
+            BumpKind::Height => {
+                // the surface is pushed out along the normal by the height, which tilts the
+                // normal against the slope of the height
+                let texel_x = 1.0 / self.texture.width() as f64;
+                let texel_y = 1.0 / self.texture.height() as f64;
+                let height = |dx: f64, dy: f64| {
+                    self.texture.sample_gray(
+                        TextureCoords {
+                            x: coords.x + dx,
+                            y: coords.y + dy,
+                        },
+                        self.wrap,
+                    )
+                };
+                let slope_x = (height(texel_x, 0.0) - height(-texel_x, 0.0)) / (2.0 * texel_x);
+                let slope_y = (height(0.0, texel_y) - height(0.0, -texel_y)) / (2.0 * texel_y);
+
+                normal - (self.strength / self.size) * (slope_x * gradient_x + slope_y * gradient_y)
+            }
+            BumpKind::Normal => {
+                let tangent = gradient_x - gradient_x.dot(normal) * normal;
+                let bitangent = gradient_y - gradient_y.dot(normal) * normal;
+                if tangent.near_zero() || bitangent.near_zero() {
+                    return normal;
+                }
+
+                let color = self.texture.sample(coords, self.wrap);
+                let (x, y, z) = (
+                    2.0 * color.r - 1.0,
+                    2.0 * color.g - 1.0,
+                    2.0 * color.b - 1.0,
+                );
+                self.strength * (x * tangent.normalize() + y * bitangent.normalize()) + z * normal
+            }
+        };
+
+        if perturbed.near_zero() {
+            normal
+        } else {
+            perturbed.normalize()
+        }
+    }
+}
+
+/// How the textures of an object are laid over its surface
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Mapping {
+    /// Over the texture coordinates of the shape
+    Uv,
+    /// Projected along each axis of the object's space and blended by how much the surface faces
+    /// each of them, which doesn't stretch on curved shapes
+    Triplanar,
+}
+
+/// What a ray is traced for, which decides the objects it can hit
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum RayKind {
+    /// Going from the camera through a pixel
+    Camera,
+    /// Going from a point towards a light, to find out if it's in shadow
+    Shadow,
+    /// Reflected or refracted off a surface, or sent from it to gather the light around it
+    Reflection,
+}
+
+/// Kinds of rays that hit an object, the others go through it as if it wasn't there
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Visibility {
+    pub camera: bool,
+    pub shadows: bool,
+    pub reflections: bool,
+}
+
+impl Visibility {
+    pub const ALL: Visibility = Visibility {
+        camera: true,
+        shadows: true,
+        reflections: true,
+    };
+
+    pub fn sees(self, kind: RayKind) -> bool {
+        match kind {
+            RayKind::Camera => self.camera,
+            RayKind::Shadow => self.shadows,
+            RayKind::Reflection => self.reflections,
+        }
+    }
+}
+
+/// How the light that reaches an object is reflected towards the viewer
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum Shading {
+    /// Phong's model, with the K_d, K_s and K_n coefficients
+    Phong,
+    /// Cook-Torrance microfacet model, described by how metallic and rough the surface is
+    Pbr { metallic: f64, roughness: f64 },
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub struct ObjectParameters {
+    /// Name of the section the object was defined in
+    pub name: String,
+    /// Number of the object hashed from its name, so it stays the same as the scene changes
+    pub id: u32,
     pub color: Color,
+    pub shading: Shading,
     pub k_a: f64,
     pub k_d: f64,
     pub k_n: f64,
@@ -720,7 +2499,39 @@ pub struct ObjectParameters {
     pub o1: f64,
     pub reflection: f64,
     pub transparency: f64,
+    /// Share of the diffuse light that gets through the object from lights behind it, like
+    /// through paper or leaves
+    pub translucency: f64,
+    /// Filters the light seen through the object, channel by channel
+    pub transparency_color: Color,
+    /// Index of refraction of what's inside the object, which bends the rays going through it
+    pub ior: f64,
+    /// Whether part of the transparency is reflected depending on the angle the surface is seen
+    /// from, like glass or water
+    pub fresnel: bool,
     pub checkerboard: f64,
+    /// Color of the checkerboard's tiles that aren't of the object's color
+    pub checkerboard_color2: Color,
+    /// Applied to the texture coordinates of the checkerboard and of bump and normal maps
+    pub uv_transform: UvTransform,
+    pub mapping: Mapping,
+    /// Light given off by the object, already multiplied by its intensity so it may go over 1
+    pub emission: Color,
+    /// Procedural texture that replaces the checkerboard
+    pub pattern: Option<Pattern>,
+    /// Colors laid over the texture coordinates that replace the checkerboard
+    pub gradient: Option<Gradient>,
+    pub bump: Option<Bump>,
+    /// Sections of the lights that don't light the object
+    pub ignored_lights: Vec<String>,
+    /// Distance secondary rays start away from the object's surface, instead of the scene's
+    pub bias: Option<f64>,
+    /// Whether the back of a surface without an inside is lit like its front, instead of facing
+    /// away from the rays that hit it
+    pub double_sided: bool,
+    pub visibility: Visibility,
+    /// Name of the render layer the object is in
+    pub layer: String,
 }
 
 #[enum_dispatch]
@@ -731,13 +2542,110 @@ pub trait ShapeCalculations: Sized {
     fn get_normal_vec(&self, intersection: Vec3) -> Vec3;
     fn get_texture_coords(&self, intersection: Vec3) -> TextureCoords;
 
+    /// Whether the shape encloses a volume, only solid shapes can be used in CSG
+    fn is_solid(&self) -> bool {
+        false
+    }
+
+    /// Whether rays can hit the shape from its inside, where its surface faces away from them
+    fn has_inside(&self) -> bool {
+        self.is_solid()
+    }
+
+    /// Sorted spans of the ray's whole line (so "t" may be negative) that lie inside the shape.
+    /// Non-solid shapes have no inside.
+    fn get_spans(&self, _ray: &Ray) -> Vec<Span> {
+        Vec::new()
+    }
+
     // This method exists so that all the other parameter getters can have default impls and each
     // struct must only define this method
     fn get_params(&self) -> &ObjectParameters;
 
+    /// Box that contains the whole shape, `None` for unbounded ones like planes
+    fn bounds(&self) -> Option<Aabb> {
+        None
+    }
+
+    /// Texture coordinates textures are looked up at, after the object's uv transform
+    fn get_uv(&self, point: Vec3) -> TextureCoords {
+        self.get_params()
+            .uv_transform
+            .apply(self.get_texture_coords(point))
+    }
+
+    /// Normal used to light the point, which bump and normal maps perturb
+    fn get_shading_normal(&self, point: Vec3, normal: Vec3) -> Vec3 {
+        let Some(bump) = &self.get_params().bump else {
+            return normal;
+        };
+
+        match self.get_params().mapping {
+            Mapping::Uv => bump.perturb(point, normal, |p| self.get_uv(p)),
+            Mapping::Triplanar => {
+                let uv_transform = self.get_params().uv_transform;
+                triplanar_weights(self.get_local_normal(point))
+                    .into_iter()
+                    .enumerate()
+                    .filter(|&(_, weight)| weight > 0.0)
+                    .fold(Vec3::new(0.0, 0.0, 0.0), |blended, (axis, weight)| {
+                        blended
+                            + weight
+                                * bump.perturb(point, normal, |p| {
+                                    uv_transform
+                                        .apply(axis_texture_coords(axis, self.get_local_point(p)))
+                                })
+                    })
+                    .normalize()
+            }
+        }
+    }
+
+    /// Blends what's sampled at the texture coordinates the textures of the object are looked up
+    /// at for the point
+    fn sample_texture(&self, point: Vec3, sample: &dyn Fn(TextureCoords) -> Color) -> Color {
+        match self.get_params().mapping {
+            Mapping::Uv => sample(self.get_uv(point)),
+            Mapping::Triplanar => {
+                let local = self.get_local_point(point);
+                let uv_transform = self.get_params().uv_transform;
+                triplanar_weights(self.get_local_normal(point))
+                    .into_iter()
+                    .enumerate()
+                    .filter(|&(_, weight)| weight > 0.0)
+                    .map(|(axis, weight)| {
+                        weight * sample(uv_transform.apply(axis_texture_coords(axis, local)))
+                    })
+                    .sum()
+            }
+        }
+    }
+
+    /// The point in the object's own space, where instances haven't transformed it yet, so that
+    /// solid textures move along with the object
+    fn get_local_point(&self, point: Vec3) -> Vec3 {
+        point
+    }
+
+    /// Normal of the surface at the point in the object's own space, like `get_local_point`
+    fn get_local_normal(&self, point: Vec3) -> Vec3 {
+        self.get_normal_vec(point)
+    }
+
     fn get_color_at(&self, point: Vec3) -> Color {
-        if self.get_params().checkerboard > 0.0 {
-            checker_pattern(self.get_texture_coords(point), self)
+        if let Some(pattern) = &self.get_params().pattern {
+            pattern.color_at(self.get_local_point(point), self.color())
+        } else if let Some(gradient) = &self.get_params().gradient {
+            self.sample_texture(point, &|coords| gradient.color_at(coords))
+        } else if self.checkerboard() > 0.0 {
+            self.sample_texture(point, &|coords| {
+                checker_pattern(
+                    coords,
+                    self.checkerboard(),
+                    self.color(),
+                    self.get_params().checkerboard_color2,
+                )
+            })
         } else {
             self.color()
         }
@@ -747,6 +2655,9 @@ pub trait ShapeCalculations: Sized {
         self.get_params().color
     }
 
+    fn shading(&self) -> Shading {
+        self.get_params().shading
+    }
     fn k_a(&self) -> f64 {
         self.get_params().k_a
     }
@@ -768,18 +2679,56 @@ pub trait ShapeCalculations: Sized {
     fn transparency(&self) -> f64 {
         self.get_params().transparency
     }
+    fn translucency(&self) -> f64 {
+        self.get_params().translucency
+    }
+    fn transparency_color(&self) -> Color {
+        self.get_params().transparency_color
+    }
+    fn ior(&self) -> f64 {
+        self.get_params().ior
+    }
+    fn bias(&self) -> Option<f64> {
+        self.get_params().bias
+    }
+    fn fresnel(&self) -> bool {
+        self.get_params().fresnel
+    }
+    fn double_sided(&self) -> bool {
+        self.get_params().double_sided
+    }
+    /// Whether rays of the kind hit the object
+    fn visible_to(&self, kind: RayKind) -> bool {
+        self.get_params().visibility.sees(kind)
+    }
+    fn layer(&self) -> &str {
+        &self.get_params().layer
+    }
     fn checkerboard(&self) -> f64 {
         self.get_params().checkerboard
     }
+    fn emission(&self) -> Color {
+        self.get_params().emission
+    }
 }
 
 #[enum_dispatch(ShapeCalculations)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub enum Shape {
     Sphere,
+    Ellipsoid,
     Cylinder,
     Cone,
     Plane,
     Disc,
+    Ring,
     Triangle,
+    Polygon,
+    Torus,
+    Mesh,
+    Csg,
+    Instance,
+    Sdf,
+    Metaballs,
+    Heightfield,
 }
@@ -0,0 +1,48 @@
+use serde::Serialize;
+
+use crate::shapes::Color;
+use crate::vec3::Vec3;
+
+/// Procedural sky seen by the rays that don't hit anything: a gradient from the horizon up to the
+/// zenith, a flat ground color below the horizon and a sun with a glow around it. Up is +y.
+#[derive(Debug, Clone, Serialize)]
+pub struct Sky {
+    /// Direction towards the sun
+    pub sun_direction: Vec3,
+    pub sun_color: Color,
+    /// Cosine of the sun's angular radius
+    pub sun_cos_size: f64,
+    pub zenith_color: Color,
+    pub horizon_color: Color,
+    pub ground_color: Color,
+}
+
+impl Sky {
+    /// Color seen looking along `dir`
+    pub fn sample(&self, dir: Vec3) -> Color {
+        let dir = dir.normalize();
+        let cos_sun = dir.dot(self.sun_direction);
+        if cos_sun >= self.sun_cos_size {
+            return self.sun_color;
+        }
+
+        let base = if dir.y >= 0.0 {
+            // the gradient is stretched near the horizon, like a real sky's
+            let t = dir.y.sqrt();
+            (1.0 - t) * self.horizon_color + t * self.zenith_color
+        } else {
+            // quick fade so the horizon isn't a hard line
+            let t = (-dir.y * 10.0).min(1.0);
+            (1.0 - t) * self.horizon_color + t * self.ground_color
+        };
+
+        // the sky brightens around the sun, mostly close to it
+        let glow = 0.6 * cos_sun.max(0.0).powi(64) + 0.15 * cos_sun.max(0.0).powi(4);
+        base + self.sun_color * glow
+    }
+
+    /// Average color of the sky above the horizon, used to tint the ambient light
+    pub fn ambient_tint(&self) -> Color {
+        0.5 * (self.zenith_color + self.horizon_color)
+    }
+}
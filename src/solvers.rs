@@ -0,0 +1,218 @@
+use std::f64::consts::PI;
+
+use crate::constants::TOLERANCE;
+
+/// Real roots of a*x^2 + b*x + c = 0, in ascending order
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a.abs() < TOLERANCE {
+        return if b.abs() < TOLERANCE {
+            vec![]
+        } else {
+            vec![-c / b]
+        };
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+
+    if discriminant < 0.0 {
+        vec![]
+    } else {
+        // avoids the catastrophic cancellation of the textbook formula
+        let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+        let (x1, x2) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+        if x1 < x2 {
+            vec![x1, x2]
+        } else {
+            vec![x2, x1]
+        }
+    }
+}
+
+/// Real roots of the monic cubic x^3 + a*x^2 + b*x + c = 0
+pub fn solve_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    // depressed cubic t^3 + p*t + q = 0 with x = t - a/3
+    let shift = a / 3.0;
+    let p = b - a * a / 3.0;
+    let q = 2.0 * a * a * a / 27.0 - a * b / 3.0 + c;
+
+    let discriminant = (q / 2.0).powi(2) + (p / 3.0).powi(3);
+
+    if p.abs() < TOLERANCE && q.abs() < TOLERANCE {
+        vec![-shift]
+    } else if discriminant > 0.0 {
+        // one real root (Cardano)
+        let sqrt_d = discriminant.sqrt();
+        vec![(-q / 2.0 + sqrt_d).cbrt() + (-q / 2.0 - sqrt_d).cbrt() - shift]
+    } else {
+        // three real roots (trigonometric method), here p is always negative
+        let r = 2.0 * (-p / 3.0).sqrt();
+        let phi = ((3.0 * q / (2.0 * p)) * (-3.0 / p).sqrt())
+            .clamp(-1.0, 1.0)
+            .acos()
+            / 3.0;
+        (0..3)
+            .map(|k| r * (phi - 2.0 * PI * k as f64 / 3.0).cos() - shift)
+            .collect()
+    }
+}
+
+/// Real roots of a4*x^4 + a3*x^3 + a2*x^2 + a1*x + a0 = 0 using Ferrari's method, polished with a
+/// few Newton iterations since the closed form loses precision
+pub fn solve_quartic(a4: f64, a3: f64, a2: f64, a1: f64, a0: f64) -> Vec<f64> {
+    if a4.abs() < TOLERANCE {
+        return solve_cubic_general(a3, a2, a1, a0);
+    }
+
+    let b = a3 / a4;
+    let c = a2 / a4;
+    let d = a1 / a4;
+    let e = a0 / a4;
+
+    // depressed quartic y^4 + p*y^2 + q*y + r = 0 with x = y - b/4
+    let shift = b / 4.0;
+    let b2 = b * b;
+    let p = c - 3.0 * b2 / 8.0;
+    let q = d - b * c / 2.0 + b2 * b / 8.0;
+    let r = e - b * d / 4.0 + b2 * c / 16.0 - 3.0 * b2 * b2 / 256.0;
+
+    let mut roots = Vec::with_capacity(4);
+
+    if q.abs() < TOLERANCE {
+        // biquadratic
+        for z in solve_quadratic(1.0, p, r) {
+            if z >= 0.0 {
+                roots.push(z.sqrt());
+                roots.push(-z.sqrt());
+            }
+        }
+    } else {
+        // a positive root of the resolvent cubic always exists when q != 0
+        let m = solve_cubic(p, p * p / 4.0 - r, -q * q / 8.0)
+            .into_iter()
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        if m > 0.0 {
+            let s = (2.0 * m).sqrt();
+            for sign in [1.0, -1.0] {
+                let inner = -(2.0 * p + 2.0 * m + sign * 2.0 * q / s);
+                if inner >= 0.0 {
+                    roots.push((sign * s + inner.sqrt()) / 2.0);
+                    roots.push((sign * s - inner.sqrt()) / 2.0);
+                }
+            }
+        }
+    }
+
+    let mut roots: Vec<f64> = roots
+        .into_iter()
+        .map(|y| polish_root([a4, a3, a2, a1, a0], y - shift))
+        .collect();
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+fn solve_cubic_general(a3: f64, a2: f64, a1: f64, a0: f64) -> Vec<f64> {
+    if a3.abs() < TOLERANCE {
+        solve_quadratic(a2, a1, a0)
+    } else {
+        let mut roots = solve_cubic(a2 / a3, a1 / a3, a0 / a3);
+        roots.sort_by(f64::total_cmp);
+        roots
+    }
+}
+
+/// Newton iterations over a polynomial whose coefficients are ordered from the highest degree
+fn polish_root<const N: usize>(coefficients: [f64; N], mut x: f64) -> f64 {
+    let evaluate = |x: f64| {
+        coefficients
+            .iter()
+            .fold((0.0, 0.0), |(v, dv), &k| (v * x + k, dv * x + v))
+    };
+
+    for _ in 0..3 {
+        let (value, derivative) = evaluate(x);
+        if derivative.abs() < f64::EPSILON {
+            break;
+        }
+        let next = x - value / derivative;
+        // near double roots newton may diverge, only keep steps that improve the residual
+        if evaluate(next).0.abs() >= value.abs() {
+            break;
+        }
+        x = next;
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that the roots are sorted and that each expected root was found
+    fn assert_roots(roots: &[f64], expected: &[f64]) {
+        assert!(
+            roots.windows(2).all(|pair| pair[0] <= pair[1]),
+            "{:?} aren't sorted",
+            roots
+        );
+        for root in expected {
+            assert!(
+                roots.iter().any(|x| (x - root).abs() < 1e-4),
+                "{} isn't in {:?}",
+                root,
+                roots
+            );
+        }
+    }
+
+    #[test]
+    fn quadratic_roots() {
+        assert_roots(&solve_quadratic(1.0, -3.0, 2.0), &[1.0, 2.0]);
+        assert_roots(&solve_quadratic(-2.0, 0.0, 8.0), &[-2.0, 2.0]);
+        assert!(solve_quadratic(1.0, 0.0, 1.0).is_empty());
+        // degenerate leading coefficient, a line
+        assert_roots(&solve_quadratic(0.0, 2.0, -4.0), &[2.0]);
+    }
+
+    #[test]
+    fn cubic_roots() {
+        // (x - 1)(x - 2)(x - 3)
+        let mut roots = solve_cubic(-6.0, 11.0, -6.0);
+        roots.sort_by(f64::total_cmp);
+        assert_eq!(roots.len(), 3);
+        assert_roots(&roots, &[1.0, 2.0, 3.0]);
+        // (x - 2)(x^2 + 1)
+        assert_roots(&solve_cubic(-2.0, 1.0, -2.0), &[2.0]);
+    }
+
+    #[test]
+    fn quartic_with_four_real_roots() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        let roots = solve_quartic(1.0, -10.0, 35.0, -50.0, 24.0);
+        assert_eq!(roots.len(), 4);
+        assert_roots(&roots, &[1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn quartic_with_a_double_root() {
+        // (x - 1)^2 (x + 2)(x - 3)
+        let roots = solve_quartic(1.0, -3.0, -3.0, 11.0, -6.0);
+        assert_roots(&roots, &[-2.0, 1.0, 3.0]);
+    }
+
+    #[test]
+    fn quartic_without_real_roots() {
+        // (x^2 + 1)(x^2 + 4)
+        assert!(solve_quartic(1.0, 0.0, 5.0, 0.0, 4.0).is_empty());
+        // (x^2 + 2x + 2)(x^2 - 4x + 5)
+        assert!(solve_quartic(1.0, -2.0, -1.0, 2.0, 10.0).is_empty());
+    }
+
+    #[test]
+    fn quartic_falls_back_to_the_cubic() {
+        // 2(x - 1)(x - 2)(x - 3)
+        let roots = solve_quartic(0.0, 2.0, -12.0, 22.0, -12.0);
+        assert_eq!(roots.len(), 3);
+        assert_roots(&roots, &[1.0, 2.0, 3.0]);
+    }
+}
@@ -0,0 +1,18 @@
+/// The linear value encoded with the sRGB transfer function, which is how images are usually
+/// displayed
+pub fn encode(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The linear value of one encoded with the sRGB transfer function
+pub fn decode(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
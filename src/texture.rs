@@ -0,0 +1,171 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::Path;
+
+use crate::screen::{extension, read_hdr};
+use crate::shapes::{Color, TextureCoords};
+use crate::srgb;
+
+/// Image laid over the texture coordinates of an object. The image spans from 0 to 1 in each
+/// coordinate, with `y` going up from its bottom row, and it's wrapped beyond that.
+#[derive(Debug)]
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    /// Row by row, starting from the top
+    pixels: Vec<Color>,
+}
+
+impl ImageTexture {
+    /// Reads the image at the path. The colors of `srgb` images are decoded into linear ones, as
+    /// 8 and 16 bit images of colors are usually stored, while images of values like heights or
+    /// normals are read as they are. HDR images are always linear.
+    pub fn open(path: &Path, srgb: bool) -> Result<ImageTexture> {
+        if extension(path).as_deref() == Some("hdr") {
+            let (width, height, pixels) = read_hdr(path)?;
+            return Ok(ImageTexture {
+                width: width as usize,
+                height: height as usize,
+                pixels: pixels
+                    .into_iter()
+                    .map(|p| Color {
+                        r: f64::from(p[0]),
+                        g: f64::from(p[1]),
+                        b: f64::from(p[2]),
+                    })
+                    .collect(),
+            });
+        }
+
+        let image = image::open(path)
+            .with_context(|| format!("Couldn't read the image '{}'", path.display()))?
+            .to_rgb16();
+        let decode = |c: u16| {
+            let c = f64::from(c) / 65535.0;
+            if srgb {
+                srgb::decode(c)
+            } else {
+                c
+            }
+        };
+
+        let pixels = image
+            .pixels()
+            .map(|p| Color {
+                r: decode(p[0]),
+                g: decode(p[1]),
+                b: decode(p[2]),
+            })
+            .collect();
+
+        Ok(ImageTexture {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            pixels,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Color at the coordinates, bilinearly filtered
+    pub fn sample(&self, coords: TextureCoords, wrap: Wrap) -> Color {
+        self.sample_wrapped(coords, wrap, wrap)
+    }
+
+    /// Color at the coordinates, bilinearly filtered, with the image wrapped differently along
+    /// each axis
+    pub fn sample_wrapped(&self, coords: TextureCoords, wrap_x: Wrap, wrap_y: Wrap) -> Color {
+        // pixel centers are at half coordinates
+        let x = coords.x * self.width as f64 - 0.5;
+        let y = (1.0 - coords.y) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+
+        let pixel = |i: f64, j: f64| {
+            self.pixels[wrap_y.index(j as i64, self.height) * self.width
+                + wrap_x.index(i as i64, self.width)]
+        };
+
+        (1.0 - fy) * ((1.0 - fx) * pixel(x0, y0) + fx * pixel(x0 + 1.0, y0))
+            + fy * ((1.0 - fx) * pixel(x0, y0 + 1.0) + fx * pixel(x0 + 1.0, y0 + 1.0))
+    }
+
+    /// Brightness at the coordinates, between 0 and 1
+    pub fn sample_gray(&self, coords: TextureCoords, wrap: Wrap) -> f64 {
+        let color = self.sample(coords, wrap);
+        (color.r + color.g + color.b) / 3.0
+    }
+}
+
+/// What an image texture shows outside of its bounds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Wrap {
+    /// The image tiles the coordinates
+    Repeat,
+    /// The pixels at the image's edges stretch out forever
+    Clamp,
+    /// The image tiles the coordinates, flipped every other time so the tiles' edges match
+    Mirror,
+}
+
+impl Wrap {
+    /// Index of the pixel shown at index `i` along an image's side with `size` pixels
+    fn index(self, i: i64, size: usize) -> usize {
+        let size = size as i64;
+        let i = match self {
+            Wrap::Repeat => i.rem_euclid(size),
+            Wrap::Clamp => i.clamp(0, size - 1),
+            Wrap::Mirror => {
+                let i = i.rem_euclid(2 * size);
+                if i < size {
+                    i
+                } else {
+                    2 * size - 1 - i
+                }
+            }
+        };
+        i as usize
+    }
+}
+
+/// Colors blended one into the next across the texture coordinates, starting at their origin
+#[derive(Debug, Clone, Serialize)]
+pub struct Gradient {
+    pub kind: GradientKind,
+    /// Evenly spaced from the start to the end of the gradient, at least two
+    pub colors: Vec<Color>,
+    /// Direction of linear gradients, normalized
+    pub axis: (f64, f64),
+    /// Distance from the first color to the last one
+    pub length: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum GradientKind {
+    /// Along the axis, the same all across it
+    Linear,
+    /// Going out from the origin, in rings
+    Radial,
+}
+
+impl Gradient {
+    pub fn color_at(&self, coords: TextureCoords) -> Color {
+        let distance = match self.kind {
+            GradientKind::Linear => coords.x * self.axis.0 + coords.y * self.axis.1,
+            GradientKind::Radial => coords.x.hypot(coords.y),
+        };
+
+        // position among the colors, which are one unit apart
+        let segments = (self.colors.len() - 1) as f64;
+        let t = (distance / self.length).clamp(0.0, 1.0) * segments;
+        let i = (t.floor() as usize).min(self.colors.len() - 2);
+        let f = t - i as f64;
+
+        (1.0 - f) * self.colors[i] + f * self.colors[i + 1]
+    }
+}
@@ -0,0 +1,128 @@
+use serde::Serialize;
+use std::ops;
+
+use crate::constants::TOLERANCE;
+use crate::vec3::{Onb, Vec3};
+
+/// Affine transformation in homogeneous coordinates, row-major
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Matrix4(pub [[f64; 4]; 4]);
+
+impl Matrix4 {
+    pub const IDENTITY: Matrix4 = Matrix4([
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]);
+
+    pub fn translation(offset: Vec3) -> Matrix4 {
+        Matrix4([
+            [1.0, 0.0, 0.0, offset.x],
+            [0.0, 1.0, 0.0, offset.y],
+            [0.0, 0.0, 1.0, offset.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scaling(factors: Vec3) -> Matrix4 {
+        Matrix4([
+            [factors.x, 0.0, 0.0, 0.0],
+            [0.0, factors.y, 0.0, 0.0],
+            [0.0, 0.0, factors.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Rotation that takes the world axes onto the basis' axes
+    pub fn rotation(basis: &Onb) -> Matrix4 {
+        let (u, v, w) = (basis.u, basis.v, basis.w);
+        Matrix4([
+            [u.x, v.x, w.x, 0.0],
+            [u.y, v.y, w.y, 0.0],
+            [u.z, v.z, w.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// Scales, then rotates and then translates
+    pub fn from_trs(translation: Vec3, rotation: &Onb, scale: Vec3) -> Matrix4 {
+        Matrix4::translation(translation) * Matrix4::rotation(rotation) * Matrix4::scaling(scale)
+    }
+
+    pub fn transpose(&self) -> Matrix4 {
+        let mut result = [[0.0; 4]; 4];
+        for (i, row) in result.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.0[j][i];
+            }
+        }
+        Matrix4(result)
+    }
+
+    /// Inverse by Gauss-Jordan elimination, `None` if the matrix is singular
+    pub fn inverse(&self) -> Option<Matrix4> {
+        let mut m = self.0;
+        let mut inv = Matrix4::IDENTITY.0;
+
+        for col in 0..4 {
+            // partial pivoting
+            let pivot = (col..4).max_by(|a, b| m[*a][col].abs().total_cmp(&m[*b][col].abs()))?;
+            if m[pivot][col].abs() < TOLERANCE {
+                return None;
+            }
+            m.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let p = m[col][col];
+            for j in 0..4 {
+                m[col][j] /= p;
+                inv[col][j] /= p;
+            }
+
+            for row in 0..4 {
+                if row != col {
+                    let factor = m[row][col];
+                    for j in 0..4 {
+                        m[row][j] -= factor * m[col][j];
+                        inv[row][j] -= factor * inv[col][j];
+                    }
+                }
+            }
+        }
+
+        Some(Matrix4(inv))
+    }
+
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        let m = &self.0;
+        Vec3::new(
+            m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3],
+        )
+    }
+
+    /// Transforms a direction, ignoring the translation
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.0;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+}
+
+impl ops::Mul for Matrix4 {
+    type Output = Matrix4;
+    fn mul(self, other: Matrix4) -> Self::Output {
+        let mut result = [[0.0; 4]; 4];
+        for (i, row) in result.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.0[i][k] * other.0[k][j]).sum();
+            }
+        }
+        Matrix4(result)
+    }
+}
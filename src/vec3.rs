@@ -1,7 +1,8 @@
 use crate::constants::TOLERANCE;
+use serde::Serialize;
 use std::ops;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize)]
 pub struct Vec3 {
     pub x: f64,
     pub y: f64,
@@ -53,6 +54,55 @@ macro_rules! vec3_op_vec {
 
 vec3_op_vec!(ops::Add, add);
 vec3_op_vec!(ops::Sub, sub);
+// component-wise
+vec3_op_vec!(ops::Mul, mul);
+vec3_op_vec!(ops::Div, div);
+
+macro_rules! vec3_op_assign_vec {
+    ($($path:ident)::+, $fn:ident) => {
+        impl $($path)::+ for Vec3 {
+            fn $fn(&mut self, other: Self) {
+                self.x.$fn(other.x);
+                self.y.$fn(other.y);
+                self.z.$fn(other.z);
+            }
+        }
+    };
+}
+
+vec3_op_assign_vec!(ops::AddAssign, add_assign);
+vec3_op_assign_vec!(ops::SubAssign, sub_assign);
+
+impl ops::MulAssign<f64> for Vec3 {
+    fn mul_assign(&mut self, other: f64) {
+        self.x *= other;
+        self.y *= other;
+        self.z *= other;
+    }
+}
+
+impl ops::Neg for Vec3 {
+    type Output = Vec3;
+    fn neg(self) -> Self::Output {
+        Vec3 {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+}
+
+impl ops::Index<usize> for Vec3 {
+    type Output = f64;
+    fn index(&self, index: usize) -> &Self::Output {
+        match index {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 index out of bounds: {}", index),
+        }
+    }
+}
 
 impl Vec3 {
     /// Get the 2-norm of the vector
@@ -60,6 +110,43 @@ impl Vec3 {
         (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
     }
 
+    /// Squared 2-norm, avoids the square root when only comparisons are needed
+    pub fn length_squared(&self) -> f64 {
+        self.dot(*self)
+    }
+
+    /// Whether every component is within TOLERANCE of zero
+    pub fn near_zero(&self) -> bool {
+        self.x.abs() < TOLERANCE && self.y.abs() < TOLERANCE && self.z.abs() < TOLERANCE
+    }
+
+    /// Component-wise absolute value
+    pub fn abs(self) -> Vec3 {
+        Vec3 {
+            x: self.x.abs(),
+            y: self.y.abs(),
+            z: self.z.abs(),
+        }
+    }
+
+    /// Component-wise minimum
+    pub fn min(self, other: Vec3) -> Vec3 {
+        Vec3 {
+            x: self.x.min(other.x),
+            y: self.y.min(other.y),
+            z: self.z.min(other.z),
+        }
+    }
+
+    /// Component-wise maximum
+    pub fn max(self, other: Vec3) -> Vec3 {
+        Vec3 {
+            x: self.x.max(other.x),
+            y: self.y.max(other.y),
+            z: self.z.max(other.z),
+        }
+    }
+
     /// Normalized vector with 2-norm (magnitude of 1)
     pub fn normalize(&self) -> Vec3 {
         *self / self.norm()
@@ -70,7 +157,23 @@ impl Vec3 {
         Vec3 { x, y, z }
     }
 
-    pub fn dot(self, other: Vec3) -> f64 {
+    /// Point at `r` from the origin, `theta` radians away from +y and turned `phi` radians around y
+    /// from +z towards +x
+    pub fn from_spherical(r: f64, theta: f64, phi: f64) -> Vec3 {
+        Vec3::new(
+            r * theta.sin() * phi.sin(),
+            r * theta.cos(),
+            r * theta.sin() * phi.cos(),
+        )
+    }
+
+    /// Point at `r` from the y axis, turned `phi` radians around it from +z towards +x, at a
+    /// height of `y`
+    pub fn from_cylindrical(r: f64, phi: f64, y: f64) -> Vec3 {
+        Vec3::new(r * phi.sin(), y, r * phi.cos())
+    }
+
+    pub fn dot(&self, other: Vec3) -> f64 {
         self.x * other.x + self.y * other.y + self.z * other.z
     }
 
@@ -93,31 +196,86 @@ impl Vec3 {
     /// The angle is in radians
     pub fn rotate_y(self, angle: f64) -> Vec3 {
         Vec3 {
-            x: self.x * angle.cos() + self.y * angle.sin(),
+            x: self.x * angle.cos() + self.z * angle.sin(),
             y: self.y,
-            z: -1.0 * self.x * angle.sin() + self.z * angle.cos(),
+            z: -self.x * angle.sin() + self.z * angle.cos(),
         }
     }
     /// The angle is in radians
     pub fn rotate_z(self, angle: f64) -> Vec3 {
         Vec3 {
-            x: self.x * angle.cos() + self.y * angle.sin(),
-            y: self.x * angle.sin() - self.y * angle.cos(),
+            x: self.x * angle.cos() - self.y * angle.sin(),
+            y: self.x * angle.sin() + self.y * angle.cos(),
             z: self.z,
         }
     }
 
-    pub fn to_align(self, to_align: Vec3) -> [[f64; 3]; 3] {
+    /// Rotation matrix of `angle` radians around this vector as the axis (Rodrigues' formula)
+    pub fn rotation_around(self, angle: f64) -> [[f64; 3]; 3] {
+        let k = self.normalize();
+        let (sin, cos) = angle.sin_cos();
+        let t = 1.0 - cos;
+
+        [
+            [
+                cos + k.x * k.x * t,
+                k.x * k.y * t - k.z * sin,
+                k.x * k.z * t + k.y * sin,
+            ],
+            [
+                k.y * k.x * t + k.z * sin,
+                cos + k.y * k.y * t,
+                k.y * k.z * t - k.x * sin,
+            ],
+            [
+                k.z * k.x * t - k.y * sin,
+                k.z * k.y * t + k.x * sin,
+                cos + k.z * k.z * t,
+            ],
+        ]
+    }
+
+    /// Any unit vector perpendicular to this one, none if this one is zero
+    pub fn orthogonal(self) -> Option<Vec3> {
+        if self.near_zero() {
+            return None;
+        }
+
+        // cross with the axis least aligned with the vector so the result never degenerates
+        let axis = if self.x.abs() < self.y.abs() && self.x.abs() < self.z.abs() {
+            Vec3::new(1.0, 0.0, 0.0)
+        } else if self.y.abs() < self.z.abs() {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(0.0, 0.0, 1.0)
+        };
+
+        Some(self.cross(axis).normalize())
+    }
+
+    /// Rotation matrix that takes the direction of this vector onto the direction of `to_align`,
+    /// none if either of them is zero and so has no direction
+    pub fn to_align(self, to_align: Vec3) -> Option<[[f64; 3]; 3]> {
         const I: [[f64; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
 
+        if self.near_zero() || to_align.near_zero() {
+            return None;
+        }
+
         // safety for unit vectors
         let a = self.normalize();
         let b = to_align.normalize();
 
         let c = a.dot(b);
 
+        if (c - 1.0).abs() < TOLERANCE {
+            return Some(I);
+        }
+
+        // opposite vectors: the cross product vanishes so any axis perpendicular to `a` works, a
+        // half turn around it flips `a` onto `b`
         if (c + 1.0).abs() < TOLERANCE {
-            return I;
+            return Some(a.orthogonal()?.rotation_around(std::f64::consts::PI));
         }
 
         let v = a.cross(b);
@@ -137,7 +295,10 @@ impl Vec3 {
             [vxvz, vyvz, -vx2 - vy2],
         ];
 
-        matrix_sum(I, matrix_sum(skew, matrix_mul_k(skew2, 1.0 / (1.0 + c))))
+        Some(matrix_sum(
+            I,
+            matrix_sum(skew, matrix_mul_k(skew2, 1.0 / (1.0 + c))),
+        ))
     }
 
     pub fn apply_matrix(self, matrix: [[f64; 3]; 3]) -> Vec3 {
@@ -158,6 +319,53 @@ impl Vec3 {
     }
 }
 
+/// Orthonormal basis built around a normal vector. `w` is the (normalized) normal and `u`, `v` are
+/// the tangent and bitangent, used as texture axes and as the frame for local directions.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    /// Branchless construction that doesn't degenerate for any normal
+    /// ref: Duff et al., "Building an Orthonormal Basis, Revisited" (2017)
+    pub fn from_normal(normal: Vec3) -> Onb {
+        let w = normal.normalize();
+        let sign = 1.0_f64.copysign(w.z);
+        let a = -1.0 / (sign + w.z);
+        let b = w.x * w.y * a;
+
+        Onb {
+            u: Vec3::new(1.0 + sign * w.x * w.x * a, sign * b, -sign * w.x),
+            v: Vec3::new(b, sign + w.y * w.y * a, -w.y),
+            w,
+        }
+    }
+
+    /// Frame of the world axes after rotating them by the given angles (in radians) around x, then
+    /// y and then z
+    pub fn from_euler(x: f64, y: f64, z: f64) -> Onb {
+        let rotate = |v: Vec3| v.rotate_x(x).rotate_y(y).rotate_z(z);
+        Onb {
+            u: rotate(Vec3::new(1.0, 0.0, 0.0)),
+            v: rotate(Vec3::new(0.0, 1.0, 0.0)),
+            w: rotate(Vec3::new(0.0, 0.0, 1.0)),
+        }
+    }
+
+    /// Expresses a world vector in this basis' coordinates
+    pub fn world_to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::new(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+    }
+
+    /// Transforms a vector expressed in this basis' coordinates into world coordinates
+    pub fn local_to_world(&self, a: Vec3) -> Vec3 {
+        a.x * self.u + a.y * self.v + a.z * self.w
+    }
+}
+
 fn matrix_sum(a: [[f64; 3]; 3], b: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
     [
         [a[0][0] + b[0][0], a[0][1] + b[0][1], a[0][2] + b[0][2]],
@@ -185,3 +393,94 @@ fn matrix_mul_k(a: [[f64; 3]; 3], k: f64) -> [[f64; 3]; 3] {
     }
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+
+    const Y: Vec3 = Vec3 {
+        x: 0.0,
+        y: 1.0,
+        z: 0.0,
+    };
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).near_zero(), "{:?} isn't {:?}", a, b);
+    }
+
+    /// Checks that the rows of the matrix are unit vectors perpendicular to each other
+    fn assert_orthonormal(matrix: [[f64; 3]; 3]) {
+        let rows = matrix.map(|[x, y, z]| Vec3::new(x, y, z));
+        for i in 0..3 {
+            for j in 0..3 {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((rows[i].dot(rows[j]) - expected).abs() < TOLERANCE);
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_vectors_align_with_the_identity() {
+        let a = Vec3::new(1.0, 2.0, 3.0);
+        let rotation = a.to_align(a * 2.0).unwrap();
+        assert_eq!(
+            rotation,
+            [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+        );
+    }
+
+    #[test]
+    fn antiparallel_vectors_are_flipped() {
+        for a in [Y, Vec3::new(1.0, 0.0, 0.0), Vec3::new(-1.0, 2.0, 0.5)] {
+            let rotation = a.to_align(-a).unwrap();
+            assert_orthonormal(rotation);
+            assert_close(a.normalize().apply_matrix(rotation), -a.normalize());
+        }
+    }
+
+    #[test]
+    fn random_vectors_are_aligned_by_a_rotation() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(0);
+        let mut random = || {
+            Vec3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            )
+        };
+        for _ in 0..20 {
+            let (a, b) = (random(), random());
+            let rotation = a.to_align(b).unwrap();
+            assert_orthonormal(rotation);
+            assert_close(a.normalize().apply_matrix(rotation), b.normalize());
+        }
+    }
+
+    #[test]
+    fn zero_vectors_have_no_alignment() {
+        let zero = Vec3::new(0.0, 0.0, 0.0);
+        assert!(zero.to_align(Y).is_none());
+        assert!(Y.to_align(zero).is_none());
+        assert!(zero.orthogonal().is_none());
+    }
+
+    #[test]
+    fn orthogonal_is_a_perpendicular_unit_vector() {
+        for a in [Y, Vec3::new(0.0, 0.0, -3.0), Vec3::new(1.0, 1.0, 1.0)] {
+            let orthogonal = a.orthogonal().unwrap();
+            assert!(orthogonal.dot(a).abs() < TOLERANCE);
+            assert!((orthogonal.length_squared() - 1.0).abs() < TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn rotation_around_turns_about_the_axis() {
+        let rotation = Vec3::new(0.0, 0.0, 2.0).rotation_around(std::f64::consts::FRAC_PI_2);
+        assert_orthonormal(rotation);
+        assert_close(
+            Vec3::new(1.0, 0.0, 0.0).apply_matrix(rotation),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+    }
+}